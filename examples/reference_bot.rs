@@ -0,0 +1,20 @@
+// The reference bot in a process of its own, for trying out the socket protocol or as a
+// starting point for a bot of your own. Any number of paddles can play through it at once.
+//   cargo run --example reference_bot -- tcp:127.0.0.1:7100
+//   cargo run -- --headless --bot left=tcp:127.0.0.1:7100 --bot right=reference
+use pong::bots::{serve_bot, BotAddress, ReferenceBot};
+
+fn main() {
+    let spec = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "tcp:127.0.0.1:7100".to_string());
+    let Some(address) = BotAddress::parse(&spec) else {
+        eprintln!("Expected tcp:<host:port> or unix:<path>, got '{spec}'");
+        std::process::exit(1);
+    };
+    println!("Reference bot listening on {address}");
+    if let Err(error) = serve_bot(&address, ReferenceBot::default) {
+        eprintln!("Reference bot stopped: {error}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,267 @@
+// Pairs up players by room code and passes their packets back and forth over WebSockets,
+// for the browser build which can't open UDP sockets of its own.
+// The first player into a room hosts the match and the second joins it. The relay doesn't look
+// inside the packets, the game does everything else itself just like over UDP.
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::protocol::frame::{coding::CloseCode, CloseFrame};
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_PORT: u16 = 9000;
+const MAX_ROOM_CODE_LEN: usize = 32;
+// How long a read waits before checking on the other player again
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+// Sent to the players as text once they're paired up, everything else is binary packets
+pub const WAITING: &str = "waiting";
+pub const HOST: &str = "host";
+pub const JOIN: &str = "join";
+
+// What a player's connection hears about the other player's
+enum FromPartner {
+    // Someone else came into the room
+    Paired(Sender<FromPartner>),
+    Packet(Vec<u8>),
+    Left,
+}
+
+// Rooms with one player in, waiting for a second
+#[derive(Default)]
+struct Lobby {
+    waiting: HashMap<String, (u64, Sender<FromPartner>)>,
+    next_id: u64,
+}
+
+// Room codes come from the path of the URL, e.g. `ws://localhost:9000/abc123`.
+// Letters, digits, `-` and `_`, and not case sensitive so they're easy to read out.
+pub fn room_code(path: &str) -> Option<String> {
+    let code = path.trim_matches('/');
+    let valid = !code.is_empty()
+        && code.len() <= MAX_ROOM_CODE_LEN
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| code.to_ascii_lowercase())
+}
+
+// Accepts players forever, each on a thread of its own
+pub fn serve(listener: TcpListener) {
+    let lobby = Arc::new(Mutex::new(Lobby::default()));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Couldn't accept a connection: {error}");
+                continue;
+            }
+        };
+        let lobby = lobby.clone();
+        thread::spawn(move || handle_player(stream, &lobby));
+    }
+}
+
+// The handshake callback's error type is tungstenite's to choose
+#[allow(clippy::result_large_err)]
+fn handle_player(stream: TcpStream, lobby: &Mutex<Lobby>) {
+    let mut path = String::new();
+    let accepted = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        path = request.uri().path().to_string();
+        Ok(response)
+    });
+    let Ok(mut socket) = accepted else {
+        return;
+    };
+    let Some(room) = room_code(&path) else {
+        close(
+            &mut socket,
+            CloseCode::Policy,
+            "Room codes are letters, digits, - and _",
+        );
+        return;
+    };
+    if socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .is_err()
+    {
+        return;
+    }
+
+    let (to_me, from_partner) = channel();
+    let (id, mut partner) = {
+        let mut lobby = lobby.lock().expect("lobby lock poisoned");
+        lobby.next_id += 1;
+        let id = lobby.next_id;
+        match lobby.waiting.remove(&room) {
+            Some((_, first)) => {
+                let _ = first.send(FromPartner::Paired(to_me));
+                (id, Some(first))
+            }
+            None => {
+                lobby.waiting.insert(room.clone(), (id, to_me));
+                (id, None)
+            }
+        }
+    };
+    let greeting = if partner.is_some() { JOIN } else { WAITING };
+    if socket.send(Message::text(greeting)).is_ok() {
+        relay_packets(&mut socket, &from_partner, &mut partner);
+    }
+
+    match partner {
+        Some(partner) => {
+            let _ = partner.send(FromPartner::Left);
+        }
+        None => {
+            let mut lobby = lobby.lock().expect("lobby lock poisoned");
+            if lobby
+                .waiting
+                .get(&room)
+                .is_some_and(|(waiting, _)| *waiting == id)
+            {
+                lobby.waiting.remove(&room);
+            }
+            drop(lobby);
+            // Someone might have come in just as this player left
+            while let Ok(message) = from_partner.try_recv() {
+                if let FromPartner::Paired(late) = message {
+                    let _ = late.send(FromPartner::Left);
+                }
+            }
+        }
+    }
+}
+
+// Passes packets both ways until either player goes
+fn relay_packets(
+    socket: &mut WebSocket<TcpStream>,
+    from_partner: &Receiver<FromPartner>,
+    partner: &mut Option<Sender<FromPartner>>,
+) {
+    loop {
+        loop {
+            let sent = match from_partner.try_recv() {
+                Ok(FromPartner::Paired(second)) => {
+                    *partner = Some(second);
+                    socket.send(Message::text(HOST))
+                }
+                Ok(FromPartner::Packet(bytes)) => socket.send(Message::binary(bytes)),
+                Ok(FromPartner::Left) | Err(TryRecvError::Disconnected) => {
+                    *partner = None;
+                    close(socket, CloseCode::Away, "The other player left");
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Binary(bytes)) => {
+                if let Some(partner) = partner {
+                    let _ = partner.send(FromPartner::Packet(bytes.to_vec()));
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            // Pings are answered by tungstenite itself
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+fn close(socket: &mut WebSocket<TcpStream>, code: CloseCode, reason: &str) {
+    let _ = socket.close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }));
+    let _ = socket.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tungstenite::stream::MaybeTlsStream;
+
+    type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+    fn start_relay() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind a free port");
+        let address = listener.local_addr().expect("bound address");
+        thread::spawn(move || serve(listener));
+        address
+    }
+
+    fn connect(address: SocketAddr, room: &str) -> Client {
+        let (client, _) =
+            tungstenite::connect(format!("ws://{address}/{room}")).expect("relay is up");
+        client
+    }
+
+    fn read_text(client: &mut Client) -> String {
+        match client.read().expect("relay sends something") {
+            Message::Text(text) => text.to_string(),
+            other => panic!("expected text, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn room_codes() {
+        assert_eq!(room_code("/ABC-12_x"), Some("abc-12_x".to_string()));
+        assert_eq!(room_code("/"), None);
+        assert_eq!(room_code("/a/b"), None);
+        assert_eq!(room_code(&format!("/{}", "a".repeat(33))), None);
+    }
+
+    #[test]
+    fn pairs_players_in_the_same_room() {
+        let address = start_relay();
+        let mut host = connect(address, "Pong");
+        assert_eq!(read_text(&mut host), WAITING);
+        // Someone in another room doesn't count
+        let mut elsewhere = connect(address, "other");
+        assert_eq!(read_text(&mut elsewhere), WAITING);
+
+        let mut joiner = connect(address, "pong");
+        assert_eq!(read_text(&mut joiner), JOIN);
+        assert_eq!(read_text(&mut host), HOST);
+
+        joiner.send(Message::binary(vec![1, 2, 3])).unwrap();
+        assert_eq!(host.read().unwrap(), Message::binary(vec![1, 2, 3]));
+        host.send(Message::binary(vec![4])).unwrap();
+        assert_eq!(joiner.read().unwrap(), Message::binary(vec![4]));
+
+        // The room is free again once they're paired
+        let mut next = connect(address, "pong");
+        assert_eq!(read_text(&mut next), WAITING);
+    }
+
+    #[test]
+    fn the_other_player_hears_when_someone_leaves() {
+        let address = start_relay();
+        let mut host = connect(address, "leaving");
+        assert_eq!(read_text(&mut host), WAITING);
+        let mut joiner = connect(address, "leaving");
+        assert_eq!(read_text(&mut joiner), JOIN);
+        assert_eq!(read_text(&mut host), HOST);
+
+        joiner.close(None).unwrap();
+        match host.read() {
+            Ok(Message::Close(Some(frame))) => assert_eq!(frame.reason, "The other player left"),
+            other => panic!("expected the relay to close, got {other:?}"),
+        }
+    }
+}
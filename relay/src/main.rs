@@ -0,0 +1,32 @@
+use pong_relay::{serve, DEFAULT_PORT};
+use std::net::TcpListener;
+
+// Parses `--port <port>` and `--bind <address>`, listening on every interface by default
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut bind = "0.0.0.0".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next().and_then(|value| value.parse::<u16>().ok()) {
+                Some(value) => port = value,
+                None => eprintln!("--port expects a port number"),
+            },
+            "--bind" => match args.next() {
+                Some(value) => bind = value,
+                None => eprintln!("--bind expects an address like 127.0.0.1"),
+            },
+            _ => eprintln!("Unknown option '{arg}'"),
+        }
+    }
+
+    let listener = match TcpListener::bind((bind.as_str(), port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Couldn't listen on {bind}:{port}: {error}");
+            std::process::exit(1);
+        }
+    };
+    println!("Pong relay listening on ws://{bind}:{port}/<room code>");
+    serve(listener);
+}
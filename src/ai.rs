@@ -0,0 +1,379 @@
+use crate::arena::{ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH};
+use crate::powerups::ControlsReversed;
+use crate::rng::GameRng;
+use crate::simulation::GameplaySet;
+use crate::systems::{constrain_paddle, paddle_position, set_paddle_position};
+use crate::tuning::GameTuning;
+use crate::{Ball, Paddle, PaddleScale, Side, Velocity};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Impossible,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            "impossible" => Some(Difficulty::Impossible),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Impossible => "Impossible",
+        }
+    }
+
+    // Cycles through the presets, wrapping back around to Easy
+    pub fn next(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Impossible,
+            Difficulty::Impossible => Difficulty::Easy,
+        }
+    }
+
+    // How long the AI waits between looks at the ball
+    pub fn reaction_delay_secs(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.2,
+            Difficulty::Hard => 0.1,
+            Difficulty::Impossible => 0.0,
+        }
+    }
+
+    // Paddle speed in half arena heights per second.
+    // For reference, the keyboard moves at 1.8.
+    pub fn max_speed(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.9,
+            Difficulty::Normal => 1.4,
+            Difficulty::Hard => 2.0,
+            Difficulty::Impossible => 6.0,
+        }
+    }
+
+    // Largest random error (in pixels) added to where the AI thinks the ball will arrive
+    pub fn prediction_error(self) -> f32 {
+        match self {
+            Difficulty::Easy => 60.0,
+            Difficulty::Normal => 30.0,
+            Difficulty::Hard => 10.0,
+            Difficulty::Impossible => 0.0,
+        }
+    }
+}
+
+// Drives a paddle instead of the keyboard/touch systems.
+// The presets come from Difficulty, but every field can be tweaked on its own.
+#[derive(Component, Clone)]
+pub struct AiController {
+    pub reaction_delay: Timer,
+    pub max_speed: f32,
+    pub prediction_error: f32,
+    // Along the paddle's track, see paddle_position
+    pub target_y: f32,
+}
+
+impl AiController {
+    pub fn new(difficulty: Difficulty) -> Self {
+        AiController {
+            reaction_delay: Timer::from_seconds(
+                difficulty.reaction_delay_secs(),
+                TimerMode::Repeating,
+            ),
+            max_speed: difficulty.max_speed(),
+            prediction_error: difficulty.prediction_error(),
+            target_y: 0.0,
+        }
+    }
+}
+
+// Which players are CPU controlled, and how well they play
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub struct AiSettings {
+    pub left: Option<Difficulty>,
+    pub right: Option<Difficulty>,
+    pub top: Option<Difficulty>,
+    pub bottom: Option<Difficulty>,
+}
+
+impl AiSettings {
+    pub fn for_side(&self, side: Side) -> Option<Difficulty> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    // Parses `--cpu <left|right|top|bottom>[:<difficulty>]`, e.g. `--cpu right:hard`.
+    // Difficulty defaults to normal. Can be passed once for each side.
+    // Top and bottom are also the forward paddles in doubles, see Paddle::player.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut settings = AiSettings::default();
+        while let Some(arg) = args.next() {
+            if arg != "--cpu" {
+                continue;
+            }
+            let Some(value) = args.next() else {
+                break;
+            };
+            let (side, difficulty) = match value.split_once(':') {
+                Some((side, difficulty)) => (side.to_string(), Difficulty::from_name(difficulty)),
+                None => (value.clone(), Some(Difficulty::Normal)),
+            };
+            let Some(difficulty) = difficulty else {
+                eprintln!("Unknown CPU difficulty in '{value}'");
+                continue;
+            };
+            match side.to_ascii_lowercase().as_str() {
+                "left" => settings.left = Some(difficulty),
+                "right" => settings.right = Some(difficulty),
+                "top" => settings.top = Some(difficulty),
+                "bottom" => settings.bottom = Some(difficulty),
+                _ => eprintln!("Unknown CPU side in '{value}'"),
+            }
+        }
+        settings
+    }
+}
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AiSettings::from_args(std::env::args().skip(1)))
+            .add_systems(
+                FixedUpdate,
+                (attach_ai_controllers, move_paddles_with_ai)
+                    .chain()
+                    .in_set(GameplaySet::Input),
+            );
+    }
+}
+
+// Paddles are respawned on every restart, so hook them up as they appear
+pub fn attach_ai_controllers(
+    mut commands: Commands,
+    settings: Res<AiSettings>,
+    paddles: Query<(Entity, &Paddle), Added<Paddle>>,
+) {
+    for (entity, paddle) in paddles.iter() {
+        if let Some(difficulty) = settings.for_side(paddle.player()) {
+            commands
+                .entity(entity)
+                .insert(AiController::new(difficulty));
+        }
+    }
+}
+
+// Where the ball will cross `target_x`, folding its path back into the
+// playfield for every top/bottom wall bounce on the way.
+// Returns None if the ball is moving away from `target_x`.
+pub fn predict_intercept_y(
+    position: Vec2,
+    velocity: Vec2,
+    target_x: f32,
+    ball_max_y: f32,
+) -> Option<f32> {
+    let distance_x = target_x - position.x;
+    if velocity.x == 0.0 || distance_x.signum() != velocity.x.signum() {
+        return None;
+    }
+    let time_to_target = distance_x / velocity.x;
+    let unfolded_y = position.y + velocity.y * time_to_target;
+
+    let span = 2.0 * ball_max_y;
+    if span <= 0.0 {
+        return Some(0.0);
+    }
+    // One full period is down and back up again
+    let shifted = (unfolded_y + ball_max_y).rem_euclid(2.0 * span);
+    let folded = if shifted > span {
+        2.0 * span - shifted
+    } else {
+        shifted
+    };
+    Some(folded - ball_max_y)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn move_paddles_with_ai(
+    time: Res<Time>,
+    mut paddles: Query<
+        (
+            &mut Transform,
+            &Paddle,
+            &PaddleScale,
+            &mut AiController,
+            Has<ControlsReversed>,
+        ),
+        Without<Ball>,
+    >,
+    balls: Query<(&Transform, &Velocity), With<Ball>>,
+    mut rng: ResMut<GameRng>,
+    tuning: Res<GameTuning>,
+) {
+    let face_offset = tuning.paddle_width / 2.0 + tuning.ball_radius;
+
+    for (mut transform, paddle, scale, mut controller, reversed) in paddles.iter_mut() {
+        controller.reaction_delay.tick(time.delta());
+        let reacting = controller.reaction_delay.duration().is_zero()
+            || controller.reaction_delay.just_finished();
+
+        if reacting {
+            // Top and bottom paddles work it out the same way, on their side
+            let to_paddle_space = |v: Vec2| paddle.side.to_paddle_space(v);
+            let ball_max_y = to_paddle_space(Vec2::new(ARENA_HALF_WIDTH, ARENA_HALF_HEIGHT)).y
+                - tuning.ball_radius;
+            // Aim for the face of the paddle, not its center
+            let face_x = to_paddle_space(
+                transform.translation.truncate() + paddle.side.normal() * face_offset,
+            )
+            .x;
+
+            // Track whichever incoming ball gets here first
+            let incoming = balls
+                .iter()
+                .filter_map(|(ball_transform, velocity)| {
+                    let position = to_paddle_space(ball_transform.translation.truncate());
+                    let velocity = to_paddle_space(Vec2::new(velocity.x, velocity.y));
+                    let y = predict_intercept_y(position, velocity, face_x, ball_max_y)?;
+                    let time_to_arrive = (face_x - position.x) / velocity.x;
+                    Some((time_to_arrive, y))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+
+            controller.target_y = match incoming {
+                Some((_, y)) => {
+                    let error = controller.prediction_error;
+                    if error > 0.0 {
+                        y + rng.gameplay.gen_range(-error..=error)
+                    } else {
+                        y
+                    }
+                }
+                // Nothing coming, drift back to the middle
+                None => 0.0,
+            };
+        }
+
+        let max_step = controller.max_speed * ARENA_HALF_HEIGHT * time.delta_secs();
+        let position = paddle_position(transform.translation, paddle.side);
+        let mut step = (controller.target_y - position).clamp(-max_step, max_step);
+        // Reverse catches out the CPU just like a player, it only finds out once it's moving
+        if reversed {
+            step = -step;
+        }
+        set_paddle_position(&mut transform.translation, paddle.side, position + step);
+        constrain_paddle(&mut transform.translation, paddle, *scale, &tuning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(str::to_string)
+    }
+
+    #[test]
+    fn a_straight_path_needs_no_folding() {
+        let y = predict_intercept_y(Vec2::new(0.0, 10.0), Vec2::new(100.0, 50.0), 200.0, 300.0);
+        assert_eq!(y, Some(110.0));
+    }
+
+    #[test]
+    fn one_bounce_comes_back_off_the_wall() {
+        // Heads for 400, 100 past the top wall at 300
+        let y = predict_intercept_y(Vec2::new(0.0, 0.0), Vec2::new(100.0, 200.0), 200.0, 300.0);
+        assert_eq!(y, Some(200.0));
+    }
+
+    #[test]
+    fn two_bounces_come_back_off_both_walls() {
+        // Heads for -1000, off the bottom wall at -300 and then the top one at 300
+        let y = predict_intercept_y(
+            Vec2::new(0.0, 0.0),
+            Vec2::new(-100.0, -500.0),
+            -200.0,
+            300.0,
+        );
+        assert_eq!(y, Some(200.0));
+    }
+
+    #[test]
+    fn a_ball_moving_away_is_never_intercepted() {
+        let position = Vec2::new(0.0, 0.0);
+        assert_eq!(
+            predict_intercept_y(position, Vec2::new(-100.0, 0.0), 200.0, 300.0),
+            None
+        );
+        assert_eq!(
+            predict_intercept_y(position, Vec2::new(0.0, 100.0), 200.0, 300.0),
+            None
+        );
+    }
+
+    #[test]
+    fn no_room_to_move_is_the_middle() {
+        let y = predict_intercept_y(Vec2::new(0.0, 0.0), Vec2::new(100.0, 200.0), 200.0, 0.0);
+        assert_eq!(y, Some(0.0));
+    }
+
+    #[test]
+    fn difficulty_names_ignore_case() {
+        assert_eq!(Difficulty::from_name("HARD"), Some(Difficulty::Hard));
+        assert_eq!(
+            Difficulty::from_name("impossible"),
+            Some(Difficulty::Impossible)
+        );
+        assert_eq!(Difficulty::from_name("tricky"), None);
+    }
+
+    #[test]
+    fn cpu_args_pick_sides_and_difficulties() {
+        let settings = AiSettings::from_args(args("--seed 4 --cpu right:hard --cpu Top"));
+        assert_eq!(
+            settings,
+            AiSettings {
+                right: Some(Difficulty::Hard),
+                top: Some(Difficulty::Normal),
+                ..default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_cpu_args_are_skipped() {
+        let settings = AiSettings::from_args(args(
+            "--cpu left:tricky --cpu middle --cpu bottom:easy --cpu",
+        ));
+        assert_eq!(
+            settings,
+            AiSettings {
+                bottom: Some(Difficulty::Easy),
+                ..default()
+            }
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::collision::Aabb;
+use crate::Side;
+use bevy::{
+    prelude::*,
+    render::camera::ScalingMode,
+    window::{PrimaryWindow, WindowResized},
+};
+
+// The playfield is always this many world units across, whatever the window size.
+// The camera scales it to fit and letterboxes whatever is left over.
+pub const ARENA_WIDTH: f32 = 1280.0;
+pub const ARENA_HEIGHT: f32 = 720.0;
+pub const ARENA_HALF_WIDTH: f32 = ARENA_WIDTH / 2.0;
+pub const ARENA_HALF_HEIGHT: f32 = ARENA_HEIGHT / 2.0;
+
+const ARENA_COLOR: Color = Color::srgb(0.1, 0.0, 0.3); // Deep Purple background
+const LETTERBOX_COLOR: Color = Color::BLACK;
+
+// Black bars covering everything outside the arena, so a ball on its way out
+// or a stray particle doesn't show up in the letterbox
+#[derive(Component)]
+pub struct Letterbox;
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(LETTERBOX_COLOR))
+            .add_systems(Startup, spawn_arena)
+            .add_systems(Update, scale_ui_to_arena);
+    }
+}
+
+// A strip `thickness` deep along the inside of the arena's edge on `side`, e.g. a wall across its goal
+pub fn edge_strip(side: Side, thickness: f32) -> Aabb {
+    let half_extent = side.to_paddle_space(Vec2::new(ARENA_HALF_WIDTH, ARENA_HALF_HEIGHT));
+    Aabb {
+        center: -side.normal() * (half_extent.x - thickness / 2.0),
+        half_size: side.to_paddle_space(Vec2::new(thickness / 2.0, half_extent.y)),
+    }
+}
+
+// Shows the whole arena at the largest size that fits the window
+pub fn arena_projection() -> Projection {
+    Projection::Orthographic(OrthographicProjection {
+        scaling_mode: ScalingMode::AutoMin {
+            min_width: ARENA_WIDTH,
+            min_height: ARENA_HEIGHT,
+        },
+        ..OrthographicProjection::default_2d()
+    })
+}
+
+pub fn spawn_arena(mut commands: Commands) {
+    commands.spawn((
+        Sprite {
+            color: ARENA_COLOR,
+            custom_size: Some(Vec2::new(ARENA_WIDTH, ARENA_HEIGHT)),
+            ..default()
+        },
+        Transform::from_xyz(0.0, 0.0, -1.0),
+    ));
+
+    // Each bar is as big as the arena itself, which covers any aspect ratio up to 3 times wider or taller
+    let size = Vec2::new(ARENA_WIDTH, ARENA_HEIGHT);
+    for offset in [Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y] {
+        let bar_size = if offset.x != 0.0 {
+            Vec2::new(size.x, size.y * 3.0)
+        } else {
+            Vec2::new(size.x * 3.0, size.y)
+        };
+        commands.spawn((
+            Letterbox,
+            Sprite {
+                color: LETTERBOX_COLOR,
+                custom_size: Some(bar_size),
+                ..default()
+            },
+            Transform::from_translation((offset * size).extend(10.0)),
+        ));
+    }
+}
+
+// The camera takes care of the world, but UI is laid out in window pixels.
+// Scaling it with the arena keeps text and buttons the same size relative to the playfield,
+// e.g. after a phone is rotated.
+pub fn scale_ui_to_arena(
+    mut resized: EventReader<WindowResized>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    if resized.read().count() == 0 && !ui_scale.is_added() {
+        return;
+    }
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let scale = (window.width() / ARENA_WIDTH).min(window.height() / ARENA_HEIGHT);
+    if scale > 0.0 {
+        ui_scale.0 = scale;
+    }
+}
@@ -0,0 +1,709 @@
+// Paddles played by bots. A bot is anything implementing PaddleController, either right here in
+// the game or in a process of its own reached over a local socket, which can be written in anything.
+//   --bot <left|right>=<bot>   reference, tcp:<host:port> or unix:<path>
+//   --bot-timeout <ms>         how long to wait for a socket bot every tick
+//   --bot-fallback <repeat|stay>  what its paddle does when it's too slow
+//   --headless [--matches <n>]    plays bot vs bot (or the CPU) without a window and prints the results
+use crate::ai::{attach_ai_controllers, predict_intercept_y, AiController, AiSettings, Difficulty};
+use crate::arena::{ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH};
+use crate::gym::{Move, Observation, PaddleAction};
+use crate::menu::RestartRequested;
+use crate::netplay::netplaying;
+use crate::rng::GameRng;
+use crate::rules::{MatchProgress, MatchRules};
+use crate::simulation::PlayerInputSet;
+use crate::snapshot::MatchState;
+use crate::states::AppState;
+use crate::systems::move_paddles_with_actions;
+use crate::tuning::GameTuning;
+use crate::{controls::ActionState, Paddle, PongPlugin, Side};
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_BOT_TIMEOUT: Duration = Duration::from_millis(4);
+
+// Decides what one paddle does, once every tick
+pub trait PaddleController: Send {
+    fn decide(&mut self, side: Side, observation: &Observation) -> PaddleAction;
+}
+
+// Sent to a socket bot every tick, one JSON object per line:
+//   {"tick": 12, "side": "left", "state": {"ball": [x, y], "ball_velocity": [x, y], "paddles": [left, right], ...}}
+// and it answers for that tick with
+//   {"tick": 12, "move": "up", "serve": false}
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BotState {
+    pub tick: u32,
+    pub side: Side,
+    pub state: Observation,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BotReply {
+    pub tick: u32,
+    #[serde(flatten)]
+    pub action: PaddleAction,
+}
+
+// Heads for wherever the ball will cross its paddle, bouncing off the walls on the way,
+// and waits in the middle otherwise. Serves straight away.
+pub struct ReferenceBot {
+    face_x: f32,
+    ball_max_y: f32,
+    // Close enough not to bother moving
+    dead_zone: f32,
+}
+
+impl Default for ReferenceBot {
+    fn default() -> Self {
+        let tuning = GameTuning::default();
+        ReferenceBot {
+            face_x: ARENA_HALF_WIDTH - tuning.paddle_margin - tuning.paddle_width / 2.0,
+            ball_max_y: ARENA_HALF_HEIGHT - tuning.ball_radius,
+            dead_zone: tuning.paddle_height / 8.0,
+        }
+    }
+}
+
+impl PaddleController for ReferenceBot {
+    fn decide(&mut self, side: Side, observation: &Observation) -> PaddleAction {
+        let face_x = match side {
+            Side::Left => -self.face_x,
+            Side::Right => self.face_x,
+            // Bots only play the left and right, see Bots::from_args
+            Side::Top | Side::Bottom => return PaddleAction::default(),
+        };
+        let target_y = predict_intercept_y(
+            Vec2::from(observation.ball),
+            Vec2::from(observation.ball_velocity),
+            face_x,
+            self.ball_max_y,
+        )
+        .unwrap_or(0.0);
+        let paddle_y = observation.paddles[side as usize];
+        let movement = if target_y > paddle_y + self.dead_zone {
+            Move::Up
+        } else if target_y < paddle_y - self.dead_zone {
+            Move::Down
+        } else {
+            Move::Stay
+        };
+        PaddleAction {
+            movement,
+            serve: true,
+        }
+    }
+}
+
+// What a socket bot's paddle does on a tick it didn't answer in time
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BotFallback {
+    // Whatever it last asked for
+    #[default]
+    Repeat,
+    Stay,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BotAddress {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl BotAddress {
+    // `tcp:127.0.0.1:7100` or `unix:/tmp/pong-bot.sock`
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(address) = value.strip_prefix("tcp:") {
+            return Some(BotAddress::Tcp(address.to_string()));
+        }
+        #[cfg(unix)]
+        if let Some(path) = value.strip_prefix("unix:") {
+            return Some(BotAddress::Unix(path.into()));
+        }
+        None
+    }
+}
+
+impl std::fmt::Display for BotAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BotAddress::Tcp(address) => write!(f, "tcp:{address}"),
+            #[cfg(unix)]
+            BotAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// Plays a side through a bot in another process. Replies are read on a thread of their own,
+// so a bot that's slow or gone only ever costs the timeout.
+pub struct SocketBot {
+    address: BotAddress,
+    writer: Box<dyn Write + Send>,
+    replies: Receiver<BotReply>,
+    timeout: Duration,
+    fallback: BotFallback,
+    last: PaddleAction,
+    connected: bool,
+}
+
+impl SocketBot {
+    pub fn connect(
+        address: BotAddress,
+        timeout: Duration,
+        fallback: BotFallback,
+    ) -> std::io::Result<Self> {
+        let (sender, replies) = channel();
+        let writer: Box<dyn Write + Send> = match &address {
+            BotAddress::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                // Every tick is one small line, waiting to batch them up only adds lag
+                stream.set_nodelay(true)?;
+                spawn_reply_reader(stream.try_clone()?, sender);
+                Box::new(stream)
+            }
+            #[cfg(unix)]
+            BotAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                spawn_reply_reader(stream.try_clone()?, sender);
+                Box::new(stream)
+            }
+        };
+        Ok(SocketBot {
+            address,
+            writer,
+            replies,
+            timeout,
+            fallback,
+            last: PaddleAction::default(),
+            connected: true,
+        })
+    }
+
+    fn fall_back(&self) -> PaddleAction {
+        match self.fallback {
+            BotFallback::Repeat => self.last,
+            BotFallback::Stay => PaddleAction::default(),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if self.connected {
+            eprintln!("Lost the bot at {}", self.address);
+            self.connected = false;
+        }
+    }
+}
+
+fn spawn_reply_reader(stream: impl Read + Send + 'static, replies: Sender<BotReply>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            match serde_json::from_str::<BotReply>(&line) {
+                Ok(reply) => {
+                    if replies.send(reply).is_err() {
+                        return;
+                    }
+                }
+                Err(error) => eprintln!("Ignoring a bot reply: {error}"),
+            }
+        }
+    });
+}
+
+impl PaddleController for SocketBot {
+    fn decide(&mut self, side: Side, observation: &Observation) -> PaddleAction {
+        if !self.connected {
+            return self.fall_back();
+        }
+        let state = BotState {
+            tick: observation.tick,
+            side,
+            state: *observation,
+        };
+        let mut line = serde_json::to_vec(&state).expect("bot state is plain data");
+        line.push(b'\n');
+        if self.writer.write_all(&line).is_err() {
+            self.disconnect();
+            return self.fall_back();
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(left) {
+                Ok(reply) if reply.tick == observation.tick => {
+                    self.last = reply.action;
+                    return reply.action;
+                }
+                // Too late for an earlier tick
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return self.fall_back(),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.disconnect();
+                    return self.fall_back();
+                }
+            }
+        }
+    }
+}
+
+// Answers a game's ticks on `address` with a fresh controller from `make_bot` for every paddle
+// that connects, each on a thread of its own. Runs until the listener fails.
+pub fn serve_bot<C: PaddleController + 'static>(
+    address: &BotAddress,
+    make_bot: impl Fn() -> C,
+) -> std::io::Result<()> {
+    match address {
+        BotAddress::Tcp(address) => {
+            for stream in TcpListener::bind(address)?.incoming() {
+                let stream = stream?;
+                stream.set_nodelay(true)?;
+                let reader = stream.try_clone()?;
+                let bot = make_bot();
+                thread::spawn(move || answer_ticks(reader, stream, bot));
+            }
+        }
+        #[cfg(unix)]
+        BotAddress::Unix(path) => {
+            // Left over from last time
+            let _ = std::fs::remove_file(path);
+            for stream in std::os::unix::net::UnixListener::bind(path)?.incoming() {
+                let stream = stream?;
+                let reader = stream.try_clone()?;
+                let bot = make_bot();
+                thread::spawn(move || answer_ticks(reader, stream, bot));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn answer_ticks(reader: impl Read, mut writer: impl Write, mut bot: impl PaddleController) {
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        let state = match serde_json::from_str::<BotState>(&line) {
+            Ok(state) => state,
+            Err(error) => {
+                eprintln!("Ignoring a game's message: {error}");
+                continue;
+            }
+        };
+        let reply = BotReply {
+            tick: state.tick,
+            action: bot.decide(state.side, &state.state),
+        };
+        let mut line = serde_json::to_vec(&reply).expect("bot reply is plain data");
+        line.push(b'\n');
+        if writer.write_all(&line).is_err() {
+            return;
+        }
+    }
+}
+
+// The bot playing each side, if any
+#[derive(Resource, Default)]
+pub struct Bots {
+    pub left: Option<Mutex<Box<dyn PaddleController>>>,
+    pub right: Option<Mutex<Box<dyn PaddleController>>>,
+}
+
+impl Bots {
+    pub fn set(&mut self, side: Side, bot: impl PaddleController + 'static) {
+        let bot: Box<dyn PaddleController> = Box::new(bot);
+        *self.slot(side) = Some(Mutex::new(bot));
+    }
+
+    pub fn has(&self, side: Side) -> bool {
+        match side {
+            Side::Left => self.left.is_some(),
+            Side::Right => self.right.is_some(),
+            Side::Top | Side::Bottom => false,
+        }
+    }
+
+    fn slot(&mut self, side: Side) -> &mut Option<Mutex<Box<dyn PaddleController>>> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top | Side::Bottom => unreachable!("bots only play the left and right"),
+        }
+    }
+
+    // Parses `--bot`, `--bot-timeout` and `--bot-fallback`, connecting to socket bots right away
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut specs = Vec::new();
+        let mut timeout = DEFAULT_BOT_TIMEOUT;
+        let mut fallback = BotFallback::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bot" => {
+                    let spec = args.next().and_then(|value| {
+                        let (side, bot) = value.split_once('=')?;
+                        let side = match side {
+                            "left" => Side::Left,
+                            "right" => Side::Right,
+                            _ => return None,
+                        };
+                        Some((side, bot.to_string()))
+                    });
+                    match spec {
+                        Some(spec) => specs.push(spec),
+                        None => eprintln!("--bot expects <left|right>=<bot>, e.g. left=reference"),
+                    }
+                }
+                "--bot-timeout" => match args.next().and_then(|value| value.parse::<u64>().ok()) {
+                    Some(ms) => timeout = Duration::from_millis(ms),
+                    None => eprintln!("--bot-timeout expects a number of milliseconds"),
+                },
+                "--bot-fallback" => match args.next().as_deref() {
+                    Some("repeat") => fallback = BotFallback::Repeat,
+                    Some("stay") => fallback = BotFallback::Stay,
+                    _ => eprintln!("--bot-fallback expects repeat or stay"),
+                },
+                _ => {}
+            }
+        }
+
+        let mut bots = Bots::default();
+        for (side, spec) in specs {
+            if spec == "reference" {
+                bots.set(side, ReferenceBot::default());
+                continue;
+            }
+            let Some(address) = BotAddress::parse(&spec) else {
+                eprintln!(
+                    "Unknown bot '{spec}', expected reference, tcp:<host:port> or unix:<path>"
+                );
+                continue;
+            };
+            match SocketBot::connect(address, timeout, fallback) {
+                Ok(bot) => bots.set(side, bot),
+                Err(error) => eprintln!("Couldn't reach the bot at {spec}: {error}"),
+            }
+        }
+        bots
+    }
+}
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world().contains_resource::<Bots>() {
+            app.insert_resource(Bots::from_args(std::env::args().skip(1)));
+        }
+        // Bots are played like people, through ActionState, so they're left out of replays and
+        // network matches the same way
+        app.add_systems(
+            FixedUpdate,
+            (
+                release_bot_paddles.after(attach_ai_controllers),
+                drive_bots.before(move_paddles_with_actions),
+            )
+                .chain()
+                .in_set(PlayerInputSet)
+                .run_if(not(netplaying)),
+        );
+    }
+}
+
+// A bot's paddle doesn't also get the CPU, whatever was picked in the menu
+pub fn release_bot_paddles(
+    mut commands: Commands,
+    bots: Res<Bots>,
+    paddles: Query<(Entity, &Paddle), With<AiController>>,
+) {
+    for (entity, paddle) in paddles.iter() {
+        if bots.has(paddle.player()) {
+            commands.entity(entity).remove::<AiController>();
+        }
+    }
+}
+
+pub fn drive_bots(
+    mut bots: ResMut<Bots>,
+    state: MatchState,
+    mut actions: ResMut<ActionState>,
+    mut tick: Local<u32>,
+) {
+    if bots.left.is_none() && bots.right.is_none() {
+        return;
+    }
+    let Some(snapshot) = state.snapshot() else {
+        return;
+    };
+    *tick += 1;
+    let observation = Observation::from_frame(&snapshot.frame(*tick));
+    for side in [Side::Left, Side::Right] {
+        let Some(bot) = bots.slot(side) else {
+            continue;
+        };
+        let bot = bot
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        bot.decide(side, &observation)
+            .apply(actions.for_side_mut(side));
+    }
+}
+
+// `--headless` plays matches without a window, `--matches <n>` of them (1 by default).
+// `--max-ticks <n>` calls a match a draw once it's gone on that long, as two good players can
+// rally forever. 30 minutes of play by default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeadlessOptions {
+    pub matches: u32,
+    pub max_ticks: Option<u64>,
+}
+
+impl HeadlessOptions {
+    // None unless `--headless` was passed
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut headless = false;
+        let mut options = HeadlessOptions {
+            matches: 1,
+            max_ticks: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--matches" => match args.next().and_then(|value| value.parse::<u32>().ok()) {
+                    Some(value) if value > 0 => options.matches = value,
+                    _ => eprintln!("--matches expects a positive number"),
+                },
+                "--max-ticks" => match args.next().and_then(|value| value.parse::<u64>().ok()) {
+                    Some(value) if value > 0 => options.max_ticks = Some(value),
+                    _ => eprintln!("--max-ticks expects a positive number of ticks"),
+                },
+                _ => {}
+            }
+        }
+        headless.then_some(options)
+    }
+}
+
+const DEFAULT_MAX_MATCH_SECS: f64 = 30.0 * 60.0;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct HeadlessResults {
+    // Left then right
+    pub wins: [u32; 2],
+    pub draws: u32,
+}
+
+// Plays matches back to back as fast as they'll go, printing each result and the tally.
+// Sides without a bot are played by the CPU, at the `--cpu` difficulty or normal.
+pub fn run_headless_matches(plugin: PongPlugin, options: HeadlessOptions) -> HeadlessResults {
+    let max_ticks = options
+        .max_ticks
+        .unwrap_or((plugin.tick_rate_hz * DEFAULT_MAX_MATCH_SECS) as u64);
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / plugin.tick_rate_hz,
+        )))
+        .add_plugins(PongPlugin {
+            headless: true,
+            ..plugin
+        })
+        .add_plugins(BotPlugin);
+    // Bots only play the left and right, on their own
+    {
+        let mut rules = app.world_mut().resource_mut::<MatchRules>();
+        rules.four_players = false;
+        rules.doubles = false;
+    }
+    {
+        let bots = app.world().resource::<Bots>();
+        let (left, right) = (bots.has(Side::Left), bots.has(Side::Right));
+        let mut cpu = app.world_mut().resource_mut::<AiSettings>();
+        if !left {
+            cpu.left = cpu.left.or(Some(Difficulty::Normal));
+        }
+        if !right {
+            cpu.right = cpu.right.or(Some(Difficulty::Normal));
+        }
+    }
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Playing);
+
+    let mut results = HeadlessResults::default();
+    for number in 1..=options.matches {
+        if number > 1 {
+            app.world_mut().send_event(RestartRequested);
+        }
+        // Starting the match is when the seed is picked
+        while *app.world().resource::<State<AppState>>().get() != AppState::Playing {
+            app.update();
+        }
+        let seed = app.world().resource::<GameRng>().seed;
+        let mut ticks = 0;
+        while ticks < max_ticks
+            && *app.world().resource::<State<AppState>>().get() != AppState::GameOver
+        {
+            app.update();
+            ticks += 1;
+        }
+
+        let rules = *app.world().resource::<MatchRules>();
+        let progress = app.world().resource::<MatchProgress>();
+        let score = final_score(&rules, progress);
+        match progress.winner {
+            Some(winner) => {
+                results.wins[winner as usize] += 1;
+                println!("Match {number} (seed {seed}): {winner:?} won {score}");
+            }
+            None => {
+                results.draws += 1;
+                println!("Match {number} (seed {seed}): drawn at {score} after {ticks} ticks");
+            }
+        }
+    }
+    println!(
+        "Left won {}, Right won {}, {} drawn",
+        results.wins[0], results.wins[1], results.draws
+    );
+    results
+}
+
+// By whatever the match was decided on
+fn final_score(rules: &MatchRules, progress: &MatchProgress) -> String {
+    let (counts, unit) = if rules.best_of_sets > 1 {
+        (progress.sets, "sets")
+    } else if rules.best_of_games > 1 {
+        (progress.games, "games")
+    } else {
+        (progress.points, "points")
+    };
+    format!("{}-{} {unit}", counts.left, counts.right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::DEFAULT_TICK_RATE_HZ;
+
+    fn observation(ball: Vec2, velocity: Vec2, paddles: [f32; 2]) -> Observation {
+        Observation {
+            ball: ball.to_array(),
+            ball_velocity: velocity.to_array(),
+            paddles,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn reference_bot_heads_for_the_ball() {
+        let mut bot = ReferenceBot::default();
+        // Coming towards the left paddle, and going to hit the top wall first
+        let coming = observation(Vec2::new(0.0, 300.0), Vec2::new(-300.0, 300.0), [0.0, 0.0]);
+        assert_eq!(bot.decide(Side::Left, &coming).movement, Move::Down);
+        // Going away from the right paddle, so back to the middle
+        let away = observation(Vec2::ZERO, Vec2::new(-300.0, 0.0), [0.0, 200.0]);
+        assert_eq!(bot.decide(Side::Right, &away).movement, Move::Down);
+        let centered = observation(Vec2::ZERO, Vec2::new(-300.0, 0.0), [0.0, 0.0]);
+        assert_eq!(bot.decide(Side::Right, &centered).movement, Move::Stay);
+    }
+
+    #[test]
+    fn socket_bots_answer_and_fall_back_when_slow() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = BotAddress::Tcp(listener.local_addr().unwrap().to_string());
+        // Answers tick 1 with up, tick 2 with down once it's too late, and tick 3 with stay
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            for tick in 1..=3 {
+                let state: BotState =
+                    serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+                assert_eq!(state.tick, tick);
+                assert_eq!(state.side, Side::Right);
+                let movement = match tick {
+                    1 => Move::Up,
+                    2 => {
+                        thread::sleep(Duration::from_millis(100));
+                        Move::Down
+                    }
+                    _ => Move::Stay,
+                };
+                let reply = BotReply {
+                    tick,
+                    action: PaddleAction {
+                        movement,
+                        serve: false,
+                    },
+                };
+                writeln!(writer, "{}", serde_json::to_string(&reply).unwrap()).unwrap();
+            }
+        });
+
+        let mut bot =
+            SocketBot::connect(address, Duration::from_millis(50), BotFallback::Repeat).unwrap();
+        let at = |tick| Observation { tick, ..default() };
+        assert_eq!(bot.decide(Side::Right, &at(1)).movement, Move::Up);
+        // Missed, so it keeps going up
+        assert_eq!(bot.decide(Side::Right, &at(2)).movement, Move::Up);
+        // The late answer for tick 2 is skipped over
+        bot.timeout = Duration::from_secs(5);
+        assert_eq!(bot.decide(Side::Right, &at(3)).movement, Move::Stay);
+        // The bot has hung up by now
+        bot.fallback = BotFallback::Stay;
+        assert_eq!(bot.decide(Side::Right, &at(4)), PaddleAction::default());
+        assert!(!bot.connected);
+    }
+
+    #[test]
+    fn bots_play_whole_matches() {
+        let mut app = App::new();
+        let mut bots = Bots::default();
+        bots.set(Side::Left, ReferenceBot::default());
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(PongPlugin::headless(11))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / DEFAULT_TICK_RATE_HZ,
+            )))
+            .insert_resource(bots)
+            .insert_resource(MatchRules {
+                points_to_win: 3,
+                ..default()
+            })
+            // Given to the bot instead
+            .insert_resource(AiSettings {
+                left: Some(Difficulty::Easy),
+                right: None,
+                ..default()
+            })
+            .add_plugins(BotPlugin);
+        app.world_mut()
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Playing);
+
+        for _ in 0..(DEFAULT_TICK_RATE_HZ as u32 * 120) {
+            app.update();
+            if *app.world().resource::<State<AppState>>().get() == AppState::GameOver {
+                break;
+            }
+        }
+        // Nobody plays the right paddle
+        let progress = app.world().resource::<MatchProgress>();
+        assert_eq!(progress.winner, Some(Side::Left));
+        let world = app.world_mut();
+        let cpu_paddles = world
+            .query_filtered::<&Paddle, With<AiController>>()
+            .iter(world)
+            .count();
+        assert_eq!(cpu_paddles, 0);
+    }
+}
@@ -0,0 +1,311 @@
+use bevy::math::Vec2;
+
+// An axis aligned box, e.g. a paddle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub center: Vec2,
+    pub half_size: Vec2,
+}
+
+// Where along a move the ball first touches something
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    // Fraction of the move travelled before contact, from 0.0 to 1.0
+    pub time: f32,
+    // Surface normal at the contact point, pointing back out towards the ball
+    pub normal: Vec2,
+}
+
+// Which part of a paddle the ball touched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContactKind {
+    // The long side facing the ball (or the back of the paddle)
+    Face,
+    // The short top or bottom end
+    Edge,
+    // The rounded corner between the two
+    Corner,
+}
+
+impl ContactKind {
+    pub fn from_normal(normal: Vec2) -> Self {
+        // Corner normals come from the rounded corner, so anything not (nearly) axis aligned is one
+        const AXIS_ALIGNED: f32 = 0.999;
+        if normal.x.abs() >= AXIS_ALIGNED {
+            ContactKind::Face
+        } else if normal.y.abs() >= AXIS_ALIGNED {
+            ContactKind::Edge
+        } else {
+            ContactKind::Corner
+        }
+    }
+}
+
+// Mirrors `velocity` about a surface with the given (unit) normal
+pub fn reflect(velocity: Vec2, normal: Vec2) -> Vec2 {
+    velocity - 2.0 * velocity.dot(normal) * normal
+}
+
+// Sweeps a circle from `start` by `motion` and finds the first time it touches `aabb`.
+// This is a ray cast against the box grown by the radius with rounded corners, so a
+// fast ball can't skip over a thin paddle between two frames.
+// Only hits where the circle is moving into the surface are reported.
+pub fn sweep_circle_aabb(start: Vec2, motion: Vec2, radius: f32, aabb: Aabb) -> Option<SweepHit> {
+    let local = start - aabb.center;
+    let half = aabb.half_size;
+
+    // Already touching, e.g. the paddle moved into the ball
+    if let Some(normal) = overlap_normal(local, radius, half) {
+        return (motion.dot(normal) < 0.0).then_some(SweepHit { time: 0.0, normal });
+    }
+
+    // Slab test against the box grown by the radius
+    let expanded = half + Vec2::splat(radius);
+    let mut t_enter = 0.0_f32;
+    let mut t_exit = 1.0_f32;
+    let mut face_normal = Vec2::ZERO;
+    for axis in 0..2 {
+        let (position, direction, extent) = (local[axis], motion[axis], expanded[axis]);
+        if direction == 0.0 {
+            if position.abs() > extent {
+                return None;
+            }
+            continue;
+        }
+        let t_near = (-direction.signum() * extent - position) / direction;
+        let t_far = (direction.signum() * extent - position) / direction;
+        if t_near > t_enter {
+            t_enter = t_near;
+            face_normal = Vec2::ZERO;
+            face_normal[axis] = -direction.signum();
+        }
+        t_exit = t_exit.min(t_far);
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    // Landed on the flat part of a face
+    let contact = local + motion * t_enter;
+    let on_face = (face_normal.x != 0.0 && contact.y.abs() <= half.y)
+        || (face_normal.y != 0.0 && contact.x.abs() <= half.x);
+    if on_face {
+        return Some(SweepHit {
+            time: t_enter,
+            normal: face_normal,
+        });
+    }
+
+    // Otherwise it's heading for one of the rounded corners
+    [
+        Vec2::new(-half.x, -half.y),
+        Vec2::new(-half.x, half.y),
+        Vec2::new(half.x, -half.y),
+        Vec2::new(half.x, half.y),
+    ]
+    .into_iter()
+    .filter_map(|corner| {
+        let time = sweep_point_circle(local, motion, corner, radius)?;
+        let normal = (local + motion * time - corner).normalize_or_zero();
+        Some(SweepHit { time, normal })
+    })
+    .min_by(|a, b| a.time.total_cmp(&b.time))
+}
+
+// Sweeps a circle against the top and bottom walls, which sit at +-max_y for the circle's center
+pub fn sweep_circle_walls(start: Vec2, motion: Vec2, max_y: f32) -> Option<SweepHit> {
+    let (wall_y, normal) = if motion.y > 0.0 {
+        (max_y, Vec2::NEG_Y)
+    } else if motion.y < 0.0 {
+        (-max_y, Vec2::Y)
+    } else {
+        return None;
+    };
+    let end_y = start.y + motion.y;
+    if (motion.y > 0.0 && end_y <= wall_y) || (motion.y < 0.0 && end_y >= wall_y) {
+        return None;
+    }
+    // Already past the wall (e.g. the window shrank) counts as hitting it straight away
+    let time = ((wall_y - start.y) / motion.y).clamp(0.0, 1.0);
+    Some(SweepHit { time, normal })
+}
+
+// First time a point moving from `start` by `motion` comes within `radius` of `center`
+fn sweep_point_circle(start: Vec2, motion: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    let offset = start - center;
+    let a = motion.length_squared();
+    if a == 0.0 {
+        return None;
+    }
+    let b = 2.0 * offset.dot(motion);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    (0.0..=1.0).contains(&time).then_some(time)
+}
+
+// If a circle at `local` (relative to the box center) overlaps the box, the direction to push it out
+fn overlap_normal(local: Vec2, radius: f32, half: Vec2) -> Option<Vec2> {
+    let closest = local.clamp(-half, half);
+    let offset = local - closest;
+    if offset.length_squared() >= radius * radius {
+        return None;
+    }
+    if offset != Vec2::ZERO {
+        return Some(offset.normalize());
+    }
+    // Center is inside the box, push out through the nearest face
+    let penetration = half - local.abs();
+    Some(if penetration.x < penetration.y {
+        Vec2::new(local.x.signum(), 0.0)
+    } else {
+        Vec2::new(0.0, local.y.signum())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: f32 = 5.0;
+
+    fn paddle_at(x: f32, y: f32) -> Aabb {
+        Aabb {
+            center: Vec2::new(x, y),
+            half_size: Vec2::new(5.0, 50.0),
+        }
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_paddle() {
+        // 2000px in a single step, way wider than the paddle
+        let hit = sweep_circle_aabb(
+            Vec2::new(-500.0, 0.0),
+            Vec2::new(2000.0, 0.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        )
+        .expect("ball should hit the paddle");
+        assert_eq!(hit.normal, Vec2::NEG_X);
+        // Touches the face 490px into the move
+        assert!((hit.time - 490.0 / 2000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn low_frame_rate_still_hits() {
+        // Max ball speed at 4fps
+        let velocity = Vec2::new(-800.0, 100.0);
+        let delta_secs = 0.25;
+        let start = Vec2::new(60.0, -10.0);
+        let hit = sweep_circle_aabb(start, velocity * delta_secs, RADIUS, paddle_at(0.0, 0.0))
+            .expect("ball should hit the paddle");
+        assert_eq!(hit.normal, Vec2::X);
+        let contact = start + velocity * delta_secs * hit.time;
+        assert!((contact.x - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn frame_hitch_at_frenzy_speed() {
+        // A 0.5s hitch with the frenzy boost on top of max speed
+        let motion = Vec2::new(800.0 * 1.5, 0.0) * 0.5;
+        let hit = sweep_circle_aabb(Vec2::new(-300.0, 20.0), motion, RADIUS, paddle_at(0.0, 0.0));
+        assert!(hit.is_some());
+    }
+
+    #[test]
+    fn passing_above_paddle_misses() {
+        let hit = sweep_circle_aabb(
+            Vec2::new(-500.0, 60.0),
+            Vec2::new(1000.0, 0.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        );
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn moving_away_misses() {
+        let hit = sweep_circle_aabb(
+            Vec2::new(-20.0, 0.0),
+            Vec2::new(-1000.0, 0.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        );
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn stops_short_of_paddle() {
+        let hit = sweep_circle_aabb(
+            Vec2::new(-100.0, 0.0),
+            Vec2::new(50.0, 0.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        );
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn contact_kinds() {
+        assert_eq!(ContactKind::from_normal(Vec2::X), ContactKind::Face);
+        assert_eq!(ContactKind::from_normal(Vec2::NEG_Y), ContactKind::Edge);
+        assert_eq!(
+            ContactKind::from_normal(Vec2::new(1.0, 1.0).normalize()),
+            ContactKind::Corner
+        );
+    }
+
+    #[test]
+    fn ball_dropping_onto_paddle_hits_top_edge() {
+        let hit = sweep_circle_aabb(
+            Vec2::new(2.0, 80.0),
+            Vec2::new(0.0, -40.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        )
+        .expect("ball should land on the paddle");
+        assert_eq!(hit.normal, Vec2::Y);
+        assert!((hit.time - 25.0 / 40.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ball_clipping_corner_gets_diagonal_normal() {
+        // Heading straight down just past the right edge, so it catches the rounded top-right corner
+        let hit = sweep_circle_aabb(
+            Vec2::new(8.0, 80.0),
+            Vec2::new(0.0, -40.0),
+            RADIUS,
+            paddle_at(0.0, 0.0),
+        )
+        .expect("ball should clip the corner");
+        assert_eq!(ContactKind::from_normal(hit.normal), ContactKind::Corner);
+        assert!(hit.normal.x > 0.0 && hit.normal.y > 0.0);
+    }
+
+    #[test]
+    fn reflect_about_normal() {
+        assert_eq!(
+            reflect(Vec2::new(-300.0, 100.0), Vec2::X),
+            Vec2::new(300.0, 100.0)
+        );
+        assert_eq!(
+            reflect(Vec2::new(200.0, -300.0), Vec2::Y),
+            Vec2::new(200.0, 300.0)
+        );
+    }
+
+    #[test]
+    fn wall_hit_time() {
+        let hit = sweep_circle_walls(Vec2::new(0.0, 90.0), Vec2::new(0.0, 40.0), 100.0)
+            .expect("ball should hit the top wall");
+        assert_eq!(hit.normal, Vec2::NEG_Y);
+        assert!((hit.time - 0.25).abs() < 1e-6);
+        assert_eq!(
+            sweep_circle_walls(Vec2::new(0.0, 50.0), Vec2::new(0.0, 40.0), 100.0),
+            None
+        );
+    }
+}
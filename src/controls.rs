@@ -0,0 +1,681 @@
+use crate::gamepad::{stick_direction, GamepadAssignments, StickResponse};
+use crate::menu::{button_bundle, hint_text, menu_root, player_number, spawn_button, MenuButton};
+use crate::netplay::NetSession;
+use crate::persist;
+use crate::states::AppState;
+use crate::Side;
+use bevy::{
+    input::InputSystem,
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::mem::discriminant;
+
+const BINDINGS_FILE: &str = "bindings.ron";
+
+// Everything a player can do. Gameplay systems ask about these instead of looking at keys or buttons.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    PaddleUp,
+    PaddleDown,
+    Pause,
+    Restart,
+    Serve,
+    Mute,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::PaddleUp,
+        Action::PaddleDown,
+        Action::Pause,
+        Action::Restart,
+        Action::Serve,
+        Action::Mute,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PaddleUp => "Paddle Up",
+            Action::PaddleDown => "Paddle Down",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Serve => "Serve",
+            Action::Mute => "Mute",
+        }
+    }
+
+    // Top and bottom paddles lie flat, so theirs move right and left
+    pub fn name_for(self, side: Side) -> &'static str {
+        match self {
+            Action::PaddleUp if side.is_horizontal() => "Paddle Right",
+            Action::PaddleDown if side.is_horizontal() => "Paddle Left",
+            _ => self.name(),
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+// Something that can trigger an action
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // A button on whichever gamepad is assigned to the player
+    Gamepad(GamepadButton),
+    TwoFingerTap,
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => {
+                let name = format!("{key:?}");
+                name.strip_prefix("Key")
+                    .or_else(|| name.strip_prefix("Digit"))
+                    .unwrap_or(&name)
+                    .to_string()
+            }
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::Gamepad(button) => format!("Pad {button:?}"),
+            Binding::TwoFingerTap => "Two Finger Tap".to_string(),
+        }
+    }
+
+    // Bindings from the same kind of device replace each other when rebinding
+    pub fn same_device(&self, other: &Binding) -> bool {
+        discriminant(self) == discriminant(other)
+    }
+
+    pub fn pressed(
+        &self,
+        keyboard: &ButtonInput<KeyCode>,
+        mouse: &ButtonInput<MouseButton>,
+        gamepad: Option<&Gamepad>,
+        touches: &Touches,
+    ) -> bool {
+        match self {
+            Binding::Key(key) => keyboard.pressed(*key),
+            Binding::Mouse(button) => mouse.pressed(*button),
+            Binding::Gamepad(button) => gamepad.is_some_and(|gamepad| gamepad.pressed(*button)),
+            Binding::TwoFingerTap => touches.iter().count() >= 2,
+        }
+    }
+}
+
+// One player's bindings for every action
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayerBindings(pub BTreeMap<Action, Vec<Binding>>);
+
+impl PlayerBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // Swaps out the action's binding for the same kind of device, leaving the others alone,
+    // e.g. a new key keeps the gamepad button. The binding is taken off any other action first.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.0.values_mut() {
+            bindings.retain(|existing| *existing != binding);
+        }
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|existing| !existing.same_device(&binding));
+        bindings.push(binding);
+    }
+
+    pub fn label(&self, action: Action) -> String {
+        let bindings = self.get(action);
+        if bindings.is_empty() {
+            return "Unbound".to_string();
+        }
+        bindings
+            .iter()
+            .map(Binding::label)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// Bindings saved before there were four players get the defaults for the other two
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputBindings {
+    pub left: PlayerBindings,
+    pub right: PlayerBindings,
+    pub top: PlayerBindings,
+    pub bottom: PlayerBindings,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::{Gamepad, Key, TwoFingerTap};
+
+        let player = |up: KeyCode, down: KeyCode, serve: KeyCode, pause: &[Binding]| {
+            PlayerBindings(BTreeMap::from([
+                (
+                    Action::PaddleUp,
+                    vec![Key(up), Gamepad(GamepadButton::DPadUp)],
+                ),
+                (
+                    Action::PaddleDown,
+                    vec![Key(down), Gamepad(GamepadButton::DPadDown)],
+                ),
+                (Action::Pause, pause.to_vec()),
+                (Action::Restart, vec![Gamepad(GamepadButton::Select)]),
+                (
+                    Action::Serve,
+                    vec![Key(serve), Gamepad(GamepadButton::South)],
+                ),
+            ]))
+        };
+
+        // Keys that aren't really anyone's (pause, restart) live with the left player
+        let mut left = player(
+            KeyCode::KeyW,
+            KeyCode::KeyS,
+            KeyCode::Space,
+            &[
+                Key(KeyCode::Escape),
+                Key(KeyCode::KeyP),
+                Gamepad(GamepadButton::Start),
+                TwoFingerTap,
+            ],
+        );
+        left.rebind(Action::Restart, Key(KeyCode::KeyR));
+        left.rebind(Action::Mute, Key(KeyCode::KeyN));
+        let right = player(
+            KeyCode::ArrowUp,
+            KeyCode::ArrowDown,
+            KeyCode::Enter,
+            &[Gamepad(GamepadButton::Start)],
+        );
+        // The top and bottom paddles go right and left, on the d-pad too
+        let sideways = |up: KeyCode, down: KeyCode, serve: KeyCode| {
+            let mut bindings = player(up, down, serve, &[Gamepad(GamepadButton::Start)]);
+            bindings.rebind(Action::PaddleUp, Gamepad(GamepadButton::DPadRight));
+            bindings.rebind(Action::PaddleDown, Gamepad(GamepadButton::DPadLeft));
+            bindings
+        };
+        let top = sideways(KeyCode::KeyL, KeyCode::KeyJ, KeyCode::KeyK);
+        let bottom = sideways(KeyCode::Numpad6, KeyCode::Numpad4, KeyCode::Numpad5);
+        InputBindings {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn for_side(&self, side: Side) -> &PlayerBindings {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+            Side::Top => &self.top,
+            Side::Bottom => &self.bottom,
+        }
+    }
+
+    pub fn for_side_mut(&mut self, side: Side) -> &mut PlayerBindings {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+
+    // Saved bindings, or the defaults if there aren't any (or they can't be read)
+    pub fn load() -> Self {
+        let Some(saved) = persist::load(BINDINGS_FILE) else {
+            return InputBindings::default();
+        };
+        match ron::from_str::<InputBindings>(&saved) {
+            Ok(mut bindings) => {
+                bindings.fill_in_new_actions();
+                bindings
+            }
+            Err(error) => {
+                eprintln!("Ignoring saved controls, couldn't read them: {error}");
+                InputBindings::default()
+            }
+        }
+    }
+
+    // Actions added since the bindings were saved get their default bindings
+    fn fill_in_new_actions(&mut self) {
+        let defaults = InputBindings::default();
+        for side in Side::ALL {
+            let player = self.for_side_mut(side);
+            for (action, bindings) in &defaults.for_side(side).0 {
+                player.0.entry(*action).or_insert_with(|| bindings.clone());
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(serialized) => persist::save(BINDINGS_FILE, &serialized),
+            Err(error) => eprintln!("Couldn't save controls: {error}"),
+        }
+    }
+}
+
+// Which actions one player is holding this frame
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PlayerActions {
+    pub pressed: u8,
+    pub just_pressed: u8,
+}
+
+impl PlayerActions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed & action.bit() != 0
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed & action.bit() != 0
+    }
+
+    pub fn set_pressed(&mut self, action: Action, pressed: bool) {
+        if pressed {
+            self.pressed |= action.bit();
+        } else {
+            self.pressed &= !action.bit();
+        }
+    }
+
+    // Paddle Up moves the paddle down and the other way around, for the reversed controls power-up
+    pub fn with_paddle_reversed(self) -> Self {
+        let swap = |bits: u8| {
+            let mut swapped = bits & !(Action::PaddleUp.bit() | Action::PaddleDown.bit());
+            if bits & Action::PaddleUp.bit() != 0 {
+                swapped |= Action::PaddleDown.bit();
+            }
+            if bits & Action::PaddleDown.bit() != 0 {
+                swapped |= Action::PaddleUp.bit();
+            }
+            swapped
+        };
+        PlayerActions {
+            pressed: swap(self.pressed),
+            just_pressed: swap(self.just_pressed),
+        }
+    }
+
+    fn update(&mut self, pressed_now: u8) {
+        self.just_pressed = pressed_now & !self.pressed;
+        self.pressed = pressed_now;
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pub left: PlayerActions,
+    pub right: PlayerActions,
+    pub top: PlayerActions,
+    pub bottom: PlayerActions,
+}
+
+impl ActionState {
+    pub fn for_side(&self, side: Side) -> PlayerActions {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    pub fn for_side_mut(&mut self, side: Side) -> &mut PlayerActions {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+
+    pub fn any_just_pressed(&self, action: Action) -> bool {
+        Side::ALL
+            .into_iter()
+            .any(|side| self.for_side(side).just_pressed(action))
+    }
+}
+
+// The action being rebound on the controls screen, waiting for the next key or button
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<(Side, Action)>);
+
+#[derive(Component, Clone, Copy)]
+pub struct RebindButton {
+    pub side: Side,
+    pub action: Action,
+}
+
+#[derive(Component)]
+pub struct RebindPrompt;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBindings::load())
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem))
+            .add_systems(OnEnter(AppState::Controls), spawn_controls_screen)
+            .add_systems(OnExit(AppState::Controls), stop_rebinding)
+            .add_systems(
+                Update,
+                (
+                    (rebind_controls, update_rebind_labels)
+                        .chain()
+                        .run_if(in_state(AppState::Controls)),
+                    save_bindings,
+                ),
+            );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_action_state(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    gamepads: Query<&Gamepad>,
+    assignments: Res<GamepadAssignments>,
+    stick_response: Res<StickResponse>,
+    bindings: Res<InputBindings>,
+    net_session: Option<Res<NetSession>>,
+    mut actions: ResMut<ActionState>,
+) {
+    for side in Side::ALL {
+        let gamepad = assignments
+            .for_side(side)
+            .and_then(|entity| gamepads.get(entity).ok());
+        let player_bindings = bindings.for_side(side);
+        let mut pressed_now = Action::ALL
+            .into_iter()
+            .filter(|action| {
+                player_bindings
+                    .get(*action)
+                    .iter()
+                    .any(|binding| binding.pressed(&keyboard, &mouse, gamepad, &touches))
+            })
+            .fold(0, |bits, action| bits | action.bit());
+        // Network matches only send which actions are held, so there the stick pushed past its
+        // deadzone holds PaddleUp or PaddleDown, at full speed
+        if let (Some(gamepad), Some(_)) = (gamepad, &net_session) {
+            let direction = stick_direction(gamepad, &stick_response, side);
+            if direction > 0.0 {
+                pressed_now |= Action::PaddleUp.bit();
+            } else if direction < 0.0 {
+                pressed_now |= Action::PaddleDown.bit();
+            }
+        }
+
+        actions.for_side_mut(side).update(pressed_now);
+    }
+}
+
+pub fn save_bindings(bindings: Res<InputBindings>) {
+    if bindings.is_changed() && !bindings.is_added() {
+        bindings.save();
+    }
+}
+
+pub fn spawn_controls_screen(mut commands: Commands, bindings: Res<InputBindings>) {
+    commands
+        .spawn((StateScoped(AppState::Controls), menu_root(Color::NONE)))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Controls"),
+                TextFont {
+                    font_size: 60.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                },
+            ));
+
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|columns| {
+                    for side in Side::ALL {
+                        columns
+                            .spawn(Node {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            })
+                            .with_children(|column| {
+                                column.spawn((
+                                    Text::new(format!("P{}", player_number(side))),
+                                    TextFont {
+                                        font_size: 30.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::WHITE),
+                                ));
+                                for action in Action::ALL {
+                                    let button = RebindButton { side, action };
+                                    column.spawn((button, button_bundle(300.0))).with_children(
+                                        |button_parent| {
+                                            button_parent.spawn((
+                                                button,
+                                                Text::new(rebind_label(&bindings, button)),
+                                                TextFont {
+                                                    font_size: 18.0,
+                                                    ..default()
+                                                },
+                                                TextColor(Color::WHITE),
+                                            ));
+                                        },
+                                    );
+                                }
+                            });
+                    }
+                });
+
+            parent.spawn((RebindPrompt, hint_text(REBIND_HINT)));
+
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, "Reset to Defaults", MenuButton::ResetControls);
+                    spawn_button(row, "Back", MenuButton::MainMenu);
+                });
+        });
+}
+
+const REBIND_HINT: &str = "Click an action to rebind it";
+
+fn rebind_label(bindings: &InputBindings, button: RebindButton) -> String {
+    format!(
+        "{}: {}",
+        button.action.name_for(button.side),
+        bindings.for_side(button.side).label(button.action)
+    )
+}
+
+// Clicking an action starts listening, and the next key, mouse or gamepad button pressed is bound to it.
+// Escape cancels.
+pub fn rebind_controls(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    buttons: Query<(&Interaction, &RebindButton), Changed<Interaction>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let Some((side, action)) = rebinding.0 else {
+        // Returning straight after means the click that started listening isn't bound itself
+        if let Some((_, button)) = buttons
+            .iter()
+            .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        {
+            rebinding.0 = Some((button.side, button.action));
+        }
+        return;
+    };
+
+    if keyboard.just_pressed(KeyCode::Escape) {
+        rebinding.0 = None;
+        return;
+    }
+
+    let pressed = keyboard
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepads.iter().find_map(|gamepad| {
+                gamepad
+                    .get_just_pressed()
+                    .next()
+                    .map(|button| Binding::Gamepad(*button))
+            })
+        });
+    if let Some(binding) = pressed {
+        bindings.for_side_mut(side).rebind(action, binding);
+        rebinding.0 = None;
+    }
+}
+
+pub fn update_rebind_labels(
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut labels: Query<(&mut Text, &RebindButton)>,
+    mut prompt: Query<&mut Text, (With<RebindPrompt>, Without<RebindButton>)>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (mut text, button) in labels.iter_mut() {
+        text.0 = rebind_label(&bindings, *button);
+    }
+    for mut text in prompt.iter_mut() {
+        text.0 = match rebinding.0 {
+            Some((side, action)) => format!(
+                "Press a key, mouse or pad button for P{} {}, Escape to cancel",
+                player_number(side),
+                action.name_for(side)
+            ),
+            None => REBIND_HINT.to_string(),
+        };
+    }
+}
+
+pub fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_keeps_other_devices() {
+        let mut bindings = InputBindings::default();
+        bindings
+            .left
+            .rebind(Action::PaddleUp, Binding::Key(KeyCode::KeyI));
+        assert_eq!(
+            bindings.left.get(Action::PaddleUp),
+            &[
+                Binding::Gamepad(GamepadButton::DPadUp),
+                Binding::Key(KeyCode::KeyI)
+            ]
+        );
+    }
+
+    #[test]
+    fn rebinding_takes_binding_off_other_actions() {
+        let mut bindings = InputBindings::default();
+        bindings
+            .left
+            .rebind(Action::PaddleDown, Binding::Key(KeyCode::KeyW));
+        assert_eq!(
+            bindings.left.get(Action::PaddleUp),
+            &[Binding::Gamepad(GamepadButton::DPadUp)]
+        );
+        // The other player is left alone
+        assert!(bindings
+            .right
+            .get(Action::Pause)
+            .contains(&Binding::Gamepad(GamepadButton::Start)));
+    }
+
+    #[test]
+    fn bindings_survive_a_save() {
+        let bindings = InputBindings::default();
+        let saved = ron::to_string(&bindings).unwrap();
+        assert_eq!(ron::from_str::<InputBindings>(&saved).unwrap(), bindings);
+    }
+
+    #[test]
+    fn new_actions_are_bound_in_old_saves() {
+        let mut bindings = InputBindings::default();
+        bindings.left.0.remove(&Action::Mute);
+        bindings.fill_in_new_actions();
+        assert_eq!(
+            bindings.left.get(Action::Mute),
+            &[Binding::Key(KeyCode::KeyN)]
+        );
+    }
+
+    #[test]
+    fn two_player_saves_get_the_top_and_bottom_defaults() {
+        let saved = "(left: ({}), right: ({}))";
+        let bindings = ron::from_str::<InputBindings>(saved).unwrap();
+        assert!(bindings.left.0.is_empty());
+        assert_eq!(bindings.top, InputBindings::default().top);
+        assert!(bindings
+            .bottom
+            .get(Action::PaddleUp)
+            .contains(&Binding::Gamepad(GamepadButton::DPadRight)));
+    }
+
+    #[test]
+    fn just_pressed_only_lasts_a_frame() {
+        let mut actions = PlayerActions::default();
+        actions.update(Action::Serve.bit());
+        assert!(actions.just_pressed(Action::Serve));
+        actions.update(Action::Serve.bit());
+        assert!(actions.pressed(Action::Serve));
+        assert!(!actions.just_pressed(Action::Serve));
+    }
+
+    #[test]
+    fn reversing_swaps_up_and_down_only() {
+        let mut actions = PlayerActions::default();
+        actions.update(Action::PaddleUp.bit() | Action::Serve.bit());
+        let reversed = actions.with_paddle_reversed();
+        assert!(reversed.pressed(Action::PaddleDown));
+        assert!(!reversed.pressed(Action::PaddleUp));
+        assert!(reversed.just_pressed(Action::Serve));
+        assert_eq!(reversed.with_paddle_reversed(), actions);
+    }
+}
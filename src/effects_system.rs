@@ -1,5 +1,6 @@
 use crate::{Particle, ScoreCelebration};
 use bevy::prelude::*; // Assuming these are pub in main.rs or lib.rs
+use rand::Rng;
 
 // Define colors for celebration
 const CELEBRATION_TEXT_COLOR: Color = Color::srgb(1.0, 1.0, 1.0); // Bright white
@@ -31,13 +32,15 @@ pub fn manage_particles(
 
 pub fn spawn_particle_burst(
     commands: &mut Commands,
+    // Pass the cosmetic stream so particles never affect gameplay
+    rng: &mut impl Rng,
     position: Vec2,
     color: Color,
     count: u32,
     base_lifetime_secs: f32,
 ) {
     for _ in 0..count {
-        let lifetime_variation = rand::random::<f32>() * 0.2 - 0.1;
+        let lifetime_variation = rng.gen::<f32>() * 0.2 - 0.1;
         let lifetime = (base_lifetime_secs + lifetime_variation).max(0.1);
 
         commands.spawn((
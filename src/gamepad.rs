@@ -0,0 +1,241 @@
+use crate::ai::{attach_ai_controllers, AiController, AiSettings};
+use crate::arena::ARENA_HALF_HEIGHT;
+use crate::controls::{Action, ActionState};
+use crate::powerups::ControlsReversed;
+use crate::rules::MatchRules;
+use crate::simulation::PlayerInputSet;
+use crate::systems::constrain_paddle;
+use crate::tuning::GameTuning;
+use crate::{Paddle, PaddleScale, Side};
+use bevy::prelude::*;
+
+// Stick positions closer to the middle than this are ignored, so a worn stick doesn't drift
+pub const DEFAULT_STICK_DEADZONE: f32 = 0.15;
+// 1.0 is linear, higher gives finer control near the middle of the stick
+pub const DEFAULT_STICK_CURVE: f32 = 2.0;
+
+// How analog stick movement turns into paddle speed
+#[derive(Resource, Clone, Copy)]
+pub struct StickResponse {
+    pub deadzone: f32,
+    pub curve: f32,
+}
+
+impl Default for StickResponse {
+    fn default() -> Self {
+        StickResponse {
+            deadzone: DEFAULT_STICK_DEADZONE,
+            curve: DEFAULT_STICK_CURVE,
+        }
+    }
+}
+
+impl StickResponse {
+    // Parses `--stick-deadzone <0..1>` and `--stick-curve <exponent>`
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut response = StickResponse::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--stick-deadzone" => {
+                    match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                        Some(deadzone) if (0.0..1.0).contains(&deadzone) => {
+                            response.deadzone = deadzone
+                        }
+                        _ => eprintln!("--stick-deadzone expects a number from 0 up to 1"),
+                    }
+                }
+                "--stick-curve" => match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                    Some(curve) if curve > 0.0 => response.curve = curve,
+                    _ => eprintln!("--stick-curve expects a positive number"),
+                },
+                _ => {}
+            }
+        }
+        response
+    }
+
+    // Maps a raw axis value (-1 to 1) to a paddle speed fraction (-1 to 1).
+    // The deadzone is cut out and the rest of the range stretched to fill it, then bent by the curve.
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+        let scaled = ((magnitude - self.deadzone) / (1.0 - self.deadzone)).min(1.0);
+        value.signum() * scaled.powf(self.curve)
+    }
+}
+
+// Which connected gamepad drives which paddle
+#[derive(Resource, Default)]
+pub struct GamepadAssignments {
+    pub left: Option<Entity>,
+    pub right: Option<Entity>,
+    pub top: Option<Entity>,
+    pub bottom: Option<Entity>,
+}
+
+impl GamepadAssignments {
+    pub fn for_side(&self, side: Side) -> Option<Entity> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    fn slot(&mut self, side: Side) -> &mut Option<Entity> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+}
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StickResponse::from_args(std::env::args().skip(1)))
+            .init_resource::<GamepadAssignments>()
+            .add_systems(Update, assign_gamepads)
+            .add_systems(
+                FixedUpdate,
+                move_paddles_with_gamepad
+                    .after(attach_ai_controllers)
+                    .in_set(PlayerInputSet),
+            );
+    }
+}
+
+// Hands newly connected pads a free paddle and frees up the paddle of any pad that was unplugged.
+// Sides played by people are filled first, so the one pad in a game against the CPU always gets a paddle to move.
+pub fn assign_gamepads(
+    mut assignments: ResMut<GamepadAssignments>,
+    gamepads: Query<(Entity, &Name), With<Gamepad>>,
+    ai_settings: Res<AiSettings>,
+    rules: Res<MatchRules>,
+) {
+    for side in Side::ALL {
+        let slot = assignments.slot(side);
+        if let Some(entity) = *slot {
+            if !gamepads.contains(entity) {
+                println!("Gamepad for the {side:?} paddle disconnected");
+                *slot = None;
+            }
+        }
+    }
+
+    // Only players with a paddle in the match, so the top and bottom only take pads in four player
+    // and doubles ones
+    let mut free_sides: Vec<Side> = rules
+        .players()
+        .iter()
+        .copied()
+        .filter(|side| assignments.for_side(*side).is_none())
+        .collect();
+    free_sides.sort_by_key(|side| ai_settings.for_side(*side).is_some());
+
+    for (entity, name) in gamepads.iter() {
+        if free_sides.is_empty() {
+            break;
+        }
+        if Side::ALL
+            .into_iter()
+            .any(|side| assignments.for_side(side) == Some(entity))
+        {
+            continue;
+        }
+        let side = free_sides.remove(0);
+        println!("{name} controls the {side:?} paddle");
+        *assignments.slot(side) = Some(entity);
+    }
+}
+
+// How far the stick is pushed along the track of a paddle on `side`, from -1 to 1.
+// Top and bottom paddles go along with the stick held sideways.
+pub fn stick_direction(gamepad: &Gamepad, response: &StickResponse, side: Side) -> f32 {
+    let axis = if side.is_horizontal() {
+        GamepadAxis::LeftStickX
+    } else {
+        GamepadAxis::LeftStickY
+    };
+    response.apply(gamepad.get(axis).unwrap_or(0.0))
+}
+
+// Analog stick for proportional speed. The d-pad goes through the action bindings like a keyboard.
+pub fn move_paddles_with_gamepad(
+    time: Res<Time>,
+    assignments: Res<GamepadAssignments>,
+    stick_response: Res<StickResponse>,
+    actions: Res<ActionState>,
+    tuning: Res<GameTuning>,
+    gamepads: Query<&Gamepad>,
+    mut paddles: Query<
+        (&mut Transform, &Paddle, &PaddleScale, Has<ControlsReversed>),
+        Without<AiController>,
+    >,
+) {
+    for (mut transform, paddle, scale, reversed) in paddles.iter_mut() {
+        let Some(gamepad) = assignments
+            .for_side(paddle.player())
+            .and_then(|entity| gamepads.get(entity).ok())
+        else {
+            continue;
+        };
+
+        // Buttons win over the stick, so the two never add up to more than full speed
+        let player = actions.for_side(paddle.player());
+        if player.pressed(Action::PaddleUp) || player.pressed(Action::PaddleDown) {
+            continue;
+        }
+        let mut direction = stick_direction(gamepad, &stick_response, paddle.side);
+        if reversed {
+            direction = -direction;
+        }
+        if direction == 0.0 {
+            continue;
+        }
+
+        // Full tilt matches the keyboard speed
+        let step = direction * ARENA_HALF_HEIGHT * tuning.keyboard_paddle_speed * time.delta_secs();
+        transform.translation += (paddle.side.along() * step).extend(0.0);
+        constrain_paddle(&mut transform.translation, paddle, *scale, &tuning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_is_ignored() {
+        let response = StickResponse::default();
+        assert_eq!(response.apply(0.1), 0.0);
+        assert_eq!(response.apply(-DEFAULT_STICK_DEADZONE), 0.0);
+    }
+
+    #[test]
+    fn full_tilt_is_full_speed() {
+        let response = StickResponse::default();
+        assert_eq!(response.apply(1.0), 1.0);
+        assert_eq!(response.apply(-1.0), -1.0);
+    }
+
+    #[test]
+    fn curve_bends_the_middle_of_the_range() {
+        let linear = StickResponse {
+            deadzone: 0.0,
+            curve: 1.0,
+        };
+        let squared = StickResponse {
+            deadzone: 0.0,
+            curve: 2.0,
+        };
+        assert!((linear.apply(0.5) - 0.5).abs() < 1e-6);
+        assert!((squared.apply(-0.5) + 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,868 @@
+// Environments for training bots: reset a match, then step it with both paddles' actions and get
+// back what happened, gym style. Each one is a headless app of its own.
+// `--gym` serves them over stdin and stdout as JSON lines for trainers that aren't written in Rust.
+use crate::ai::{AiSettings, Difficulty};
+use crate::arena::{ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH, ARENA_HEIGHT};
+use crate::controls::{Action, ActionState, PlayerActions};
+use crate::rules::MatchRules;
+use crate::simulation::{restore_simulated_translation, DEFAULT_TICK_RATE_HZ};
+use crate::snapshot::MatchState;
+use crate::spectate::{returned, scorer, StateFrame};
+use crate::states::AppState;
+use crate::tuning::GameTuning;
+use crate::{PongPlugin, Side};
+use bevy::{ecs::system::SystemState, prelude::*, time::TimeUpdateStrategy};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+// Twice the serve speed, which keeps scaled velocities at roughly -1..1
+const VELOCITY_SCALE: f32 = ARENA_HALF_WIDTH;
+
+// What the reward is made of. Everything is from the agent's side, so losing a point costs `point`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardShaping {
+    pub point: f32,
+    // Every time the agent sends the ball back
+    pub hit: f32,
+    // Taken off every tick, times how far the agent's paddle is from the ball's height
+    // as a fraction of the arena. Helps early on, before points are scored at all.
+    pub tracking: f32,
+    // On top of the last point
+    pub win: f32,
+}
+
+impl Default for RewardShaping {
+    fn default() -> Self {
+        RewardShaping {
+            point: 1.0,
+            hit: 0.0,
+            tracking: 0.0,
+            win: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvConfig {
+    // The side being rewarded
+    pub agent: Side,
+    // The CPU plays the other side, or None to drive both from the actions, e.g. for self-play
+    pub opponent: Option<Difficulty>,
+    // Gameplay ticks per step, each holding the same actions
+    pub ticks_per_step: u32,
+    // Episodes are cut short after this many ticks, and `truncated` is set
+    pub max_ticks: Option<u32>,
+    pub rules: MatchRules,
+    pub tuning: GameTuning,
+    pub reward: RewardShaping,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            agent: Side::Left,
+            opponent: Some(Difficulty::Normal),
+            ticks_per_step: 4,
+            max_ticks: None,
+            rules: MatchRules::default(),
+            tuning: GameTuning::default(),
+            reward: RewardShaping::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Move {
+    #[default]
+    Stay,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaddleAction {
+    #[serde(rename = "move")]
+    pub movement: Move,
+    pub serve: bool,
+}
+
+impl PaddleAction {
+    pub fn apply(self, actions: &mut PlayerActions) {
+        actions.set_pressed(Action::PaddleUp, self.movement == Move::Up);
+        actions.set_pressed(Action::PaddleDown, self.movement == Move::Down);
+        actions.set_pressed(Action::Serve, self.serve);
+    }
+}
+
+// A CPU controlled side ignores its action
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Actions {
+    pub left: PaddleAction,
+    pub right: PaddleAction,
+}
+
+// In arena units, with the origin in the middle and y going up. Pairs are left then right.
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Observation {
+    pub tick: u32,
+    pub ball: [f32; 2],
+    pub ball_velocity: [f32; 2],
+    pub paddles: [f32; 2],
+    pub points: [u32; 2],
+    pub games: [u32; 2],
+    pub sets: [u32; 2],
+    pub hit_streak: u32,
+}
+
+impl Observation {
+    pub fn from_frame(frame: &StateFrame) -> Self {
+        Observation {
+            tick: frame.tick,
+            ball: frame.ball.to_array(),
+            ball_velocity: frame.ball_velocity.to_array(),
+            paddles: frame.paddles,
+            points: [frame.points.left, frame.points.right],
+            games: [frame.games.left, frame.games.right],
+            sets: [frame.sets.left, frame.sets.right],
+            hit_streak: frame.hit_streak,
+        }
+    }
+
+    // Positions and velocities scaled to about -1..1, for feeding straight into a network
+    pub fn features(&self) -> [f32; 6] {
+        [
+            self.ball[0] / ARENA_HALF_WIDTH,
+            self.ball[1] / ARENA_HALF_HEIGHT,
+            self.ball_velocity[0] / VELOCITY_SCALE,
+            self.ball_velocity[1] / VELOCITY_SCALE,
+            self.paddles[0] / ARENA_HALF_HEIGHT,
+            self.paddles[1] / ARENA_HALF_HEIGHT,
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StepInfo {
+    // Points won during the step, left then right
+    pub scored: [u32; 2],
+    // Times each side sent the ball back during the step
+    pub returns: [u32; 2],
+    pub winner: Option<Side>,
+    // Ran out of ticks rather than finished
+    pub truncated: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+pub struct PongEnv {
+    config: EnvConfig,
+    app: App,
+    state: SystemState<MatchState<'static, 'static>>,
+    frame: StateFrame,
+    done: bool,
+}
+
+impl PongEnv {
+    // Ready to step straight away, as if just reset with `seed`
+    pub fn new(config: EnvConfig, seed: u64) -> Self {
+        let (app, state, frame) = start_match(&config, seed);
+        PongEnv {
+            config,
+            app,
+            state,
+            frame,
+            done: false,
+        }
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn observation(&self) -> Observation {
+        Observation::from_frame(&self.frame)
+    }
+
+    // A fresh match, played the same way every time for the same seed and actions
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        *self = PongEnv::new(self.config.clone(), seed);
+        self.observation()
+    }
+
+    // Once done, stepping again changes nothing until the next reset
+    pub fn step(&mut self, actions: Actions) -> Step {
+        let mut reward = 0.0;
+        let mut info = StepInfo::default();
+        if self.done {
+            return Step {
+                observation: self.observation(),
+                reward,
+                done: true,
+                info,
+            };
+        }
+
+        let agent = self.config.agent;
+        let shaping = self.config.reward;
+        for _ in 0..self.config.ticks_per_step {
+            {
+                let mut action_state = self.app.world_mut().resource_mut::<ActionState>();
+                actions.left.apply(&mut action_state.left);
+                actions.right.apply(&mut action_state.right);
+            }
+            self.app.update();
+            let frame = self.capture_frame(self.frame.tick + 1);
+
+            if let Some(side) = scorer(&self.frame, &frame) {
+                info.scored[side as usize] += 1;
+                reward += if side == agent {
+                    shaping.point
+                } else {
+                    -shaping.point
+                };
+            }
+            if returned(&self.frame, &frame) {
+                // Heading away from whoever hit it
+                let side = if frame.ball_velocity.x > 0.0 {
+                    Side::Left
+                } else {
+                    Side::Right
+                };
+                info.returns[side as usize] += 1;
+                if side == agent {
+                    reward += shaping.hit;
+                }
+            }
+            reward -=
+                shaping.tracking * (frame.paddle_y(agent) - frame.ball.y).abs() / ARENA_HEIGHT;
+            self.frame = frame;
+
+            if let Some(winner) = frame.winner {
+                info.winner = Some(winner);
+                reward += if winner == agent {
+                    shaping.win
+                } else {
+                    -shaping.win
+                };
+                self.done = true;
+                break;
+            }
+            if self
+                .config
+                .max_ticks
+                .is_some_and(|max_ticks| frame.tick >= max_ticks)
+            {
+                info.truncated = true;
+                self.done = true;
+                break;
+            }
+        }
+
+        Step {
+            observation: self.observation(),
+            reward,
+            done: self.done,
+            info,
+        }
+    }
+
+    fn capture_frame(&mut self, tick: u32) -> StateFrame {
+        capture_frame(&mut self.state, self.app.world_mut(), tick)
+    }
+}
+
+fn start_match(
+    config: &EnvConfig,
+    seed: u64,
+) -> (App, SystemState<MatchState<'static, 'static>>, StateFrame) {
+    let mut app = App::new();
+    let opponent = config.opponent;
+    let ai = match config.agent {
+        Side::Left => AiSettings {
+            left: None,
+            right: opponent,
+            ..default()
+        },
+        Side::Right => AiSettings {
+            left: opponent,
+            right: None,
+            ..default()
+        },
+        // Turned away when the config comes in
+        Side::Top | Side::Bottom => AiSettings::default(),
+    };
+    // One tick per update, so a step is always exactly `ticks_per_step` ticks
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(PongPlugin::headless(seed))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / DEFAULT_TICK_RATE_HZ,
+        )))
+        .insert_resource(ai)
+        .insert_resource(config.rules)
+        .insert_resource(config.tuning.clone());
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Playing);
+    app.update();
+
+    let mut state = SystemState::new(app.world_mut());
+    let frame = capture_frame(&mut state, app.world_mut(), 0);
+    (app, state, frame)
+}
+
+fn capture_frame(
+    state: &mut SystemState<MatchState<'static, 'static>>,
+    world: &mut World,
+    tick: u32,
+) -> StateFrame {
+    // Transforms are left blended for display after an update
+    world
+        .run_system_cached(restore_simulated_translation)
+        .expect("restoring translations can't fail");
+    state
+        .get(world)
+        .snapshot()
+        .expect("the match is set up on reset")
+        .frame(tick)
+}
+
+// Steps a batch of environments side by side, spread over a few threads.
+// Apps can't be moved between threads, so each thread builds and keeps its own share.
+pub struct VecEnv {
+    workers: Vec<Worker>,
+    len: usize,
+}
+
+struct Worker {
+    jobs: Sender<Job>,
+    results: Receiver<JobResult>,
+    // Index of this worker's first environment
+    first: usize,
+    len: usize,
+}
+
+enum Job {
+    // Indices are this worker's own
+    Reset(Vec<(usize, u64)>),
+    Step(Vec<Actions>),
+    Observe,
+}
+
+enum JobResult {
+    Observe(Vec<Observation>),
+    Reset(Vec<Observation>),
+    Step(Vec<Step>),
+}
+
+impl VecEnv {
+    // One environment per seed
+    pub fn new(config: &EnvConfig, seeds: &[u64], threads: usize) -> Self {
+        let threads = threads.clamp(1, seeds.len().max(1));
+        let per_thread = seeds.len().div_ceil(threads);
+        let mut workers = Vec::new();
+        for (index, chunk) in seeds.chunks(per_thread.max(1)).enumerate() {
+            let (jobs, job_receiver) = channel();
+            let (result_sender, results) = channel();
+            let config = config.clone();
+            let len = chunk.len();
+            let chunk = chunk.to_vec();
+            thread::spawn(move || run_worker(&config, &chunk, &job_receiver, &result_sender));
+            workers.push(Worker {
+                jobs,
+                results,
+                first: index * per_thread,
+                len,
+            });
+        }
+        VecEnv {
+            workers,
+            len: seeds.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Where every environment is up to, in order
+    pub fn observations(&mut self) -> Vec<Observation> {
+        for worker in &self.workers {
+            worker.send(Job::Observe);
+        }
+        let mut observations = Vec::with_capacity(self.len);
+        for worker in &self.workers {
+            let JobResult::Observe(results) = worker.receive() else {
+                unreachable!("asked for observations");
+            };
+            observations.extend(results);
+        }
+        observations
+    }
+
+    // Resets the environments at the given indices
+    pub fn reset(&mut self, seeds: &[(usize, u64)]) -> Vec<Observation> {
+        let mut asked = Vec::new();
+        for worker in &self.workers {
+            let mine: Vec<(usize, u64)> = seeds
+                .iter()
+                .filter(|(index, _)| (worker.first..worker.first + worker.len).contains(index))
+                .map(|(index, seed)| (index - worker.first, *seed))
+                .collect();
+            if !mine.is_empty() {
+                asked.push((worker, mine.clone()));
+                worker.send(Job::Reset(mine));
+            }
+        }
+
+        let mut observations = vec![Observation::default(); seeds.len()];
+        for (worker, mine) in asked {
+            let JobResult::Reset(results) = worker.receive() else {
+                unreachable!("asked for a reset");
+            };
+            for ((local, _), observation) in mine.into_iter().zip(results) {
+                let position = seeds
+                    .iter()
+                    .position(|(index, _)| *index == local + worker.first)
+                    .expect("asked for this one");
+                observations[position] = observation;
+            }
+        }
+        observations
+    }
+
+    // Resets every environment, with seeds in order
+    pub fn reset_all(&mut self, seeds: &[u64]) -> Vec<Observation> {
+        assert_eq!(seeds.len(), self.len, "one seed per environment");
+        let seeds: Vec<(usize, u64)> = seeds.iter().copied().enumerate().collect();
+        self.reset(&seeds)
+    }
+
+    // One set of actions per environment, in order
+    pub fn step(&mut self, actions: &[Actions]) -> Vec<Step> {
+        assert_eq!(
+            actions.len(),
+            self.len,
+            "one set of actions per environment"
+        );
+        for worker in &self.workers {
+            let mine = &actions[worker.first..worker.first + worker.len];
+            worker.send(Job::Step(mine.to_vec()));
+        }
+        let mut steps = Vec::with_capacity(self.len);
+        for worker in &self.workers {
+            let JobResult::Step(results) = worker.receive() else {
+                unreachable!("asked for a step");
+            };
+            steps.extend(results);
+        }
+        steps
+    }
+}
+
+impl Worker {
+    fn send(&self, job: Job) {
+        self.jobs.send(job).expect("gym worker stopped");
+    }
+
+    fn receive(&self) -> JobResult {
+        self.results.recv().expect("gym worker stopped")
+    }
+}
+
+// Runs until the VecEnv is dropped
+fn run_worker(
+    config: &EnvConfig,
+    seeds: &[u64],
+    jobs: &Receiver<Job>,
+    results: &Sender<JobResult>,
+) {
+    let mut envs: Vec<PongEnv> = seeds
+        .iter()
+        .map(|seed| PongEnv::new(config.clone(), *seed))
+        .collect();
+    while let Ok(job) = jobs.recv() {
+        let result = match job {
+            Job::Reset(seeds) => JobResult::Reset(
+                seeds
+                    .into_iter()
+                    .map(|(index, seed)| envs[index].reset(seed))
+                    .collect(),
+            ),
+            Job::Observe => JobResult::Observe(envs.iter().map(PongEnv::observation).collect()),
+            Job::Step(actions) => JobResult::Step(
+                envs.iter_mut()
+                    .zip(actions)
+                    .map(|(env, actions)| env.step(actions))
+                    .collect(),
+            ),
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+// One JSON object per line each way. Always a `reset` first, which starts one environment per seed:
+//   {"reset": {"seeds": [1, 2], "config": {"opponent": "hard", "reward": {"hit": 0.1}}}}
+//   {"step": {"actions": [{"left": {"move": "up"}}, {"left": {"move": "down", "serve": true}}]}}
+// `"envs": [1]` in a reset restarts just those environments, keeping the rest going.
+// Every request gets one reply line, `{"reset": {"observations": [...]}}`,
+// `{"step": {"steps": [...]}}` or `{"error": {"message": "..."}}`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    Reset {
+        seeds: Vec<u64>,
+        #[serde(default)]
+        envs: Option<Vec<usize>>,
+        #[serde(default)]
+        config: Option<EnvConfig>,
+    },
+    Step {
+        actions: Vec<Actions>,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Reset { observations: Vec<Observation> },
+    Step { steps: Vec<Step> },
+    Error { message: String },
+}
+
+// Answers requests until the input runs out
+pub fn serve_json_lines(
+    input: impl BufRead,
+    mut output: impl Write,
+    threads: usize,
+) -> std::io::Result<()> {
+    let mut config = EnvConfig::default();
+    let mut envs: Option<VecEnv> = None;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle_request(request, &mut config, &mut envs, threads),
+            Err(error) => Err(format!("Couldn't read the request: {error}")),
+        }
+        .unwrap_or_else(|message| Response::Error { message });
+        serde_json::to_writer(&mut output, &response)?;
+        output.write_all(b"\n")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn handle_request(
+    request: Request,
+    config: &mut EnvConfig,
+    envs: &mut Option<VecEnv>,
+    threads: usize,
+) -> Result<Response, String> {
+    match request {
+        Request::Reset {
+            seeds,
+            envs: Some(indices),
+            config: new_config,
+        } => {
+            let envs = envs
+                .as_mut()
+                .ok_or("Nothing to restart yet, reset without envs first")?;
+            if new_config.is_some() {
+                return Err("The config can only change when resetting every environment".into());
+            }
+            if indices.len() != seeds.len() {
+                return Err(format!(
+                    "{} envs but {} seeds, there should be one seed each",
+                    indices.len(),
+                    seeds.len()
+                ));
+            }
+            if let Some(index) = indices.iter().find(|index| **index >= envs.len()) {
+                return Err(format!("There's no env {index}, only {}", envs.len()));
+            }
+            let seeds: Vec<(usize, u64)> = indices.into_iter().zip(seeds).collect();
+            Ok(Response::Reset {
+                observations: envs.reset(&seeds),
+            })
+        }
+        Request::Reset {
+            seeds,
+            envs: None,
+            config: new_config,
+        } => {
+            if seeds.is_empty() {
+                return Err("Reset needs at least one seed".into());
+            }
+            if let Some(new_config) = new_config {
+                if new_config.agent.is_horizontal()
+                    || new_config.rules.four_players
+                    || new_config.rules.doubles
+                {
+                    return Err(
+                        "Environments are one against one, the agent plays left or right".into(),
+                    );
+                }
+                // Observations only have room for the one ball, and leave out power-ups
+                if new_config.rules.balls != 1
+                    || new_config.rules.extra_ball_every.is_some()
+                    || new_config.rules.power_ups
+                {
+                    return Err(
+                        "Environments play with one ball and no power-ups, those rules can't be observed"
+                            .into(),
+                    );
+                }
+                *config = new_config;
+            }
+            let started = envs.insert(VecEnv::new(config, &seeds, threads));
+            Ok(Response::Reset {
+                observations: started.observations(),
+            })
+        }
+        Request::Step { actions } => {
+            let envs = envs.as_mut().ok_or("Reset before stepping")?;
+            if actions.len() != envs.len() {
+                return Err(format!(
+                    "{} actions for {} envs, there should be one each",
+                    actions.len(),
+                    envs.len()
+                ));
+            }
+            Ok(Response::Step {
+                steps: envs.step(&actions),
+            })
+        }
+    }
+}
+
+// `--gym` serves environments over stdin and stdout instead of opening a window.
+// `--gym-threads <n>` sets how many threads batches are spread over, one per core by default.
+// Returns the number of threads if the gym was asked for.
+pub fn gym_threads_from_args(mut args: impl Iterator<Item = String>) -> Option<usize> {
+    let mut gym = false;
+    let mut threads = thread::available_parallelism().map_or(1, |cores| cores.get());
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gym" => gym = true,
+            "--gym-threads" => match args.next().and_then(|value| value.parse::<usize>().ok()) {
+                Some(value) if value > 0 => threads = value,
+                _ => eprintln!("--gym-threads expects a positive number of threads"),
+            },
+            _ => {}
+        }
+    }
+    gym.then_some(threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chasing_ball(observation: &Observation, side: Side) -> PaddleAction {
+        let paddle = observation.paddles[side as usize];
+        let movement = if observation.ball[1] > paddle + 10.0 {
+            Move::Up
+        } else if observation.ball[1] < paddle - 10.0 {
+            Move::Down
+        } else {
+            Move::Stay
+        };
+        PaddleAction {
+            movement,
+            serve: true,
+        }
+    }
+
+    #[test]
+    fn an_idle_agent_loses_every_point() {
+        let config = EnvConfig {
+            rules: MatchRules {
+                points_to_win: 2,
+                ..default()
+            },
+            opponent: Some(Difficulty::Hard),
+            reward: RewardShaping {
+                win: 5.0,
+                ..default()
+            },
+            ..default()
+        };
+        let mut env = PongEnv::new(config, 7);
+        let mut total = 0.0;
+        let mut steps = 0;
+        loop {
+            let step = env.step(Actions::default());
+            total += step.reward;
+            steps += 1;
+            if step.done {
+                assert_eq!(step.info.winner, Some(Side::Right));
+                assert_eq!(step.observation.points, [0, 2]);
+                break;
+            }
+            assert!(steps < 10_000, "the match never ended");
+        }
+        assert_eq!(total, -2.0 - 5.0);
+        // Stays finished
+        let after = env.step(Actions::default());
+        assert!(after.done);
+        assert_eq!(after.reward, 0.0);
+    }
+
+    #[test]
+    fn returns_are_rewarded_and_truncation_stops_the_episode() {
+        let config = EnvConfig {
+            opponent: Some(Difficulty::Impossible),
+            max_ticks: Some(120 * 20),
+            ticks_per_step: 2,
+            reward: RewardShaping {
+                point: 0.0,
+                hit: 1.0,
+                ..default()
+            },
+            ..default()
+        };
+        let mut env = PongEnv::new(config, 3);
+        let mut observation = env.observation();
+        let mut total = 0.0;
+        let mut returns = 0;
+        loop {
+            let step = env.step(Actions {
+                left: chasing_ball(&observation, Side::Left),
+                ..default()
+            });
+            observation = step.observation;
+            total += step.reward;
+            returns += step.info.returns[0];
+            if step.done {
+                assert!(step.info.truncated);
+                assert_eq!(observation.tick, 120 * 20);
+                break;
+            }
+        }
+        assert!(returns > 0);
+        assert_eq!(total, returns as f32);
+    }
+
+    #[test]
+    fn batches_match_single_environments() {
+        let config = EnvConfig {
+            opponent: None,
+            ..default()
+        };
+        let seeds = [1, 2, 3, 4, 5];
+        let mut batch = VecEnv::new(&config, &seeds, 2);
+        let mut singles: Vec<PongEnv> = seeds
+            .iter()
+            .map(|seed| PongEnv::new(config.clone(), *seed))
+            .collect();
+        let mut observations: Vec<Observation> = singles.iter().map(PongEnv::observation).collect();
+        assert_eq!(batch.observations(), observations);
+        assert_eq!(batch.reset_all(&seeds), observations);
+
+        for _ in 0..300 {
+            let actions: Vec<Actions> = observations
+                .iter()
+                .map(|observation| Actions {
+                    left: chasing_ball(observation, Side::Left),
+                    right: chasing_ball(observation, Side::Right),
+                })
+                .collect();
+            let steps = batch.step(&actions);
+            let expected: Vec<Step> = singles
+                .iter_mut()
+                .zip(&actions)
+                .map(|(env, actions)| env.step(*actions))
+                .collect();
+            assert_eq!(steps, expected);
+            observations = steps.iter().map(|step| step.observation).collect();
+        }
+
+        // Restarting one leaves the others alone
+        let restarted = batch.reset(&[(3, 9)]);
+        assert_eq!(
+            restarted,
+            vec![PongEnv::new(config.clone(), 9).observation()]
+        );
+    }
+
+    #[test]
+    fn json_lines_protocol() {
+        let input = [
+            r#"{"reset": {"seeds": [4, 5], "config": {"opponent": "easy", "ticks_per_step": 1}}}"#,
+            r#"{"step": {"actions": [{"left": {"move": "up"}}, {}]}}"#,
+            r#"{"step": {"actions": [{}]}}"#,
+            r#"{"reset": {"seeds": [6], "envs": [1]}}"#,
+            "not json",
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_json_lines(input.as_bytes(), &mut output, 2).unwrap();
+        let replies: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 5);
+
+        let observations = replies[0]["reset"]["observations"].as_array().unwrap();
+        assert_eq!(observations.len(), 2);
+        let steps = replies[1]["step"]["steps"].as_array().unwrap();
+        assert_eq!(steps[0]["observation"]["tick"], 1);
+        let moved = steps[0]["observation"]["paddles"][0].as_f64().unwrap();
+        let stayed = steps[1]["observation"]["paddles"][0].as_f64().unwrap();
+        assert!(moved > stayed);
+        assert!(replies[2]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("1 actions for 2 envs"));
+        assert_eq!(replies[3]["reset"]["observations"][0]["tick"], 0);
+        assert!(replies[4]["error"].is_object());
+    }
+
+    #[test]
+    fn rules_the_observations_miss_are_turned_away() {
+        let input = [
+            r#"{"reset": {"seeds": [1], "config": {"rules": {"balls": 2}}}}"#,
+            r#"{"reset": {"seeds": [1], "config": {"rules": {"extra_ball_every": 3}}}}"#,
+            r#"{"reset": {"seeds": [1], "config": {"rules": {"power_ups": true}}}}"#,
+            r#"{"reset": {"seeds": [1], "config": {"rules": {"points_to_win": 3}}}}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_json_lines(input.as_bytes(), &mut output, 1).unwrap();
+        let replies: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        for reply in &replies[..3] {
+            assert!(reply["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("one ball and no power-ups"));
+        }
+        assert!(replies[3]["reset"].is_object());
+    }
+}
@@ -0,0 +1,370 @@
+// The whole game as a plugin. The binary adds it to a window, while tests and tools can run it
+// headless under MinimalPlugins, see PongPlugin::headless.
+use bevy::{prelude::*, state::app::StatesPlugin};
+use serde::{Deserialize, Serialize};
+
+pub mod ai;
+pub mod arena;
+#[cfg(not(target_family = "wasm"))]
+pub mod bots;
+pub mod collision;
+pub mod controls;
+pub mod effects_system;
+pub mod gamepad;
+// Threads and stdin don't exist on the web
+#[cfg(not(target_family = "wasm"))]
+pub mod gym;
+pub mod menu;
+pub mod netplay;
+pub mod pause;
+pub mod persist;
+pub mod powerups;
+pub mod replay;
+pub mod rng;
+pub mod rules;
+pub mod setup;
+pub mod simulation;
+pub mod snapshot;
+pub mod sound;
+pub mod spectate;
+pub mod spin;
+pub mod states;
+pub mod systems;
+pub mod transport;
+pub mod tuning;
+
+use ai::{attach_ai_controllers, AiPlugin};
+use arena::ArenaPlugin;
+use controls::{ActionState, ControlsPlugin};
+use effects_system::EffectsPlugin;
+use gamepad::GamepadPlugin;
+use menu::{MenuPlugin, RestartRequested};
+use netplay::{netplaying, NetplayPlugin};
+use pause::PausePlugin;
+use powerups::PowerUpPlugin;
+use replay::{replaying, ReplayPlugin};
+use rng::{advance_match_seed, log_match_seed, seed_from_launch_options, RngPlugin};
+use rules::RulesPlugin;
+use setup::{set_window_icon, setup_camera, setup_game};
+use simulation::{
+    tick_rate_from_args, GameplaySet, PlayerInputSet, SimulationPlugin, DEFAULT_TICK_RATE_HZ,
+};
+use sound::{PlaySound, SoundPlugin};
+use spectate::{spectating, SpectatePlugin};
+use spin::{show_spin, track_paddle_velocity};
+use states::{AppState, InGame};
+use systems::{
+    add_chaos_balls, check_new_goal, game_over, move_ball, move_paddles_with_actions,
+    move_paddles_with_touch, read_touch_input, restart_game, serve_ball, show_knocked_out_sides,
+    GoalScored, Rally, TouchTargets,
+};
+use tuning::{GameTuning, TuningPlugin};
+
+#[derive(Component)]
+#[require(PaddleScale, PaddleVelocity)]
+pub struct Paddle {
+    pub side: Side,
+    pub role: Role,
+}
+
+impl Paddle {
+    // Whose controls, CPU setting, gamepad and replay input move this paddle
+    pub fn player(&self) -> Side {
+        match self.role {
+            Role::Back => self.side,
+            Role::Forward => self.side.teammate(),
+        }
+    }
+}
+
+// Which of a side's paddles. Every side has a back one, and doubles adds a forward one
+// further into the arena, see MatchRules::doubles.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Role {
+    #[default]
+    Back,
+    Forward,
+}
+
+impl Role {
+    pub const ALL: [Role; 2] = [Role::Back, Role::Forward];
+}
+
+// Paddle length as a multiple of the tuned one. Power-ups grow and shrink it,
+// and collisions and keeping the paddle in the arena go by it as well as the sprite.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct PaddleScale(pub f32);
+
+impl Default for PaddleScale {
+    fn default() -> Self {
+        PaddleScale(1.0)
+    }
+}
+
+impl PaddleScale {
+    pub fn half_length(self, tuning: &GameTuning) -> f32 {
+        tuning.paddle_half_size().y * self.0
+    }
+
+    // In the world, so lying flat for the top and bottom paddles
+    pub fn half_size(self, side: Side, tuning: &GameTuning) -> Vec2 {
+        side.to_paddle_space(Vec2::new(
+            tuning.paddle_half_size().x,
+            self.half_length(tuning),
+        ))
+    }
+
+    pub fn size(self, side: Side, tuning: &GameTuning) -> Vec2 {
+        self.half_size(side, tuning) * 2.0
+    }
+}
+
+// How fast the paddle moved over the last tick, in world units per second.
+// Whatever moved it, so a ball can pick up spin from it, see spin.rs.
+#[derive(Component, Clone, Copy, Default, PartialEq, Debug)]
+pub struct PaddleVelocity(pub Vec2);
+
+// Top and bottom only play in four player matches, see MatchRules::four_players
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+        }
+    }
+
+    // Who plays the forward paddle on this side in doubles. It's the player who'd otherwise
+    // be on the top or bottom, so they keep their keys, CPU setting and gamepad.
+    pub fn teammate(self) -> Side {
+        match self {
+            Side::Left => Side::Top,
+            Side::Top => Side::Left,
+            Side::Right => Side::Bottom,
+            Side::Bottom => Side::Right,
+        }
+    }
+
+    // Top and bottom paddles lie flat and move left and right
+    pub fn is_horizontal(self) -> bool {
+        matches!(self, Side::Top | Side::Bottom)
+    }
+
+    // Points from this side's wall into the arena
+    pub fn normal(self) -> Vec2 {
+        match self {
+            Side::Left => Vec2::X,
+            Side::Right => Vec2::NEG_X,
+            Side::Top => Vec2::NEG_Y,
+            Side::Bottom => Vec2::Y,
+        }
+    }
+
+    // The way this side's paddle moves when going "up"
+    pub fn along(self) -> Vec2 {
+        if self.is_horizontal() {
+            Vec2::X
+        } else {
+            Vec2::Y
+        }
+    }
+
+    // Swaps x and y for the top and bottom, so their paddles can be handled as if they stood
+    // upright like the left and right ones. Swapping back is the same thing.
+    pub fn to_paddle_space(self, v: Vec2) -> Vec2 {
+        if self.is_horizontal() {
+            Vec2::new(v.y, v.x)
+        } else {
+            v
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Ball;
+
+// Balls are numbered as they come into play, so every copy of a match lists them in the same order
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct BallNumber(pub u32);
+
+#[derive(Component, Default)]
+pub struct HitStreak {
+    pub count: u32,
+}
+
+// How fast the ball turns, in radians per second, anticlockwise above 0. See spin.rs.
+#[derive(Component, Clone, Copy, Default, PartialEq, Debug)]
+pub struct Spin(pub f32);
+
+// Whichever paddle touched the ball last, if any has since it was served
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct LastTouch(pub Option<Side>);
+
+#[derive(Component)]
+pub struct Particle {
+    // Ensure this is public if accessed from particle_system.rs
+    pub lifetime: Timer,
+}
+
+#[derive(Component)]
+pub struct ScoreCelebration {
+    pub timer: Timer,
+    pub original_color: Color,
+    // We might not need original_font_size if we just flash color
+    // pub original_font_size: f32,
+    pub scored_side: Side, // To know which score text this belongs to
+}
+
+#[derive(Component)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Score {
+    pub value: u32,
+    pub side: Side,
+}
+
+#[derive(Component)]
+pub struct Border;
+
+// Where a side's goal was, once it's out of a four player match
+#[derive(Component)]
+pub struct KnockedOutWall {
+    pub side: Side,
+}
+
+pub struct PongPlugin {
+    pub tick_rate_hz: f64,
+    // A random one if not given
+    pub seed: Option<u64>,
+    // Just the match itself: no window, menus, sound, saved settings or networking.
+    // The arena is the same fixed size as always, and paddles are moved through ActionState.
+    pub headless: bool,
+}
+
+impl PongPlugin {
+    // Tick rate and seed from the command line, or the page URL on the web
+    pub fn from_launch_options() -> Self {
+        PongPlugin {
+            tick_rate_hz: tick_rate_from_args(std::env::args().skip(1)),
+            seed: seed_from_launch_options(),
+            headless: false,
+        }
+    }
+
+    pub fn headless(seed: u64) -> Self {
+        PongPlugin {
+            tick_rate_hz: DEFAULT_TICK_RATE_HZ,
+            seed: Some(seed),
+            headless: true,
+        }
+    }
+}
+
+impl Plugin for PongPlugin {
+    fn build(&self, app: &mut App) {
+        // MinimalPlugins leaves states out
+        if !app.is_plugin_added::<StatesPlugin>() {
+            app.add_plugins(StatesPlugin);
+        }
+        app.init_state::<AppState>()
+            .add_computed_state::<InGame>()
+            .enable_state_scoped_entities::<InGame>()
+            .add_plugins(SimulationPlugin {
+                tick_rate_hz: self.tick_rate_hz,
+            })
+            .add_plugins(RngPlugin { seed: self.seed })
+            .init_resource::<TouchTargets>()
+            .init_resource::<Rally>()
+            .add_event::<GoalScored>()
+            .add_plugins(EffectsPlugin) // Changed from ParticlePlugin
+            .add_plugins(RulesPlugin)
+            .add_plugins(AiPlugin)
+            .add_plugins(PowerUpPlugin)
+            .add_systems(OnEnter(InGame), setup_game)
+            .add_systems(OnExit(InGame), advance_match_seed)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (move_paddles_with_actions, move_paddles_with_touch)
+                        .after(attach_ai_controllers)
+                        .in_set(PlayerInputSet),
+                    (
+                        track_paddle_velocity,
+                        serve_ball,
+                        move_ball,
+                        add_chaos_balls,
+                    )
+                        .chain()
+                        .in_set(GameplaySet::Physics),
+                    // Over the network the match only ends once both ends agree on the winner
+                    (check_new_goal, game_over.run_if(not(netplaying)))
+                        .chain()
+                        .in_set(GameplaySet::Scoring),
+                ),
+            );
+
+        if self.headless {
+            // What the left out plugins would otherwise provide. Tuning stays at its defaults
+            // unless a different GameTuning is inserted.
+            app.init_resource::<GameTuning>()
+                .init_resource::<ActionState>()
+                .add_event::<PlaySound>()
+                .add_event::<RestartRequested>()
+                // Replays, network matches and spectating don't run headless
+                .add_systems(Update, restart_game.run_if(in_state(InGame)));
+            return;
+        }
+
+        app.add_plugins(ArenaPlugin)
+            .add_plugins(TuningPlugin)
+            .add_plugins(GamepadPlugin)
+            .add_plugins(ControlsPlugin)
+            .add_plugins(SoundPlugin)
+            .add_plugins(MenuPlugin)
+            .add_plugins(PausePlugin)
+            .add_plugins(ReplayPlugin)
+            .add_plugins(NetplayPlugin)
+            .add_plugins(SpectatePlugin)
+            .add_systems(Startup, (set_window_icon, setup_camera))
+            // Headless runs keep stdout to themselves
+            .add_systems(OnEnter(InGame), log_match_seed)
+            // A replay restarts by going back to its start instead, and a network match can't be
+            // restarted. Restarting stays in the match, so the new seed is logged here.
+            .add_systems(
+                Update,
+                (restart_game, log_match_seed).chain().run_if(
+                    on_event::<RestartRequested>
+                        .and(in_state(InGame))
+                        .and(not(replaying))
+                        .and(not(netplaying))
+                        .and(not(spectating)),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    read_touch_input,
+                    (show_spin, show_knocked_out_sides).run_if(in_state(InGame)),
+                ),
+            );
+
+        #[cfg(not(target_family = "wasm"))]
+        app.add_plugins(bots::BotPlugin);
+    }
+}
@@ -0,0 +1,489 @@
+use crate::ai::{AiSettings, Difficulty};
+use crate::controls::{Action, ActionState, InputBindings};
+use crate::netplay::{NetSession, NetSettings};
+use crate::pause::ResumeRequested;
+use crate::rules::{MatchProgress, MatchRules, RulesPreset};
+use crate::sound::AudioSettings;
+use crate::spectate::Watching;
+use crate::states::AppState;
+use crate::Side;
+use bevy::{
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+
+const TITLE_COLOR: Color = Color::srgb(0.0, 1.0, 1.0); // Neon Cyan
+const BUTTON_COLOR: Color = Color::srgb(0.2, 0.0, 0.45);
+const BUTTON_HOVERED_COLOR: Color = Color::srgb(0.35, 0.0, 0.6);
+const BUTTON_PRESSED_COLOR: Color = Color::srgb(1.0, 0.0, 1.0); // Neon Magenta
+const BUTTON_BORDER_COLOR: Color = Color::srgb(0.0, 1.0, 1.0); // Neon Cyan
+pub const OVERLAY_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    OnePlayer,
+    TwoPlayers,
+    CpuVsCpu,
+    // Everyone on a paddle, switching to the four player rules unless it's doubles
+    FourPlayers,
+    Difficulty,
+    Rules,
+    Volume,
+    SfxVolume,
+    Controls,
+    ResetControls,
+    Replays,
+    // Hosting or joining as set up with `--host` / `--join`
+    Online,
+    Resume,
+    Restart,
+    MainMenu,
+}
+
+#[derive(Component)]
+pub struct DifficultyLabel;
+
+#[derive(Component)]
+pub struct RulesLabel;
+
+#[derive(Component)]
+pub struct VolumeLabel;
+
+#[derive(Component)]
+pub struct SfxVolumeLabel;
+
+// Difficulty used for any CPU paddles picked from the menu
+#[derive(Resource)]
+pub struct SelectedDifficulty(pub Difficulty);
+
+impl FromWorld for SelectedDifficulty {
+    // Start from whatever was asked for on the command line
+    fn from_world(world: &mut World) -> Self {
+        let settings = world
+            .get_resource::<AiSettings>()
+            .copied()
+            .unwrap_or_default();
+        SelectedDifficulty(
+            settings
+                .right
+                .or(settings.left)
+                .unwrap_or(Difficulty::Normal),
+        )
+    }
+}
+
+// Sent by the menus when the current match should be thrown away and started again
+#[derive(Event)]
+pub struct RestartRequested;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedDifficulty>()
+            .add_event::<RestartRequested>()
+            .add_systems(OnEnter(AppState::MainMenu), spawn_main_menu)
+            .add_systems(OnEnter(AppState::GameOver), spawn_game_over_screen)
+            .add_systems(
+                Update,
+                (
+                    color_menu_buttons,
+                    handle_menu_input,
+                    update_difficulty_label,
+                    update_rules_label,
+                    update_volume_label,
+                )
+                    .chain(),
+            );
+    }
+}
+
+// Shared look of every menu button. The label goes in as a child.
+pub fn button_bundle(width: f32) -> (Button, Node, BackgroundColor, BorderColor) {
+    (
+        Button,
+        Node {
+            width: Val::Px(width),
+            padding: UiRect::all(Val::Px(10.0)),
+            margin: UiRect::all(Val::Px(8.0)),
+            border: UiRect::all(Val::Px(2.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+        BorderColor(BUTTON_BORDER_COLOR),
+    )
+}
+
+pub fn spawn_button(parent: &mut ChildSpawnerCommands, label: &str, button: MenuButton) {
+    parent
+        .spawn((button, button_bundle(320.0)))
+        .with_children(|button_parent| {
+            let mut text = button_parent.spawn((
+                Text::new(label),
+                TextFont {
+                    font_size: 30.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            match button {
+                MenuButton::Difficulty => {
+                    text.insert(DifficultyLabel);
+                }
+                MenuButton::Rules => {
+                    text.insert(RulesLabel);
+                }
+                MenuButton::Volume => {
+                    text.insert(VolumeLabel);
+                }
+                MenuButton::SfxVolume => {
+                    text.insert(SfxVolumeLabel);
+                }
+                _ => {}
+            }
+        });
+}
+
+// Full screen column that centers everything in it
+pub fn menu_root(background: Color) -> (Node, BackgroundColor) {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(background),
+    )
+}
+
+pub fn hint_text(text: &str) -> (Text, TextFont, TextColor, Node) {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
+        Node {
+            margin: UiRect::top(Val::Px(20.0)),
+            ..default()
+        },
+    )
+}
+
+pub fn spawn_main_menu(
+    mut commands: Commands,
+    selected: Res<SelectedDifficulty>,
+    rules: Res<MatchRules>,
+    audio: Res<AudioSettings>,
+    net: Res<NetSettings>,
+) {
+    commands
+        .spawn((StateScoped(AppState::MainMenu), menu_root(Color::NONE)))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("PONG"),
+                TextFont {
+                    font_size: 100.0,
+                    ..default()
+                },
+                TextColor(TITLE_COLOR),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+            spawn_button(parent, "1 Player", MenuButton::OnePlayer);
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, "2 Players", MenuButton::TwoPlayers);
+                    spawn_button(row, "4 Players", MenuButton::FourPlayers);
+                });
+            spawn_button(parent, "CPU vs CPU", MenuButton::CpuVsCpu);
+            // Back for another go after a network match
+            if let Some(label) = net.label() {
+                spawn_button(parent, &label, MenuButton::Online);
+            }
+            spawn_button(
+                parent,
+                &difficulty_label(selected.0),
+                MenuButton::Difficulty,
+            );
+            spawn_button(parent, &rules.label(), MenuButton::Rules);
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, &audio.label(), MenuButton::Volume);
+                    spawn_button(row, &audio.sfx_label(), MenuButton::SfxVolume);
+                });
+            // Side by side so the menu still fits on a landscape phone
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_button(row, "Controls", MenuButton::Controls);
+                    spawn_button(row, "Replays", MenuButton::Replays);
+                });
+            parent.spawn(hint_text(
+                "1 / 2 / 3 / 4 to start, D to change difficulty, R for rules, V / E for volume, C for controls, L for replays",
+            ));
+        });
+}
+
+pub fn spawn_game_over_screen(
+    mut commands: Commands,
+    rules: Res<MatchRules>,
+    progress: Res<MatchProgress>,
+    net_session: Option<Res<NetSession>>,
+    watching: Option<Res<Watching>>,
+) {
+    let winner = progress.winner.unwrap_or(Side::Left);
+
+    commands
+        .spawn((StateScoped(AppState::GameOver), menu_root(OVERLAY_COLOR)))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(winner_text(&rules, winner)),
+                TextFont {
+                    font_size: 60.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                Text::new(progress.summary(&rules)),
+                TextFont {
+                    font_size: 30.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                },
+            ));
+            // A rematch over the network means hosting or joining again
+            if net_session.is_some() || watching.is_some() {
+                spawn_button(parent, "Main Menu", MenuButton::MainMenu);
+                parent.spawn(hint_text("M for the menu"));
+            } else {
+                spawn_button(parent, "Restart", MenuButton::Restart);
+                spawn_button(parent, "Main Menu", MenuButton::MainMenu);
+                parent.spawn(hint_text("R to restart, M for the menu"));
+            }
+        });
+}
+
+pub fn player_number(side: Side) -> u8 {
+    match side {
+        Side::Left => 1,
+        Side::Right => 2,
+        Side::Top => 3,
+        Side::Bottom => 4,
+    }
+}
+
+// The winning side's players, both of them in doubles
+fn winner_text(rules: &MatchRules, winner: Side) -> String {
+    if rules.roles().len() > 1 {
+        format!(
+            "P{} + P{} win!",
+            player_number(winner),
+            player_number(winner.teammate())
+        )
+    } else {
+        format!("P{} wins!", player_number(winner))
+    }
+}
+
+fn everyone_cpu(difficulty: Difficulty) -> AiSettings {
+    AiSettings {
+        left: Some(difficulty),
+        right: Some(difficulty),
+        top: Some(difficulty),
+        bottom: Some(difficulty),
+    }
+}
+
+fn difficulty_label(difficulty: Difficulty) -> String {
+    format!("Difficulty: {}", difficulty.name())
+}
+
+#[allow(clippy::type_complexity)]
+pub fn color_menu_buttons(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, mut background) in buttons.iter_mut() {
+        background.0 = match interaction {
+            Interaction::Pressed => BUTTON_PRESSED_COLOR,
+            Interaction::Hovered => BUTTON_HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        };
+    }
+}
+
+// Buttons and their keyboard shortcuts both end up here
+#[allow(clippy::too_many_arguments)]
+pub fn handle_menu_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    state: Res<State<AppState>>,
+    mut selected: ResMut<SelectedDifficulty>,
+    mut ai_settings: ResMut<AiSettings>,
+    mut bindings: ResMut<InputBindings>,
+    mut rules: ResMut<MatchRules>,
+    mut audio: ResMut<AudioSettings>,
+    net: Res<NetSettings>,
+    watching: Option<Res<Watching>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut restart: EventWriter<RestartRequested>,
+    mut resume: EventWriter<ResumeRequested>,
+) {
+    let shortcuts: &[(KeyCode, MenuButton)] = match state.get() {
+        AppState::MainMenu => &[
+            (KeyCode::Digit1, MenuButton::OnePlayer),
+            (KeyCode::Digit2, MenuButton::TwoPlayers),
+            (KeyCode::Digit3, MenuButton::CpuVsCpu),
+            (KeyCode::Digit4, MenuButton::FourPlayers),
+            (KeyCode::KeyD, MenuButton::Difficulty),
+            (KeyCode::KeyR, MenuButton::Rules),
+            (KeyCode::KeyV, MenuButton::Volume),
+            (KeyCode::KeyE, MenuButton::SfxVolume),
+            (KeyCode::KeyC, MenuButton::Controls),
+            (KeyCode::KeyL, MenuButton::Replays),
+            (KeyCode::KeyN, MenuButton::Online),
+        ],
+        AppState::Paused | AppState::GameOver | AppState::Replays | AppState::Connecting => {
+            &[(KeyCode::KeyM, MenuButton::MainMenu)]
+        }
+        // Spectators can't pause, so this is the way out
+        AppState::Playing if watching.is_some() => &[(KeyCode::KeyM, MenuButton::MainMenu)],
+        _ => &[],
+    };
+    // Restart is a rebindable action rather than a fixed shortcut
+    let restart_pressed = matches!(state.get(), AppState::Paused | AppState::GameOver)
+        && actions.any_just_pressed(Action::Restart);
+
+    let pressed = buttons
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| *button)
+        .chain(
+            shortcuts
+                .iter()
+                .filter(|(key, _)| keyboard_input.just_pressed(*key))
+                .map(|(_, button)| *button),
+        )
+        .chain(restart_pressed.then_some(MenuButton::Restart));
+
+    for button in pressed {
+        match button {
+            // Under the four player rules, the top and bottom are the CPU's unless everyone's playing.
+            // The same goes for the forward paddles in doubles.
+            MenuButton::OnePlayer => {
+                *ai_settings = AiSettings {
+                    left: None,
+                    ..everyone_cpu(selected.0)
+                };
+                next_state.set(AppState::Playing);
+            }
+            MenuButton::TwoPlayers => {
+                *ai_settings = AiSettings {
+                    left: None,
+                    right: None,
+                    ..everyone_cpu(selected.0)
+                };
+                next_state.set(AppState::Playing);
+            }
+            MenuButton::CpuVsCpu => {
+                *ai_settings = everyone_cpu(selected.0);
+                next_state.set(AppState::Playing);
+            }
+            MenuButton::FourPlayers => {
+                *ai_settings = AiSettings::default();
+                if !rules.four_players && !rules.doubles {
+                    *rules = RulesPreset::FourPlayers.rules();
+                }
+                next_state.set(AppState::Playing);
+            }
+            MenuButton::Difficulty => selected.0 = selected.0.next(),
+            MenuButton::Rules => rules.next_preset(),
+            MenuButton::Volume => audio.step_master_volume(),
+            MenuButton::SfxVolume => audio.step_sfx_volume(),
+            MenuButton::Controls => next_state.set(AppState::Controls),
+            MenuButton::ResetControls => *bindings = InputBindings::default(),
+            MenuButton::Replays => next_state.set(AppState::Replays),
+            MenuButton::Online => {
+                if net.role.is_some() {
+                    next_state.set(AppState::Connecting);
+                }
+            }
+            MenuButton::Resume => {
+                resume.write(ResumeRequested);
+            }
+            MenuButton::Restart => {
+                restart.write(RestartRequested);
+            }
+            MenuButton::MainMenu => next_state.set(AppState::MainMenu),
+        }
+    }
+}
+
+pub fn update_difficulty_label(
+    selected: Res<SelectedDifficulty>,
+    mut labels: Query<&mut Text, With<DifficultyLabel>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    for mut text in labels.iter_mut() {
+        text.0 = difficulty_label(selected.0);
+    }
+}
+
+pub fn update_rules_label(rules: Res<MatchRules>, mut labels: Query<&mut Text, With<RulesLabel>>) {
+    if !rules.is_changed() {
+        return;
+    }
+    for mut text in labels.iter_mut() {
+        text.0 = rules.label();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_volume_label(
+    audio: Res<AudioSettings>,
+    mut labels: ParamSet<(
+        Query<&mut Text, With<VolumeLabel>>,
+        Query<&mut Text, With<SfxVolumeLabel>>,
+    )>,
+) {
+    if !audio.is_changed() {
+        return;
+    }
+    for mut text in labels.p0().iter_mut() {
+        text.0 = audio.label();
+    }
+    for mut text in labels.p1().iter_mut() {
+        text.0 = audio.sfx_label();
+    }
+}
@@ -0,0 +1,1303 @@
+use crate::ai::{attach_ai_controllers, AiSettings};
+use crate::controls::{Action, ActionState, PlayerActions};
+use crate::menu::{hint_text, menu_root, player_number, spawn_button, MenuButton};
+use crate::powerups::ControlsReversed;
+use crate::rng::{advance_match_seed, GameRng};
+use crate::rules::{MatchProgress, MatchRules};
+use crate::simulation::{restore_simulated_translation, run_tick_now, GameplaySet, PlayerInputSet};
+use crate::snapshot::{restore_snapshot, MatchState, Snapshot};
+use crate::sound::PlaySound;
+use crate::states::{AppState, InGame};
+use crate::systems::step_paddle;
+use crate::transport::{room_url, PeerRole, Transport, UdpTransport, WebSocketTransport};
+use crate::tuning::GameTuning;
+use crate::{Paddle, PaddleScale, Particle, Side};
+use bevy::{
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io;
+use std::time::Duration;
+
+// Starts every packet, spectators' included
+pub const MAGIC: &[u8; 7] = b"PONGNET";
+// Both peers have to run the same version, anything else is turned away.
+// 2 put spin on the ball, which plays out differently from the same inputs.
+pub const NET_VERSION: u16 = 2;
+pub const DEFAULT_INPUT_DELAY_TICKS: usize = 2;
+// How far a peer may run ahead of the other player's inputs, guessing them, before it waits
+const MAX_PREDICTION_TICKS: usize = 20;
+// Every packet carries all the inputs the other peer hasn't got yet, up to this many,
+// so a lost packet is made up for by the next one
+const MAX_INPUTS_PER_PACKET: usize = 64;
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Confirmed checksums kept for comparing with the other peer's, which arrive a little later
+const KEPT_CHECKSUMS: usize = 600;
+// Where `--room` looks for a relay unless told otherwise, e.g. one started with `cargo run -p pong-relay`
+pub const DEFAULT_RELAY: &str = "ws://localhost:9000";
+pub const DEFAULT_BROADCAST_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NetRole {
+    // Waits on this port for someone to join, and plays on the left
+    Host { port: u16 },
+    // Joins a host at `<ip>:<port>`, and plays on the right
+    Join { address: String },
+    // Meets the other player in a room on a relay. Whoever gets there first hosts.
+    Room { room: String },
+    // Watches a match being played, from the `<ip>:<port>` one of the players opened with `--spectators`
+    Spectate { address: String },
+}
+
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct NetSettings {
+    // None plays locally
+    pub role: Option<NetRole>,
+    // Ticks between pressing a key and it taking effect. Each one is a tick less to guess
+    // of the other player's input, so less snapping around when a guess was wrong.
+    pub input_delay: usize,
+    // Fakes a bad network for trying things out on one machine: the fraction of packets
+    // thrown away and how long the rest are held back. Applies to everything this peer sends.
+    pub loss: f32,
+    pub latency: Duration,
+    // The relay rooms are on, a `ws://` URL
+    pub relay: String,
+    // Port this end broadcasts the match on for spectators, if any
+    pub spectator_port: Option<u16>,
+    // How far behind the match spectators are kept, so watching can't be used to help a player
+    pub broadcast_delay: Duration,
+}
+
+impl Default for NetSettings {
+    fn default() -> Self {
+        NetSettings {
+            role: None,
+            input_delay: DEFAULT_INPUT_DELAY_TICKS,
+            loss: 0.0,
+            latency: Duration::ZERO,
+            relay: DEFAULT_RELAY.to_string(),
+            spectator_port: None,
+            broadcast_delay: DEFAULT_BROADCAST_DELAY,
+        }
+    }
+}
+
+impl NetSettings {
+    // Parses `--host <port>`, `--join <ip:port>`, `--room <code>`, `--relay <url>`, `--spectate <ip:port>`,
+    // `--spectators <port>`, `--broadcast-delay <secs>`, `--input-delay <ticks>`, `--net-loss <0..1>`
+    // and `--net-latency <ms>`
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut settings = NetSettings::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--host" => match args.next().and_then(|value| value.parse::<u16>().ok()) {
+                    Some(port) => settings.role = Some(NetRole::Host { port }),
+                    None => eprintln!("--host expects a port number"),
+                },
+                "--join" => match args.next() {
+                    Some(address) => settings.role = Some(NetRole::Join { address }),
+                    None => eprintln!("--join expects an address like 192.168.1.20:7000"),
+                },
+                "--room" => match args.next() {
+                    Some(room) if is_room_code(&room) => {
+                        settings.role = Some(NetRole::Room { room })
+                    }
+                    _ => eprintln!("--room expects a code of up to 32 letters, digits, - or _"),
+                },
+                "--spectate" => match args.next() {
+                    Some(address) => settings.role = Some(NetRole::Spectate { address }),
+                    None => eprintln!("--spectate expects an address like 192.168.1.20:7001"),
+                },
+                "--spectators" => match args.next().and_then(|value| value.parse::<u16>().ok()) {
+                    Some(port) => settings.spectator_port = Some(port),
+                    None => eprintln!("--spectators expects a port number"),
+                },
+                "--broadcast-delay" => {
+                    match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                        Some(secs) if (0.0..=60.0).contains(&secs) => {
+                            settings.broadcast_delay = Duration::from_secs_f32(secs)
+                        }
+                        _ => eprintln!("--broadcast-delay expects a number of seconds up to 60"),
+                    }
+                }
+                "--relay" => match args.next() {
+                    Some(relay) if relay.starts_with("ws://") || relay.starts_with("wss://") => {
+                        settings.relay = relay
+                    }
+                    _ => eprintln!("--relay expects a URL like ws://localhost:9000"),
+                },
+                "--input-delay" => {
+                    match args.next().and_then(|value| value.parse::<usize>().ok()) {
+                        Some(ticks) if ticks < MAX_PREDICTION_TICKS => settings.input_delay = ticks,
+                        _ => eprintln!(
+                        "--input-delay expects a whole number of ticks below {MAX_PREDICTION_TICKS}"
+                    ),
+                    }
+                }
+                "--net-loss" => match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                    Some(loss) if (0.0..1.0).contains(&loss) => settings.loss = loss,
+                    _ => eprintln!("--net-loss expects a fraction of packets from 0 up to 1"),
+                },
+                "--net-latency" => match args.next().and_then(|value| value.parse::<u64>().ok()) {
+                    Some(ms) => settings.latency = Duration::from_millis(ms),
+                    None => eprintln!("--net-latency expects a whole number of milliseconds"),
+                },
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    pub fn label(&self) -> Option<String> {
+        match self.role.as_ref()? {
+            NetRole::Host { port } => Some(format!("Host on port {port}")),
+            NetRole::Join { address } => Some(format!("Join {address}")),
+            NetRole::Room { room } => Some(format!("Play in room {room}")),
+            NetRole::Spectate { address } => Some(format!("Watch {address}")),
+        }
+    }
+
+    // The command line, or `?room=<code>&relay=<url>` in the page URL for the wasm build
+    pub fn from_launch_options() -> Self {
+        #[cfg(target_family = "wasm")]
+        if let Some(search) = web_sys::window().and_then(|window| window.location().search().ok()) {
+            let args = search
+                .trim_start_matches('?')
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .filter(|(key, _)| ["room", "relay", "input-delay"].contains(key))
+                .flat_map(|(key, value)| [format!("--{key}"), value.to_string()]);
+            return NetSettings::from_args(args);
+        }
+        NetSettings::from_args(std::env::args().skip(1))
+    }
+}
+
+// Same rules as the relay's, so a bad code is caught before connecting
+fn is_room_code(room: &str) -> bool {
+    !room.is_empty()
+        && room.len() <= 32
+        && room
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// What one player is holding on one tick. Only these go over the network, everything else
+// follows from them on both ends.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct NetInput(u8);
+
+const NET_ACTIONS: [(Action, u8); 3] = [
+    (Action::PaddleUp, 1 << 0),
+    (Action::PaddleDown, 1 << 1),
+    (Action::Serve, 1 << 2),
+];
+
+impl NetInput {
+    // Either player's bindings work, there's only one paddle to move
+    pub fn from_actions(actions: &ActionState) -> Self {
+        let bits = NET_ACTIONS
+            .into_iter()
+            .filter(|(action, _)| actions.left.pressed(*action) || actions.right.pressed(*action))
+            .fold(0, |bits, (_, bit)| bits | bit);
+        NetInput(bits)
+    }
+
+    pub fn apply(self, player: &mut PlayerActions) {
+        for (action, bit) in NET_ACTIONS {
+            player.set_pressed(action, self.0 & bit != 0);
+        }
+    }
+}
+
+// Both players' inputs, tick by tick. The other player's are guessed until they arrive.
+#[derive(Default)]
+pub struct InputTimeline {
+    input_delay: usize,
+    local: Vec<NetInput>,
+    // Only the other player's inputs that have actually arrived, with no gaps
+    remote: Vec<NetInput>,
+    // Guesses ticks were simulated with before the real input arrived
+    predicted: BTreeMap<usize, NetInput>,
+}
+
+impl InputTimeline {
+    pub fn new(input_delay: usize) -> Self {
+        InputTimeline {
+            input_delay,
+            // Nothing is held for the first few ticks
+            local: vec![NetInput::default(); input_delay],
+            ..default()
+        }
+    }
+
+    // Input sampled while running `tick`, taking effect `input_delay` ticks later.
+    // Ticks run again after a rollback already have theirs.
+    pub fn add_local(&mut self, tick: usize, input: NetInput) {
+        while self.local.len() <= tick + self.input_delay {
+            self.local.push(input);
+        }
+    }
+
+    pub fn local(&self, tick: usize) -> NetInput {
+        self.local.get(tick).copied().unwrap_or_default()
+    }
+
+    // The other player's input for `tick`, or a guess that they're still holding what they were
+    pub fn remote(&mut self, tick: usize) -> NetInput {
+        if let Some(input) = self.remote.get(tick) {
+            return *input;
+        }
+        let guess = self.remote.last().copied().unwrap_or_default();
+        self.predicted.insert(tick, guess);
+        guess
+    }
+
+    // Ticks of the other player's input that have arrived
+    pub fn confirmed_ticks(&self) -> usize {
+        self.remote.len()
+    }
+
+    pub fn local_since(&self, start: usize) -> &[NetInput] {
+        let start = start.min(self.local.len());
+        let end = self.local.len().min(start + MAX_INPUTS_PER_PACKET);
+        &self.local[start..end]
+    }
+
+    // Takes the other player's inputs from tick `start` on. Returns the first tick that was
+    // simulated with a wrong guess, which has to be rolled back and run again.
+    pub fn receive(&mut self, start: usize, inputs: &[NetInput]) -> Option<usize> {
+        let mut mispredicted = None;
+        for (tick, input) in (start..).zip(inputs) {
+            if tick < self.remote.len() {
+                continue;
+            }
+            if tick > self.remote.len() {
+                break;
+            }
+            self.remote.push(*input);
+            if let Some(guess) = self.predicted.remove(&tick) {
+                if guess != *input && mispredicted.is_none() {
+                    mispredicted = Some(tick);
+                }
+            }
+        }
+        mispredicted
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Packet {
+    // Sent by someone joining until the host answers
+    Hello {
+        version: u16,
+    },
+    // The host's answer, with everything both peers have to agree on to play the same match
+    Welcome {
+        seed: u64,
+        timestep: Duration,
+        tuning: GameTuning,
+        rules: MatchRules,
+    },
+    Inputs(InputsPacket),
+    // Leaving the match
+    Bye,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct InputsPacket {
+    // Tick of the first input
+    pub start: u32,
+    pub inputs: Vec<NetInput>,
+    // How many of the receiver's inputs the sender has, so it knows where to start next time
+    pub ack: u32,
+    // The sender's tick and how far ahead of the receiver it thinks it is, for keeping in step
+    pub tick: u32,
+    pub advantage: i32,
+    // Real time on the sender's clock, and the last one it got from the receiver, for the ping
+    pub sent_ms: u32,
+    pub echo_ms: u32,
+    // Latest tick the sender is sure about, and the checksum of the match at its start
+    pub checksum: Option<(u32, u64)>,
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match self {
+            Packet::Hello { version } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            Packet::Welcome {
+                seed,
+                timestep,
+                tuning,
+                rules,
+            } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&seed.to_le_bytes());
+                bytes.extend_from_slice(&(timestep.as_nanos() as u64).to_le_bytes());
+                for text in [
+                    ron::to_string(tuning).expect("tuning always serializes"),
+                    ron::to_string(rules).expect("rules always serialize"),
+                ] {
+                    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(text.as_bytes());
+                }
+            }
+            Packet::Inputs(packet) => {
+                bytes.push(2);
+                for value in [packet.start, packet.ack, packet.tick] {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                bytes.extend_from_slice(&packet.advantage.to_le_bytes());
+                bytes.extend_from_slice(&packet.sent_ms.to_le_bytes());
+                bytes.extend_from_slice(&packet.echo_ms.to_le_bytes());
+                match packet.checksum {
+                    Some((tick, checksum)) => {
+                        bytes.push(1);
+                        bytes.extend_from_slice(&tick.to_le_bytes());
+                        bytes.extend_from_slice(&checksum.to_le_bytes());
+                    }
+                    None => bytes.push(0),
+                }
+                bytes.push(packet.inputs.len() as u8);
+                bytes.extend(packet.inputs.iter().map(|input| input.0));
+            }
+            Packet::Bye => bytes.push(3),
+        }
+        bytes
+    }
+
+    // None for anything that isn't one of ours
+    pub fn decode(bytes: &[u8]) -> Option<Packet> {
+        let mut reader = PacketReader::new(bytes)?;
+        let packet = match reader.u8()? {
+            0 => Packet::Hello {
+                version: u16::from_le_bytes(reader.array()?),
+            },
+            1 => Packet::Welcome {
+                seed: u64::from_le_bytes(reader.array()?),
+                timestep: Duration::from_nanos(u64::from_le_bytes(reader.array()?)),
+                tuning: ron::from_str(&reader.string()?).ok()?,
+                rules: ron::from_str(&reader.string()?).ok()?,
+            },
+            2 => {
+                let start = reader.u32()?;
+                let ack = reader.u32()?;
+                let tick = reader.u32()?;
+                let advantage = i32::from_le_bytes(reader.array()?);
+                let sent_ms = reader.u32()?;
+                let echo_ms = reader.u32()?;
+                let checksum = match reader.u8()? {
+                    0 => None,
+                    _ => Some((reader.u32()?, u64::from_le_bytes(reader.array()?))),
+                };
+                let count = reader.u8()? as usize;
+                let inputs = reader.take(count)?.iter().map(|bits| NetInput(*bits));
+                Packet::Inputs(InputsPacket {
+                    start,
+                    inputs: inputs.collect(),
+                    ack,
+                    tick,
+                    advantage,
+                    sent_ms,
+                    echo_ms,
+                    checksum,
+                })
+            }
+            3 => Packet::Bye,
+            _ => return None,
+        };
+        Some(packet)
+    }
+}
+
+pub struct PacketReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    // None for anything that isn't one of ours
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        Some(PacketReader {
+            bytes: bytes.strip_prefix(MAGIC.as_slice())?,
+        })
+    }
+
+    pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        Some(self.array::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
+
+// The connection to the other peer. Kept from the connecting screen until back at the main menu.
+// A non-send resource, since the browser's WebSocket can only be used from the main thread.
+pub struct NetLink {
+    transport: Box<dyn Transport>,
+    // Packets held back to fake latency, with when they're due to go
+    outgoing: VecDeque<(Duration, Vec<u8>)>,
+    loss: f32,
+    latency: Duration,
+    last_heard: Duration,
+    last_hello: Option<Duration>,
+    // The host's answer to a hello, sent again if the joiner didn't get it
+    welcome: Option<Packet>,
+}
+
+impl NetLink {
+    pub fn open(role: &NetRole, settings: &NetSettings, now: Duration) -> io::Result<Self> {
+        let transport: Box<dyn Transport> = match role {
+            NetRole::Host { port } => Box::new(UdpTransport::host(*port)?),
+            NetRole::Join { address } => Box::new(UdpTransport::join(address)?),
+            NetRole::Room { room } => Box::new(WebSocketTransport::connect(&room_url(
+                &settings.relay,
+                room,
+            ))),
+            NetRole::Spectate { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "spectators don't play",
+                ))
+            }
+        };
+        Ok(NetLink {
+            transport,
+            outgoing: VecDeque::new(),
+            loss: settings.loss,
+            latency: settings.latency,
+            last_heard: now,
+            last_hello: None,
+            welcome: None,
+        })
+    }
+
+    pub fn role(&self) -> Option<PeerRole> {
+        self.transport.role()
+    }
+
+    pub fn send(&mut self, packet: &Packet, now: Duration) {
+        // Not the game's RNG, dropping packets mustn't change how the match plays out
+        if self.loss > 0.0 && rand::random::<f32>() < self.loss {
+            return;
+        }
+        self.outgoing
+            .push_back((now + self.latency, packet.encode()));
+        self.flush(now);
+    }
+
+    pub fn flush(&mut self, now: Duration) {
+        while let Some((due, bytes)) = self.outgoing.front() {
+            if *due > now {
+                break;
+            }
+            self.transport.send(bytes);
+            self.outgoing.pop_front();
+        }
+    }
+
+    // Packets that have arrived since last time, or why the connection's gone
+    pub fn receive(&mut self, now: Duration) -> Result<Vec<Packet>, String> {
+        let packets: Vec<Packet> = self
+            .transport
+            .receive()?
+            .iter()
+            .filter_map(|bytes| Packet::decode(bytes))
+            .collect();
+        if !packets.is_empty() {
+            self.last_heard = now;
+        }
+        Ok(packets)
+    }
+}
+
+// Settings the match swapped out, put back once it's over
+struct SettingsBeforeNetplay {
+    timestep: Duration,
+    cpu: AiSettings,
+    tuning: GameTuning,
+    rules: MatchRules,
+}
+
+// A match against someone over the network. Every tick runs on both ends with the same inputs,
+// guessing the other player's until they arrive, and going back to fix things up when a guess was wrong.
+#[derive(Resource)]
+pub struct NetSession {
+    pub local_side: Side,
+    timeline: InputTimeline,
+    // Next tick to run
+    tick: usize,
+    // Running ticks again after a rollback
+    resimulating: bool,
+    // Too far ahead of the other player, so ticks are on hold
+    waiting: bool,
+    // What the local player is holding this frame, read before the ticks overwrite the action state
+    local_input: NetInput,
+    // First tick simulated with a wrong guess, rolled back to before the next ticks run
+    rollback_to: Option<usize>,
+    snapshots: BTreeMap<usize, Snapshot>,
+    checksums: BTreeMap<usize, u64>,
+    remote_checksums: BTreeMap<usize, u64>,
+    // How many of our inputs the other peer has
+    remote_ack: usize,
+    remote_tick: usize,
+    remote_advantage: i32,
+    remote_sent_ms: u32,
+    pub ping: Option<Duration>,
+    // First tick the two ends disagreed about
+    pub desync: Option<usize>,
+    before: SettingsBeforeNetplay,
+}
+
+impl NetSession {
+    // Last tick both ends have run with the real inputs, so its checksum has to match
+    pub fn settled_tick(&self) -> usize {
+        self.timeline.confirmed_ticks().min(self.tick)
+    }
+
+    // This end's checksum of the match at the start of `tick`, for the last KEPT_CHECKSUMS ticks
+    pub fn checksum(&self, tick: usize) -> Option<u64> {
+        self.checksums.get(&tick).copied()
+    }
+
+    // The match at the start of the settled tick, for broadcasting to spectators
+    pub fn settled_snapshot(&self, state: &MatchState) -> Option<(usize, Snapshot)> {
+        let settled = self.settled_tick();
+        let snapshot = if settled == self.tick {
+            state.snapshot()
+        } else {
+            self.snapshots.get(&settled).cloned()
+        };
+        Some((settled, snapshot?))
+    }
+}
+
+pub fn netplaying(session: Option<Res<NetSession>>) -> bool {
+    session.is_some()
+}
+
+pub fn waiting_for_peer(session: Option<Res<NetSession>>) -> bool {
+    session.is_some_and(|session| session.waiting)
+}
+
+#[derive(Component)]
+pub struct NetHud;
+
+pub struct NetplayPlugin;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NetSettings::from_launch_options())
+            .configure_sets(FixedUpdate, PlayerInputSet.run_if(not(netplaying)))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    GameplaySet::Input,
+                    GameplaySet::Physics,
+                    GameplaySet::Scoring,
+                )
+                    .run_if(not(waiting_for_peer)),
+            )
+            .add_systems(Startup, connect_from_launch_options)
+            .add_systems(
+                OnEnter(AppState::Connecting),
+                (open_net_link, spawn_connecting_screen),
+            )
+            .add_systems(OnEnter(AppState::MainMenu), close_net_link)
+            .add_systems(OnEnter(InGame), spawn_net_hud)
+            .add_systems(OnExit(InGame), end_net_session.after(advance_match_seed))
+            .add_systems(
+                Update,
+                (
+                    connect_to_peer.run_if(in_state(AppState::Connecting)),
+                    update_net_hud.run_if(in_state(InGame).and(netplaying)),
+                ),
+            )
+            .add_systems(
+                RunFixedMainLoop,
+                (
+                    receive_net_packets,
+                    roll_back_mispredictions,
+                    check_net_session,
+                    send_net_packets,
+                )
+                    .chain()
+                    .after(restore_simulated_translation)
+                    .in_set(RunFixedMainLoopSystem::BeforeFixedMainLoop)
+                    .run_if(netplaying),
+            )
+            .add_systems(
+                FixedPreUpdate,
+                decide_net_tick.run_if(in_state(AppState::Playing).and(netplaying)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (save_net_snapshot, apply_net_inputs)
+                    .chain()
+                    .before(attach_ai_controllers)
+                    .before(PlayerInputSet)
+                    .in_set(GameplaySet::Input)
+                    .run_if(netplaying),
+            );
+    }
+}
+
+// Straight to the connecting screen when started with `--host`, `--join`, `--room` or `--spectate`
+pub fn connect_from_launch_options(
+    settings: Res<NetSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if settings.role.is_some() {
+        next_state.set(AppState::Connecting);
+    }
+}
+
+// Exclusive, because commands can't carry the link over to the main thread
+pub fn open_net_link(world: &mut World) {
+    let settings = world.resource::<NetSettings>();
+    let Some(role) = &settings.role else {
+        world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::MainMenu);
+        return;
+    };
+    // Watching goes through spectate.rs instead
+    if matches!(role, NetRole::Spectate { .. }) {
+        return;
+    }
+    match NetLink::open(role, settings, world.resource::<Time<Real>>().elapsed()) {
+        Ok(link) => {
+            world.insert_non_send_resource(link);
+        }
+        Err(error) => {
+            eprintln!("Couldn't open a network connection: {error}");
+            world
+                .resource_mut::<NextState<AppState>>()
+                .set(AppState::MainMenu);
+        }
+    }
+}
+
+pub fn spawn_connecting_screen(mut commands: Commands, settings: Res<NetSettings>) {
+    let status = match &settings.role {
+        Some(NetRole::Host { port }) => format!("Waiting for someone to join on port {port}"),
+        Some(NetRole::Join { address }) => format!("Looking for a host at {address}"),
+        Some(NetRole::Room { room }) => format!("Waiting in room {room} for someone to join"),
+        Some(NetRole::Spectate { address }) => format!("Tuning in to the match at {address}"),
+        None => String::new(),
+    };
+
+    commands
+        .spawn((StateScoped(AppState::Connecting), menu_root(Color::NONE)))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(status),
+                TextFont {
+                    font_size: 40.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+            spawn_button(parent, "Cancel", MenuButton::MainMenu);
+            parent.spawn(hint_text("M to cancel"));
+        });
+}
+
+// Joiners say hello until the host welcomes them. The host starts as soon as someone says hello.
+#[allow(clippy::too_many_arguments)]
+pub fn connect_to_peer(
+    mut commands: Commands,
+    link: Option<NonSendMut<NetLink>>,
+    settings: Res<NetSettings>,
+    real_time: Res<Time<Real>>,
+    mut rng: ResMut<GameRng>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut cpu: ResMut<AiSettings>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(mut link) = link else {
+        return;
+    };
+    let now = real_time.elapsed();
+    let before = SettingsBeforeNetplay {
+        timestep: fixed_time.timestep(),
+        cpu: *cpu,
+        tuning: tuning.clone(),
+        rules: *rules,
+    };
+
+    let packets = match link.receive(now) {
+        Ok(packets) => packets,
+        Err(error) => {
+            eprintln!("Couldn't connect: {error}");
+            next_state.set(AppState::MainMenu);
+            return;
+        }
+    };
+    let role = link.role();
+    let mut local_side = None;
+    for packet in packets {
+        match packet {
+            Packet::Hello { version } if version != NET_VERSION => {
+                eprintln!("Someone tried to join with a different version of the game ({version}, this is {NET_VERSION})");
+                link.transport.forget_peer();
+            }
+            Packet::Hello { .. } if role == Some(PeerRole::Host) && link.welcome.is_none() => {
+                // Only two people play over the network, and spectators are only sent the one
+                // ball and the paddles as they are, see Snapshot::frame
+                rules.four_players = false;
+                rules.doubles = false;
+                rules.balls = 1;
+                rules.extra_ball_every = None;
+                rules.power_ups = false;
+                let welcome = Packet::Welcome {
+                    seed: rng.seed,
+                    timestep: fixed_time.timestep(),
+                    tuning: tuning.clone(),
+                    rules: *rules,
+                };
+                link.send(&welcome, now);
+                link.welcome = Some(welcome);
+                local_side = Some(Side::Left);
+            }
+            Packet::Welcome {
+                seed,
+                timestep,
+                tuning: host_tuning,
+                rules: host_rules,
+            } if role == Some(PeerRole::Join) && local_side.is_none() => {
+                fixed_time.set_timestep(timestep);
+                *tuning = host_tuning;
+                *rules = host_rules;
+                *rng = GameRng::from_seed(seed);
+                local_side = Some(Side::Right);
+            }
+            _ => {}
+        }
+    }
+
+    let Some(local_side) = local_side else {
+        let joining = role == Some(PeerRole::Join);
+        let hello_due = link
+            .last_hello
+            .is_none_or(|last| now >= last + HELLO_INTERVAL);
+        if joining && hello_due {
+            link.send(
+                &Packet::Hello {
+                    version: NET_VERSION,
+                },
+                now,
+            );
+            link.last_hello = Some(now);
+        }
+        link.flush(now);
+        return;
+    };
+
+    println!("Connected, playing as P{}", player_number(local_side));
+    // Both ends start from the same seed with fresh streams
+    *rng = GameRng::from_seed(rng.seed);
+    *cpu = AiSettings::default();
+    commands.insert_resource(NetSession {
+        local_side,
+        timeline: InputTimeline::new(settings.input_delay),
+        tick: 0,
+        resimulating: false,
+        waiting: false,
+        local_input: NetInput::default(),
+        rollback_to: None,
+        snapshots: BTreeMap::new(),
+        checksums: BTreeMap::new(),
+        remote_checksums: BTreeMap::new(),
+        remote_ack: 0,
+        remote_tick: 0,
+        remote_advantage: 0,
+        remote_sent_ms: 0,
+        ping: None,
+        desync: None,
+        before,
+    });
+    next_state.set(AppState::Playing);
+}
+
+// Decided once at the start of each tick, so a tick runs all the way through or not at all
+pub fn decide_net_tick(mut session: ResMut<NetSession>, progress: Res<MatchProgress>) {
+    session.waiting = session.tick >= session.timeline.confirmed_ticks() + MAX_PREDICTION_TICKS
+        // The match only ends once both ends agree on how it ended
+        || progress.winner.is_some();
+}
+
+pub fn save_net_snapshot(mut session: ResMut<NetSession>, state: MatchState) {
+    let Some(snapshot) = state.snapshot() else {
+        return;
+    };
+    let tick = session.tick;
+    session.checksums.insert(tick, snapshot.checksum());
+    session.snapshots.insert(tick, snapshot);
+}
+
+// Stands in for the people's paddle controllers, with both players' inputs for this tick
+pub fn apply_net_inputs(
+    mut session: ResMut<NetSession>,
+    mut actions: ResMut<ActionState>,
+    time: Res<Time>,
+    tuning: Res<GameTuning>,
+    mut paddles: Query<(&Paddle, &PaddleScale, Has<ControlsReversed>, &mut Transform)>,
+) {
+    let tick = session.tick;
+    if !session.resimulating {
+        let input = session.local_input;
+        session.timeline.add_local(tick, input);
+    }
+    let local_side = session.local_side;
+    let local = session.timeline.local(tick);
+    let remote = session.timeline.remote(tick);
+    local.apply(actions.for_side_mut(local_side));
+    remote.apply(actions.for_side_mut(local_side.opposite()));
+
+    for (paddle, scale, reversed, mut transform) in paddles.iter_mut() {
+        let mut player = actions.for_side(paddle.player());
+        if reversed {
+            player = player.with_paddle_reversed();
+        }
+        step_paddle(
+            &mut transform.translation,
+            paddle,
+            *scale,
+            player,
+            &tuning,
+            time.delta_secs(),
+        );
+    }
+    session.tick += 1;
+}
+
+pub fn receive_net_packets(
+    mut link: NonSendMut<NetLink>,
+    mut session: ResMut<NetSession>,
+    real_time: Res<Time<Real>>,
+    actions: Res<ActionState>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let now = real_time.elapsed();
+    session.local_input = NetInput::from_actions(&actions);
+
+    let (packets, lost) = match link.receive(now) {
+        Ok(packets) => (
+            packets,
+            now.saturating_sub(link.last_heard) > DISCONNECT_TIMEOUT,
+        ),
+        Err(_) => (Vec::new(), true),
+    };
+    let mut hung_up = false;
+    for packet in packets {
+        match packet {
+            Packet::Inputs(packet) => {
+                if let Some(tick) = session
+                    .timeline
+                    .receive(packet.start as usize, &packet.inputs)
+                {
+                    session.rollback_to = Some(session.rollback_to.map_or(tick, |t| t.min(tick)));
+                }
+                session.remote_ack = session.remote_ack.max(packet.ack as usize);
+                session.remote_tick = packet.tick as usize;
+                session.remote_advantage = packet.advantage;
+                session.remote_sent_ms = packet.sent_ms;
+                if let Some(echoed) = packet.echo_ms.checked_sub(1) {
+                    session.ping = now.checked_sub(Duration::from_millis(echoed.into()));
+                }
+                if let Some((tick, checksum)) = packet.checksum {
+                    session.remote_checksums.insert(tick as usize, checksum);
+                }
+            }
+            // The joiner didn't get the welcome
+            Packet::Hello { .. } => {
+                if let Some(welcome) = link.welcome.clone() {
+                    link.send(&welcome, now);
+                }
+            }
+            Packet::Bye => hung_up = true,
+            Packet::Welcome { .. } => {}
+        }
+    }
+
+    // Once it's over there's nothing left to wait for
+    if (hung_up || lost) && *state.get() != AppState::GameOver {
+        if hung_up {
+            eprintln!("The other player left the match");
+        } else {
+            eprintln!("Lost the connection to the other player");
+        }
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+// Goes back to the first tick that was run with a wrong guess and runs every tick since then
+// again with what the other player really did, all within this frame
+pub fn roll_back_mispredictions(world: &mut World) {
+    let Some(tick) = world.resource_mut::<NetSession>().rollback_to.take() else {
+        return;
+    };
+    let session = world.resource::<NetSession>();
+    let current_tick = session.tick;
+    let Some(snapshot) = session.snapshots.get(&tick).cloned() else {
+        return;
+    };
+    if tick >= current_tick {
+        return;
+    }
+
+    let mut particles = world.query_filtered::<Entity, With<Particle>>();
+    let particles_before: HashSet<Entity> = particles.iter(world).collect();
+
+    restore_snapshot(world, &snapshot);
+    world.resource_mut::<NetSession>().tick = tick;
+    world.resource_mut::<NetSession>().resimulating = true;
+    let mut tick = tick;
+    while tick < current_tick {
+        run_tick_now(world);
+        let next_tick = world.resource::<NetSession>().tick;
+        // The match ended on the way, which has to be confirmed before going any further
+        if next_tick == tick {
+            break;
+        }
+        tick = next_tick;
+    }
+    world.resource_mut::<NetSession>().resimulating = false;
+
+    // Those ticks were already seen and heard the first time round
+    world.resource_mut::<Events<PlaySound>>().clear();
+    let new_particles: Vec<Entity> = particles
+        .iter(world)
+        .filter(|entity| !particles_before.contains(entity))
+        .collect();
+    for entity in new_particles {
+        world.despawn(entity);
+    }
+}
+
+// Compares checksums, ends the match once both ends are sure of the winner, and keeps the two ends in step
+pub fn check_net_session(
+    mut session: ResMut<NetSession>,
+    progress: Res<MatchProgress>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    let settled = session.settled_tick();
+    let compared: Vec<(usize, u64)> = session
+        .remote_checksums
+        .range(..=settled)
+        .map(|(tick, checksum)| (*tick, *checksum))
+        .collect();
+    for (tick, remote) in compared {
+        session.remote_checksums.remove(&tick);
+        let Some(local) = session.checksums.get(&tick).copied() else {
+            continue;
+        };
+        if local != remote && session.desync.is_none() {
+            eprintln!("Desync at tick {tick}: the two ends of the match have drifted apart ({local:016x} here, {remote:016x} there)");
+            session.desync = Some(tick);
+        }
+    }
+
+    let playing = *state.get() == AppState::Playing;
+    if playing && progress.winner.is_some() && session.timeline.confirmed_ticks() >= session.tick {
+        next_state.set(AppState::GameOver);
+    }
+
+    // Each end thinks it's ahead by the time inputs take to arrive. Whichever is further ahead
+    // than that slows down a touch so the other catches up, instead of one always waiting.
+    let advantage = session.tick as i32 - session.remote_tick as i32;
+    let drift = (advantage - session.remote_advantage) / 2;
+    let speed = if playing && drift > 1 { 0.9 } else { 1.0 };
+    if virtual_time.relative_speed() != speed {
+        virtual_time.set_relative_speed(speed);
+    }
+
+    // Rollbacks never go further back than the first tick that might have been guessed wrong
+    let oldest_needed = settled;
+    session.snapshots.retain(|tick, _| *tick >= oldest_needed);
+    let oldest_checksum = settled.saturating_sub(KEPT_CHECKSUMS);
+    session.checksums.retain(|tick, _| *tick >= oldest_checksum);
+}
+
+pub fn send_net_packets(
+    mut link: NonSendMut<NetLink>,
+    session: Res<NetSession>,
+    real_time: Res<Time<Real>>,
+) {
+    let now = real_time.elapsed();
+    let settled = session.settled_tick();
+    let packet = InputsPacket {
+        start: session.remote_ack as u32,
+        inputs: session.timeline.local_since(session.remote_ack).to_vec(),
+        ack: session.timeline.confirmed_ticks() as u32,
+        tick: session.tick as u32,
+        advantage: session.tick as i32 - session.remote_tick as i32,
+        // Zero means nothing to echo yet, so the clock starts at one
+        sent_ms: now.as_millis() as u32 + 1,
+        echo_ms: session.remote_sent_ms,
+        checksum: session
+            .checksums
+            .range(..=settled)
+            .next_back()
+            .map(|(tick, checksum)| (*tick as u32, *checksum)),
+    };
+    link.send(&Packet::Inputs(packet), now);
+    link.flush(now);
+}
+
+// Puts back the settings the host's replaced
+pub fn end_net_session(
+    mut commands: Commands,
+    session: Option<Res<NetSession>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut cpu: ResMut<AiSettings>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+) {
+    let Some(session) = session else {
+        return;
+    };
+    let before = &session.before;
+    fixed_time.set_timestep(before.timestep);
+    virtual_time.set_relative_speed(1.0);
+    *cpu = before.cpu;
+    *tuning = before.tuning.clone();
+    *rules = before.rules;
+    commands.remove_resource::<NetSession>();
+}
+
+pub fn close_net_link(
+    mut commands: Commands,
+    link: Option<NonSendMut<NetLink>>,
+    real_time: Res<Time<Real>>,
+) {
+    let Some(mut link) = link else {
+        return;
+    };
+    // Held back packets would never go, so this one skips the fake latency
+    link.latency = Duration::ZERO;
+    link.send(&Packet::Bye, real_time.elapsed());
+    commands.queue(|world: &mut World| {
+        world.remove_non_send_resource::<NetLink>();
+    });
+}
+
+pub fn spawn_net_hud(mut commands: Commands, session: Option<Res<NetSession>>) {
+    if session.is_none() {
+        return;
+    }
+    commands.spawn((
+        NetHud,
+        StateScoped(InGame),
+        Text::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(20.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+    ));
+}
+
+pub fn update_net_hud(
+    session: Res<NetSession>,
+    mut texts: Query<(&mut Text, &mut TextColor), With<NetHud>>,
+) {
+    let side = match session.local_side {
+        Side::Left => "left",
+        Side::Right => "right",
+        Side::Top => "top",
+        Side::Bottom => "bottom",
+    };
+    let mut line = format!(
+        "You're P{}, on the {side}",
+        player_number(session.local_side)
+    );
+    if let Some(ping) = session.ping {
+        line += &format!("  Ping {} ms", ping.as_millis());
+    }
+    if session.waiting && session.tick > 0 {
+        line += "  Waiting for the other player...";
+    }
+    let color = match session.desync {
+        Some(tick) => {
+            line += &format!("\nOut of sync since tick {tick}");
+            Color::srgb(1.0, 0.3, 0.3)
+        }
+        None => Color::srgb(0.8, 0.8, 0.8), // Light Gray
+    };
+    for (mut text, mut text_color) in texts.iter_mut() {
+        if text.0 != line {
+            text.0 = line.clone();
+        }
+        text_color.0 = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: NetInput = NetInput(1 << 0);
+    const DOWN: NetInput = NetInput(1 << 1);
+
+    #[test]
+    fn local_input_is_delayed() {
+        let mut timeline = InputTimeline::new(2);
+        timeline.add_local(0, UP);
+        timeline.add_local(1, DOWN);
+        assert_eq!(timeline.local(0), NetInput::default());
+        assert_eq!(timeline.local(1), NetInput::default());
+        assert_eq!(timeline.local(2), UP);
+        assert_eq!(timeline.local(3), DOWN);
+        // Running tick 1 again after a rollback keeps what was pressed the first time
+        timeline.add_local(1, UP);
+        assert_eq!(timeline.local(3), DOWN);
+    }
+
+    #[test]
+    fn wrong_guesses_are_rolled_back() {
+        let mut timeline = InputTimeline::new(0);
+        assert_eq!(timeline.receive(0, &[UP]), None);
+        // Still holding up, or so it's guessed
+        assert_eq!(timeline.remote(1), UP);
+        assert_eq!(timeline.remote(2), UP);
+        assert_eq!(timeline.remote(3), UP);
+        // Tick 1 was right, tick 2 wasn't
+        assert_eq!(timeline.receive(0, &[UP, UP, DOWN, DOWN]), Some(2));
+        assert_eq!(timeline.confirmed_ticks(), 4);
+        assert_eq!(timeline.remote(3), DOWN);
+        // Resent inputs that are already known change nothing
+        assert_eq!(timeline.receive(2, &[DOWN]), None);
+        // Inputs after a gap wait for the gap to be filled
+        assert_eq!(timeline.receive(6, &[UP]), None);
+        assert_eq!(timeline.confirmed_ticks(), 4);
+    }
+
+    #[test]
+    fn packets_survive_a_round_trip() {
+        let packets = [
+            Packet::Hello {
+                version: NET_VERSION,
+            },
+            Packet::Welcome {
+                seed: 42,
+                timestep: Duration::from_secs_f64(1.0 / 120.0),
+                tuning: GameTuning::default(),
+                rules: MatchRules::default(),
+            },
+            Packet::Inputs(InputsPacket {
+                start: 300,
+                inputs: vec![UP, UP, DOWN, NetInput::default()],
+                ack: 298,
+                tick: 301,
+                advantage: -3,
+                sent_ms: 12_345,
+                echo_ms: 12_300,
+                checksum: Some((290, 0xdead_beef_cafe_f00d)),
+            }),
+            Packet::Bye,
+        ];
+        for packet in packets {
+            assert_eq!(Packet::decode(&packet.encode()), Some(packet));
+        }
+        assert_eq!(Packet::decode(b"GET / HTTP/1.1"), None);
+        let hello = Packet::Hello { version: 1 }.encode();
+        assert_eq!(Packet::decode(&hello[..hello.len() - 1]), None);
+    }
+
+    #[test]
+    fn parses_network_options() {
+        let args = [
+            "--join",
+            "127.0.0.1:7000",
+            "--input-delay",
+            "4",
+            "--net-loss",
+            "0.1",
+            "--net-latency",
+            "80",
+        ];
+        let settings = NetSettings::from_args(args.into_iter().map(String::from));
+        assert_eq!(
+            settings.role,
+            Some(NetRole::Join {
+                address: "127.0.0.1:7000".to_string()
+            })
+        );
+        assert_eq!(settings.input_delay, 4);
+        assert_eq!(settings.loss, 0.1);
+        assert_eq!(settings.latency, Duration::from_millis(80));
+        assert_eq!(
+            NetSettings::from_args(["--host", "7000"].into_iter().map(String::from)).role,
+            Some(NetRole::Host { port: 7000 })
+        );
+        let args = ["--relay", "ws://example.com:9000", "--room", "abc-1"];
+        let settings = NetSettings::from_args(args.into_iter().map(String::from));
+        assert_eq!(
+            settings.role,
+            Some(NetRole::Room {
+                room: "abc-1".to_string()
+            })
+        );
+        assert_eq!(settings.relay, "ws://example.com:9000");
+        let args = [
+            "--host",
+            "7000",
+            "--spectators",
+            "7001",
+            "--broadcast-delay",
+            "0.5",
+        ];
+        let settings = NetSettings::from_args(args.into_iter().map(String::from));
+        assert_eq!(settings.spectator_port, Some(7001));
+        assert_eq!(settings.broadcast_delay, Duration::from_millis(500));
+        let args = ["--room", "no spaces", "--relay", "localhost"];
+        assert_eq!(
+            NetSettings::from_args(args.into_iter().map(String::from)),
+            NetSettings::default()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::controls::{Action, ActionState};
+use crate::menu::{hint_text, menu_root, spawn_button, MenuButton, OVERLAY_COLOR};
+use crate::netplay::netplaying;
+use crate::rng::GameRng;
+use crate::spectate::spectating;
+use crate::states::{AppState, InGame};
+use bevy::{
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+
+pub const RESUME_COUNTDOWN_SECS: f32 = 3.0;
+
+// Counts down in real time before play carries on, so nobody loses a point the moment they unpause
+#[derive(Resource)]
+pub struct ResumeCountdown {
+    pub timer: Timer,
+}
+
+// Sent by the Resume button
+#[derive(Event)]
+pub struct ResumeRequested;
+
+#[derive(Component)]
+pub struct PauseMenu;
+
+#[derive(Component)]
+pub struct CountdownText;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ResumeRequested>()
+            .add_systems(OnEnter(AppState::Paused), (freeze_time, spawn_pause_menu))
+            .add_systems(OnExit(AppState::Paused), unfreeze_time)
+            .add_systems(
+                Update,
+                (
+                    // The other player's game can't be frozen from here, and neither can a broadcast
+                    toggle_pause.run_if(not(netplaying).and(not(spectating))),
+                    start_resume_countdown,
+                    tick_resume_countdown,
+                )
+                    .chain()
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+// Either player's Pause binding toggles it
+pub fn toggle_pause(
+    actions: Res<ActionState>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resume: EventWriter<ResumeRequested>,
+) {
+    if !actions.any_just_pressed(Action::Pause) {
+        return;
+    }
+    match state.get() {
+        AppState::Playing => next_state.set(AppState::Paused),
+        AppState::Paused => {
+            resume.write(ResumeRequested);
+        }
+        _ => {}
+    }
+}
+
+// Stopping virtual time freezes everything that ticks off Res<Time>, particles and score flashes included
+pub fn freeze_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+pub fn unfreeze_time(mut commands: Commands, mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+    commands.remove_resource::<ResumeCountdown>();
+}
+
+pub fn spawn_pause_menu(mut commands: Commands, rng: Res<GameRng>) {
+    commands
+        .spawn((
+            StateScoped(AppState::Paused),
+            PauseMenu,
+            menu_root(OVERLAY_COLOR),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Paused"),
+                TextFont {
+                    font_size: 60.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+            spawn_button(parent, "Resume", MenuButton::Resume);
+            spawn_button(parent, "Restart", MenuButton::Restart);
+            spawn_button(parent, "Quit to Menu", MenuButton::MainMenu);
+            // Handy for bug reports, `--seed` replays the same serves
+            parent.spawn(hint_text(&format!("Seed: {}", rng.seed)));
+        });
+}
+
+// Swaps the pause menu for a big countdown number
+pub fn start_resume_countdown(
+    mut commands: Commands,
+    mut resume_requests: EventReader<ResumeRequested>,
+    countdown: Option<Res<ResumeCountdown>>,
+    pause_menu: Query<Entity, With<PauseMenu>>,
+) {
+    if resume_requests.read().count() == 0 || countdown.is_some() {
+        return;
+    }
+
+    for entity in pause_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.insert_resource(ResumeCountdown {
+        timer: Timer::from_seconds(RESUME_COUNTDOWN_SECS, TimerMode::Once),
+    });
+    commands
+        .spawn((StateScoped(AppState::Paused), menu_root(Color::NONE)))
+        .with_children(|parent| {
+            parent.spawn((
+                CountdownText,
+                Text::new(format!("{}", RESUME_COUNTDOWN_SECS.ceil())),
+                TextFont {
+                    font_size: 120.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+        });
+}
+
+pub fn tick_resume_countdown(
+    real_time: Res<Time<Real>>,
+    countdown: Option<ResMut<ResumeCountdown>>,
+    mut countdown_text: Query<&mut Text, With<CountdownText>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(mut countdown) = countdown else {
+        return;
+    };
+    // Virtual time is frozen while paused, so this has to run off the wall clock
+    countdown.timer.tick(real_time.delta());
+
+    if countdown.timer.finished() {
+        next_state.set(AppState::Playing);
+        return;
+    }
+    let seconds_left = countdown.timer.remaining_secs().ceil();
+    for mut text in countdown_text.iter_mut() {
+        text.0 = format!("{seconds_left}");
+    }
+}
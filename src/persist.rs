@@ -0,0 +1,115 @@
+// Small settings files (and replays) that are kept between sessions.
+// Native builds keep them in the user's config directory, the web build in localStorage.
+// Names can include a folder, e.g. `replays/1.pongreplay`.
+
+#[cfg(not(target_family = "wasm"))]
+fn config_dir() -> std::path::PathBuf {
+    use std::{env, path::PathBuf};
+
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("pong")
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn load(name: &str) -> Option<String> {
+    std::fs::read_to_string(config_dir().join(name)).ok()
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn save(name: &str, contents: &str) {
+    save_bytes(name, contents.as_bytes());
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn load_bytes(name: &str) -> Option<Vec<u8>> {
+    std::fs::read(config_dir().join(name)).ok()
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn save_bytes(name: &str, contents: &[u8]) {
+    let path = config_dir().join(name);
+    let result = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+        .and_then(|_| std::fs::write(&path, contents));
+    if let Err(error) = result {
+        eprintln!("Couldn't save {name}: {error}");
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn remove(name: &str) {
+    if let Err(error) = std::fs::remove_file(config_dir().join(name)) {
+        eprintln!("Couldn't remove {name}: {error}");
+    }
+}
+
+// Names of everything saved in `folder`, in no particular order
+#[cfg(not(target_family = "wasm"))]
+pub fn list(folder: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(config_dir().join(folder)) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .map(|file_name| format!("{folder}/{file_name}"))
+        .collect()
+}
+
+#[cfg(target_family = "wasm")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_family = "wasm")]
+pub fn load(name: &str) -> Option<String> {
+    local_storage()?.get_item(&format!("pong/{name}")).ok()?
+}
+
+#[cfg(target_family = "wasm")]
+pub fn save(name: &str, contents: &str) {
+    let saved = local_storage()
+        .map(|storage| storage.set_item(&format!("pong/{name}"), contents).is_ok())
+        .unwrap_or(false);
+    if !saved {
+        eprintln!("Couldn't save {name}");
+    }
+}
+
+// localStorage only holds text, so binary files are stored as hex
+#[cfg(target_family = "wasm")]
+pub fn load_bytes(name: &str) -> Option<Vec<u8>> {
+    let hex = load(name)?;
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(target_family = "wasm")]
+pub fn save_bytes(name: &str, contents: &[u8]) {
+    let hex: String = contents.iter().map(|byte| format!("{byte:02x}")).collect();
+    save(name, &hex);
+}
+
+#[cfg(target_family = "wasm")]
+pub fn remove(name: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.remove_item(&format!("pong/{name}"));
+    }
+}
+
+#[cfg(target_family = "wasm")]
+pub fn list(folder: &str) -> Vec<String> {
+    let Some(storage) = local_storage() else {
+        return Vec::new();
+    };
+    let prefix = format!("pong/{folder}/");
+    (0..storage.length().unwrap_or(0))
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter(|key| key.starts_with(&prefix))
+        .map(|key| key["pong/".len()..].to_string())
+        .collect()
+}
@@ -0,0 +1,606 @@
+// Pickups that turn up around the center line under the power-ups rules. Whoever touched the
+// ball last when it runs over one gets its effect for a while. Everything that decides how the
+// match goes is kept in PowerUps and the pickup and bonus ball entities, which snapshots include,
+// so replays and network matches play out the same with them on.
+use crate::ai::{attach_ai_controllers, move_paddles_with_ai};
+use crate::arena::{edge_strip, ARENA_HALF_HEIGHT};
+use crate::collision::Aabb;
+use crate::effects_system::spawn_particle_burst;
+use crate::rng::GameRng;
+use crate::rules::{MatchRules, MAX_BALLS};
+use crate::setup::ball_bundle;
+use crate::simulation::{GameplaySet, PlayerInputSet};
+use crate::sound::{PlaySound, SoundEffect};
+use crate::states::{AppState, InGame};
+use crate::systems::{check_new_goal, move_ball, GoalScored, Rally};
+use crate::tuning::GameTuning;
+use crate::{Ball, BallNumber, LastTouch, Paddle, PaddleScale, Side, Velocity};
+use bevy::{
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+use rand::Rng;
+use std::time::Duration;
+
+// Pickups only turn up this close to the center line, so neither side is favoured
+pub const NEUTRAL_ZONE_HALF_WIDTH: f32 = 160.0;
+pub const PICKUP_SIZE: f32 = 24.0;
+const MAX_PICKUPS: usize = 2;
+// The first pickup of a rally turns up after this long, the rest somewhere in SPAWN_INTERVAL_SECS
+const FIRST_SPAWN_SECS: f32 = 3.0;
+const SPAWN_INTERVAL_SECS: (f32, f32) = (4.0, 9.0);
+const GROW_SCALE: f32 = 1.5;
+const SHRINK_SCALE: f32 = 0.6;
+// Balls move at this fraction of their speed during slow-mo
+pub const SLOW_MO_TIME_SCALE: f32 = 0.5;
+const SHIELD_THICKNESS: f32 = 6.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum PowerUpKind {
+    // The collector's paddle gets taller
+    Grow,
+    // The other paddle gets shorter
+    Shrink,
+    // Every ball slows down
+    SlowMo,
+    // A second ball splits off the one that picked it up
+    MultiBall,
+    // The other player's up and down swap
+    Reverse,
+    // A wall across the collector's goal
+    Shield,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 6] = [
+        PowerUpKind::Grow,
+        PowerUpKind::Shrink,
+        PowerUpKind::SlowMo,
+        PowerUpKind::MultiBall,
+        PowerUpKind::Reverse,
+        PowerUpKind::Shield,
+    ];
+
+    pub fn duration_secs(self) -> f32 {
+        match self {
+            PowerUpKind::Grow | PowerUpKind::Shrink => 8.0,
+            PowerUpKind::SlowMo => 4.0,
+            PowerUpKind::MultiBall => 10.0,
+            PowerUpKind::Reverse => 5.0,
+            PowerUpKind::Shield => 6.0,
+        }
+    }
+
+    // Shrink and Reverse are done to the other side, the rest help whoever picked them up
+    pub fn targets_opponent(self) -> bool {
+        matches!(self, PowerUpKind::Shrink | PowerUpKind::Reverse)
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            PowerUpKind::Grow => Color::srgb(0.2, 1.0, 0.3), // Neon Green
+            PowerUpKind::Shrink => Color::srgb(1.0, 0.3, 0.2), // Hot Red
+            PowerUpKind::SlowMo => Color::srgb(0.3, 0.5, 1.0), // Electric Blue
+            PowerUpKind::MultiBall => Color::srgb(1.0, 1.0, 0.0), // Electric Yellow
+            PowerUpKind::Reverse => Color::srgb(1.0, 0.5, 0.0), // Neon Orange
+            PowerUpKind::Shield => Color::srgb(0.8, 0.8, 1.0), // Pale Blue
+        }
+    }
+
+    // Shown on the pickup and in the HUD
+    pub fn icon(self) -> &'static str {
+        match self {
+            PowerUpKind::Grow => "+",
+            PowerUpKind::Shrink => "-",
+            PowerUpKind::SlowMo => "~",
+            PowerUpKind::MultiBall => "*",
+            PowerUpKind::Reverse => "R",
+            PowerUpKind::Shield => "#",
+        }
+    }
+}
+
+// A pickup waiting in the neutral zone
+#[derive(Component, Clone, Copy)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+}
+
+// A ball split off by the multi-ball power-up. It goes again when the effect runs out or the rally ends.
+#[derive(Component)]
+pub struct BonusBall;
+
+// Up and down are swapped for this paddle's player
+#[derive(Component)]
+pub struct ControlsReversed;
+
+// Draws the shield across a goal
+#[derive(Component)]
+pub struct ShieldSprite {
+    pub side: Side,
+}
+
+// The row of icons showing what's affecting one side, and for how long
+#[derive(Component)]
+pub struct PowerUpHud {
+    pub side: Side,
+    shown: String,
+}
+
+impl PowerUpHud {
+    pub fn new(side: Side) -> Self {
+        PowerUpHud {
+            side,
+            shown: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ActiveEffect {
+    pub kind: PowerUpKind,
+    // Who picked it up
+    pub owner: Side,
+    pub timer: Timer,
+}
+
+impl ActiveEffect {
+    // The side it's acting on
+    pub fn target(&self) -> Side {
+        if self.kind.targets_opponent() {
+            self.owner.opposite()
+        } else {
+            self.owner
+        }
+    }
+}
+
+// The effects in play and when the next pickup turns up. Cleared whenever a rally ends.
+#[derive(Resource, Clone, Debug)]
+pub struct PowerUps {
+    pub next_spawn: Timer,
+    pub active: Vec<ActiveEffect>,
+}
+
+impl Default for PowerUps {
+    fn default() -> Self {
+        PowerUps {
+            next_spawn: Timer::from_seconds(FIRST_SPAWN_SECS, TimerMode::Once),
+            active: Vec::new(),
+        }
+    }
+}
+
+impl PowerUps {
+    pub fn is_active(&self, kind: PowerUpKind, target: Side) -> bool {
+        self.active
+            .iter()
+            .any(|effect| effect.kind == kind && effect.target() == target)
+    }
+
+    // Picking up something that's already running starts its timer again
+    pub fn grant(&mut self, kind: PowerUpKind, owner: Side) {
+        let timer = Timer::from_seconds(kind.duration_secs(), TimerMode::Once);
+        match self
+            .active
+            .iter_mut()
+            .find(|effect| effect.kind == kind && effect.owner == owner)
+        {
+            Some(effect) => effect.timer = timer,
+            None => self.active.push(ActiveEffect { kind, owner, timer }),
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        for effect in &mut self.active {
+            effect.timer.tick(delta);
+        }
+        self.active.retain(|effect| !effect.timer.finished());
+    }
+
+    pub fn paddle_scale(&self, side: Side) -> PaddleScale {
+        let mut scale = 1.0;
+        if self.is_active(PowerUpKind::Grow, side) {
+            scale *= GROW_SCALE;
+        }
+        if self.is_active(PowerUpKind::Shrink, side) {
+            scale *= SHRINK_SCALE;
+        }
+        PaddleScale(scale)
+    }
+
+    pub fn controls_reversed(&self, side: Side) -> bool {
+        self.is_active(PowerUpKind::Reverse, side)
+    }
+
+    pub fn shielded(&self, side: Side) -> bool {
+        self.is_active(PowerUpKind::Shield, side)
+    }
+
+    // How much of each tick the balls get to move in
+    pub fn ball_time_scale(&self) -> f32 {
+        if self
+            .active
+            .iter()
+            .any(|effect| effect.kind == PowerUpKind::SlowMo)
+        {
+            SLOW_MO_TIME_SCALE
+        } else {
+            1.0
+        }
+    }
+}
+
+// The shield across a side's goal, right at the edge of the arena behind the paddle
+pub fn shield_wall(side: Side) -> Aabb {
+    edge_strip(side, SHIELD_THICKNESS)
+}
+
+pub fn pickup_bundle(kind: PowerUpKind, position: Vec2) -> impl Bundle {
+    (
+        PowerUp { kind },
+        StateScoped(InGame),
+        Sprite {
+            color: kind.color(),
+            custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+            ..default()
+        },
+        Transform::from_translation(position.extend(0.05)),
+        children![(
+            Text2d::new(kind.icon()),
+            TextFont {
+                font_size: PICKUP_SIZE * 0.8,
+                ..default()
+            },
+            TextColor(Color::BLACK),
+            Transform::from_xyz(0.0, 0.0, 0.01),
+        )],
+    )
+}
+
+pub fn power_ups_enabled(rules: Res<MatchRules>) -> bool {
+    rules.power_ups
+}
+
+// Only the goal that ends a rally clears the effects, balls still in play carry on with them
+fn rally_ended(mut goals: EventReader<GoalScored>) -> bool {
+    goals
+        .read()
+        .fold(false, |ended, goal| ended | goal.rally_over)
+}
+
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PowerUps>()
+            .add_systems(OnEnter(InGame), reset_power_ups)
+            .add_systems(OnEnter(AppState::GameOver), clear_power_ups)
+            .add_systems(
+                FixedUpdate,
+                (
+                    // Paddles are resized and reversed before anything moves them
+                    apply_power_up_effects
+                        .after(attach_ai_controllers)
+                        .before(PlayerInputSet)
+                        .before(move_paddles_with_ai)
+                        .in_set(GameplaySet::Input),
+                    (spawn_power_ups, collect_power_ups)
+                        .chain()
+                        .after(move_ball)
+                        .in_set(GameplaySet::Physics)
+                        .run_if(power_ups_enabled),
+                    clear_power_ups
+                        .after(check_new_goal)
+                        .in_set(GameplaySet::Scoring)
+                        .run_if(rally_ended),
+                ),
+            )
+            .add_systems(
+                Update,
+                (update_power_up_hud, show_shields).run_if(in_state(InGame)),
+            );
+    }
+}
+
+pub fn reset_power_ups(mut power_ups: ResMut<PowerUps>) {
+    *power_ups = PowerUps::default();
+}
+
+// Runs the effect timers down and brings the paddles and bonus balls in line with what's left
+#[allow(clippy::type_complexity)]
+pub fn apply_power_up_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    tuning: Res<GameTuning>,
+    mut power_ups: ResMut<PowerUps>,
+    mut paddles: Query<(
+        Entity,
+        &Paddle,
+        &mut PaddleScale,
+        &mut Sprite,
+        Has<ControlsReversed>,
+    )>,
+    balls: Query<(Entity, &BallNumber, Has<BonusBall>), With<Ball>>,
+) {
+    power_ups.tick(time.delta());
+
+    for (entity, paddle, mut scale, mut sprite, reversed) in paddles.iter_mut() {
+        // Collisions and the sprite change size together
+        let wanted = power_ups.paddle_scale(paddle.side);
+        if *scale != wanted {
+            *scale = wanted;
+            sprite.custom_size = Some(wanted.size(paddle.side, &tuning));
+        }
+        let wanted = power_ups.controls_reversed(paddle.side);
+        if wanted && !reversed {
+            commands.entity(entity).insert(ControlsReversed);
+        } else if !wanted && reversed {
+            commands.entity(entity).remove::<ControlsReversed>();
+        }
+    }
+
+    let multi_ball = power_ups
+        .active
+        .iter()
+        .any(|effect| effect.kind == PowerUpKind::MultiBall);
+    if multi_ball {
+        return;
+    }
+    // If the ball that was split scored meanwhile, the oldest bonus ball stays on as the match ball
+    let keep = (!balls.iter().any(|(_, _, bonus)| !bonus))
+        .then(|| balls.iter().min_by_key(|(_, number, _)| **number))
+        .flatten()
+        .map(|(entity, _, _)| entity);
+    for (entity, _, bonus) in balls.iter() {
+        if Some(entity) == keep {
+            commands.entity(entity).remove::<BonusBall>();
+        } else if bonus {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn spawn_power_ups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut power_ups: ResMut<PowerUps>,
+    mut rng: ResMut<GameRng>,
+    pickups: Query<(), With<PowerUp>>,
+) {
+    if !power_ups.next_spawn.tick(time.delta()).finished() {
+        return;
+    }
+    let (min_secs, max_secs) = SPAWN_INTERVAL_SECS;
+    power_ups.next_spawn =
+        Timer::from_seconds(rng.gameplay.gen_range(min_secs..=max_secs), TimerMode::Once);
+    if pickups.iter().count() >= MAX_PICKUPS {
+        return;
+    }
+
+    let kind = PowerUpKind::ALL[rng.gameplay.gen_range(0..PowerUpKind::ALL.len())];
+    let max_x = NEUTRAL_ZONE_HALF_WIDTH - PICKUP_SIZE / 2.0;
+    let max_y = ARENA_HALF_HEIGHT - PICKUP_SIZE;
+    let position = Vec2::new(
+        rng.gameplay.gen_range(-max_x..=max_x),
+        rng.gameplay.gen_range(-max_y..=max_y),
+    );
+    commands.spawn(pickup_bundle(kind, position));
+}
+
+// A ball running over a pickup gives it to whoever touched the ball last.
+// Nobody has touched a freshly served ball, so it passes straight over.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn collect_power_ups(
+    mut commands: Commands,
+    mut power_ups: ResMut<PowerUps>,
+    mut rng: ResMut<GameRng>,
+    mut sounds: EventWriter<PlaySound>,
+    mut rally: ResMut<Rally>,
+    tuning: Res<GameTuning>,
+    balls: Query<(&Transform, &Velocity, &LastTouch), With<Ball>>,
+    pickups: Query<(Entity, &PowerUp, &Transform), Without<Ball>>,
+) {
+    let reach = Vec2::splat(PICKUP_SIZE / 2.0 + tuning.ball_radius);
+    // Query order isn't the same everywhere the match is played, so go by what's on the field
+    let mut collected: Vec<(PowerUpKind, Side, Entity, Vec3, Vec2)> = pickups
+        .iter()
+        .filter_map(|(entity, pickup, pickup_transform)| {
+            let pickup_position = pickup_transform.translation.truncate();
+            let (owner, ball_transform, velocity) = balls
+                .iter()
+                .filter(|(ball_transform, _, _)| {
+                    let offset = ball_transform.translation.truncate() - pickup_position;
+                    offset.abs().cmple(reach).all()
+                })
+                .filter_map(|(ball_transform, velocity, last_touch)| {
+                    Some((last_touch.0?, ball_transform, velocity))
+                })
+                .min_by_key(|(owner, _, _)| *owner as u8)?;
+            Some((
+                pickup.kind,
+                owner,
+                entity,
+                ball_transform.translation,
+                Vec2::new(velocity.x, velocity.y),
+            ))
+        })
+        .collect();
+    collected.sort_by_key(|(kind, owner, _, _, _)| (*kind, *owner as u8));
+    let mut ball_count = balls.iter().count() as u32;
+
+    for (kind, owner, entity, ball_translation, ball_velocity) in collected {
+        commands.entity(entity).despawn();
+        power_ups.grant(kind, owner);
+        sounds.write(PlaySound::new(SoundEffect::PowerUp));
+        spawn_particle_burst(
+            &mut commands,
+            &mut rng.cosmetic,
+            ball_translation.truncate(),
+            kind.color(),
+            10,
+            0.5,
+        );
+
+        if kind == PowerUpKind::MultiBall && ball_count < MAX_BALLS {
+            ball_count += 1;
+            // Splits off the other way up, so the two don't travel together
+            let velocity = if ball_velocity.y == 0.0 {
+                Vec2::new(ball_velocity.x, ball_velocity.x.abs() * 0.5)
+            } else {
+                Vec2::new(ball_velocity.x, -ball_velocity.y)
+            };
+            let number = rally.number_next_ball();
+            commands
+                .spawn(ball_bundle(&tuning, number, ball_translation, velocity))
+                .insert((BonusBall, LastTouch(Some(owner))));
+        }
+    }
+}
+
+// Ends every effect and takes the pickups and bonus balls away, on a goal and at the end of the match
+#[allow(clippy::type_complexity)]
+pub fn clear_power_ups(
+    mut commands: Commands,
+    tuning: Res<GameTuning>,
+    mut power_ups: ResMut<PowerUps>,
+    leftovers: Query<Entity, Or<(With<PowerUp>, With<BonusBall>)>>,
+    mut paddles: Query<(Entity, &Paddle, &mut PaddleScale, &mut Sprite)>,
+) {
+    *power_ups = PowerUps::default();
+    for entity in leftovers.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (entity, paddle, mut scale, mut sprite) in paddles.iter_mut() {
+        *scale = PaddleScale::default();
+        sprite.custom_size = Some(scale.size(paddle.side, &tuning));
+        commands.entity(entity).remove::<ControlsReversed>();
+    }
+}
+
+pub fn show_shields(
+    mut commands: Commands,
+    power_ups: Res<PowerUps>,
+    shields: Query<(Entity, &ShieldSprite)>,
+) {
+    for side in Side::ALL {
+        let shown = shields.iter().find(|(_, shield)| shield.side == side);
+        match (power_ups.shielded(side), shown) {
+            (true, None) => {
+                let wall = shield_wall(side);
+                commands.spawn((
+                    ShieldSprite { side },
+                    StateScoped(InGame),
+                    Sprite {
+                        color: PowerUpKind::Shield.color().with_alpha(0.7),
+                        custom_size: Some(wall.half_size * 2.0),
+                        ..default()
+                    },
+                    Transform::from_translation(wall.center.extend(0.05)),
+                ));
+            }
+            (false, Some((entity, _))) => {
+                commands.entity(entity).despawn();
+            }
+            _ => {}
+        }
+    }
+}
+
+// An icon for each effect on the side, with the seconds it has left
+pub fn update_power_up_hud(
+    mut commands: Commands,
+    power_ups: Res<PowerUps>,
+    mut huds: Query<(Entity, &mut PowerUpHud)>,
+) {
+    for (entity, mut hud) in huds.iter_mut() {
+        let effects: Vec<(PowerUpKind, u32)> = power_ups
+            .active
+            .iter()
+            .filter(|effect| effect.target() == hud.side)
+            .map(|effect| (effect.kind, effect.timer.remaining_secs().ceil() as u32))
+            .collect();
+        let shown = format!("{effects:?}");
+        if hud.shown == shown {
+            continue;
+        }
+        hud.shown = shown;
+
+        commands
+            .entity(entity)
+            .despawn_related::<Children>()
+            .with_children(|row| {
+                for (kind, secs) in effects {
+                    row.spawn((
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                            margin: UiRect::horizontal(Val::Px(4.0)),
+                            ..default()
+                        },
+                        BackgroundColor(kind.color()),
+                    ))
+                    .with_child((
+                        Text::new(format!("{} {secs}", kind.icon())),
+                        TextFont {
+                            font_size: 20.0,
+                            ..default()
+                        },
+                        TextColor(Color::BLACK),
+                    ));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::ARENA_HALF_WIDTH;
+
+    #[test]
+    fn effects_land_on_the_right_side() {
+        let mut power_ups = PowerUps::default();
+        power_ups.grant(PowerUpKind::Grow, Side::Left);
+        power_ups.grant(PowerUpKind::Shrink, Side::Left);
+        power_ups.grant(PowerUpKind::Reverse, Side::Right);
+        assert_eq!(power_ups.paddle_scale(Side::Left), PaddleScale(GROW_SCALE));
+        assert_eq!(
+            power_ups.paddle_scale(Side::Right),
+            PaddleScale(SHRINK_SCALE)
+        );
+        assert!(power_ups.controls_reversed(Side::Left));
+        assert!(!power_ups.controls_reversed(Side::Right));
+    }
+
+    #[test]
+    fn effects_run_out_and_picking_one_up_again_starts_it_over() {
+        let mut power_ups = PowerUps::default();
+        power_ups.grant(PowerUpKind::SlowMo, Side::Right);
+        assert_eq!(power_ups.ball_time_scale(), SLOW_MO_TIME_SCALE);
+
+        let duration = PowerUpKind::SlowMo.duration_secs();
+        power_ups.tick(Duration::from_secs_f32(duration * 0.75));
+        power_ups.grant(PowerUpKind::SlowMo, Side::Right);
+        assert_eq!(power_ups.active.len(), 1);
+        power_ups.tick(Duration::from_secs_f32(duration * 0.75));
+        assert_eq!(power_ups.ball_time_scale(), SLOW_MO_TIME_SCALE);
+
+        power_ups.tick(Duration::from_secs_f32(duration));
+        assert!(power_ups.active.is_empty());
+        assert_eq!(power_ups.ball_time_scale(), 1.0);
+    }
+
+    #[test]
+    fn shields_sit_behind_the_paddles() {
+        let tuning = GameTuning::default();
+        let paddle_x = ARENA_HALF_WIDTH - tuning.paddle_margin;
+        let left = shield_wall(Side::Left);
+        assert!(left.center.x + left.half_size.x < -paddle_x);
+        assert_eq!(shield_wall(Side::Right).center.x, -left.center.x);
+
+        let paddle_y = ARENA_HALF_HEIGHT - tuning.paddle_margin;
+        let top = shield_wall(Side::Top);
+        assert!(top.center.y - top.half_size.y > paddle_y);
+        assert_eq!(top.half_size.x, ARENA_HALF_WIDTH);
+    }
+}
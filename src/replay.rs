@@ -0,0 +1,1090 @@
+use crate::ai::{
+    attach_ai_controllers, move_paddles_with_ai, AiController, AiSettings, Difficulty,
+};
+use crate::controls::{Action, ActionState};
+use crate::menu::{
+    button_bundle, hint_text, menu_root, player_number, spawn_button, MenuButton, RestartRequested,
+};
+use crate::netplay::NetSession;
+use crate::persist;
+use crate::rng::{advance_match_seed, GameRng};
+use crate::rules::{MatchProgress, MatchRules};
+use crate::simulation::{run_tick_now, GameplaySet, Interpolated, PlayerInputSet};
+use crate::snapshot::{restore_snapshot, settle_after_jump, MatchState, Snapshot};
+use crate::spectate::Watching;
+use crate::states::{AppState, InGame};
+use crate::systems::{paddle_position, restart_game, set_paddle_position};
+use crate::tuning::GameTuning;
+use crate::{Paddle, Side};
+use bevy::{
+    prelude::*,
+    ui::{Node, UiRect, Val},
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"PONGRPLY";
+// Bumped whenever the layout of the file changes, or the same file would play back differently.
+// Newer versions than this are refused.
+// 2 put spin on the ball. Older replays play back without it.
+// 3 added the top and bottom paddles of four player matches.
+// 4 added doubles, saving the forward paddles as top and bottom. Older versions would play
+// them back with one paddle a side.
+// 5 added the tuning file's changes during the match, after the ticks.
+pub const REPLAY_VERSION: u16 = 5;
+const REPLAY_FOLDER: &str = "replays";
+const REPLAY_EXTENSION: &str = "pongreplay";
+// Older replays are deleted as new ones are saved
+const KEPT_REPLAYS: usize = 10;
+// How often playback remembers the whole state of the match, so seeking back doesn't start from the beginning
+const SNAPSHOT_INTERVAL_SECS: f64 = 2.0;
+const SEEK_STEP_SECS: f64 = 5.0;
+const PLAYBACK_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+// Bits of the flags byte in front of every tick
+const LEFT_MOVED: u8 = 1 << 0;
+const RIGHT_MOVED: u8 = 1 << 1;
+const LEFT_SERVE: u8 = 1 << 2;
+const RIGHT_SERVE: u8 = 1 << 3;
+const TOP_MOVED: u8 = 1 << 4;
+const BOTTOM_MOVED: u8 = 1 << 5;
+const TOP_SERVE: u8 = 1 << 6;
+const BOTTOM_SERVE: u8 = 1 << 7;
+const SIDE_FLAGS: [(Side, u8, u8); 4] = [
+    (Side::Left, LEFT_MOVED, LEFT_SERVE),
+    (Side::Right, RIGHT_MOVED, RIGHT_SERVE),
+    (Side::Top, TOP_MOVED, TOP_SERVE),
+    (Side::Bottom, BOTTOM_MOVED, BOTTOM_SERVE),
+];
+
+// What one player did in one tick
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct PaddleInput {
+    // Where their paddle ended up along its track, see paddle_position.
+    // None for CPU paddles, the AI plays those again.
+    pub y: Option<f32>,
+    pub serve: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct TickInput {
+    pub left: PaddleInput,
+    pub right: PaddleInput,
+    pub top: PaddleInput,
+    pub bottom: PaddleInput,
+}
+
+impl TickInput {
+    pub fn for_side(&self, side: Side) -> PaddleInput {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    pub fn for_side_mut(&mut self, side: Side) -> &mut PaddleInput {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+}
+
+// Everything needed to play a match again: how it was set up and what people did on every tick.
+// The rest (the ball, serves, the CPU) follows from the seed as the same gameplay systems run again.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub timestep: Duration,
+    pub cpu: AiSettings,
+    pub tuning: GameTuning,
+    pub rules: MatchRules,
+    // How it ended, e.g. "P1 wins 10 - 7". Shown in the replays menu.
+    pub result: String,
+    pub ticks: Vec<TickInput>,
+    // The tuning file saved mid-match, with the tick it was first played by
+    pub tuning_changes: Vec<(usize, GameTuning)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    NotAReplay,
+    // Saved by a newer version of the game
+    TooNew(u16),
+    Truncated,
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::NotAReplay => write!(f, "not a replay file"),
+            ReplayError::TooNew(version) => write!(
+                f,
+                "saved by a newer version of the game (replay version {version}, this reads up to {REPLAY_VERSION})"
+            ),
+            ReplayError::Truncated => write!(f, "the file is cut short"),
+            ReplayError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        timestep: Duration,
+        cpu: AiSettings,
+        tuning: GameTuning,
+        rules: MatchRules,
+    ) -> Self {
+        Replay {
+            seed,
+            timestep,
+            cpu,
+            tuning,
+            rules,
+            result: String::new(),
+            ticks: Vec::new(),
+            tuning_changes: Vec::new(),
+        }
+    }
+
+    // The tuning `tick` was played by
+    pub fn tuning_at(&self, tick: usize) -> &GameTuning {
+        self.tuning_changes
+            .iter()
+            .rev()
+            .find(|(from, _)| *from <= tick)
+            .map_or(&self.tuning, |(_, tuning)| tuning)
+    }
+
+    pub fn ticks_for_secs(&self, secs: f64) -> usize {
+        (secs / self.timestep.as_secs_f64()).round().max(1.0) as usize
+    }
+
+    pub fn clock_at(&self, tick: usize) -> String {
+        let seconds = (self.timestep.as_secs_f64() * tick as f64) as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}  ({})", self.result, self.clock_at(self.ticks.len()))
+    }
+
+    // Header first, then a flags byte per tick followed by the position of any paddle that moved.
+    // People spend most of a match not moving, so most ticks are a single byte.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.timestep.as_nanos() as u64).to_le_bytes());
+        for side in Side::ALL {
+            let difficulty = self.cpu.for_side(side).map_or("", Difficulty::name);
+            write_str(&mut bytes, difficulty);
+        }
+        write_str(
+            &mut bytes,
+            &ron::to_string(&self.tuning).expect("tuning always serializes"),
+        );
+        write_str(
+            &mut bytes,
+            &ron::to_string(&self.rules).expect("rules always serialize"),
+        );
+        write_str(&mut bytes, &self.result);
+
+        bytes.extend_from_slice(&(self.ticks.len() as u32).to_le_bytes());
+        // Paddles start in the middle
+        let middle = PaddleInput {
+            y: Some(0.0),
+            serve: false,
+        };
+        let mut last = TickInput {
+            left: middle,
+            right: middle,
+            top: middle,
+            bottom: middle,
+        };
+        for tick in &self.ticks {
+            let mut flags = 0;
+            let mut positions = Vec::new();
+            for (side, moved, serve) in SIDE_FLAGS {
+                let input = tick.for_side(side);
+                if input.serve {
+                    flags |= serve;
+                }
+                if input.y.is_some() && input.y != last.for_side(side).y {
+                    flags |= moved;
+                    positions.extend_from_slice(&input.y.unwrap_or_default().to_le_bytes());
+                    last.for_side_mut(side).y = input.y;
+                }
+            }
+            bytes.push(flags);
+            bytes.extend_from_slice(&positions);
+        }
+
+        bytes.extend_from_slice(&(self.tuning_changes.len() as u32).to_le_bytes());
+        for (tick, tuning) in &self.tuning_changes {
+            bytes.extend_from_slice(&(*tick as u32).to_le_bytes());
+            write_str(
+                &mut bytes,
+                &ron::to_string(tuning).expect("tuning always serializes"),
+            );
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        let mut reader = ReplayReader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = reader.u16()?;
+        if version > REPLAY_VERSION {
+            return Err(ReplayError::TooNew(version));
+        }
+
+        let seed = reader.u64()?;
+        let timestep = Duration::from_nanos(reader.u64()?);
+        if timestep.is_zero() {
+            return Err(ReplayError::Invalid("the tick rate is missing".to_string()));
+        }
+        let mut cpu = AiSettings::default();
+        // Before version 3 there were only ever two players
+        let sides = if version < 3 {
+            &Side::ALL[..2]
+        } else {
+            &Side::ALL[..]
+        };
+        for side in sides.iter().copied() {
+            let name = reader.string()?;
+            let difficulty = match name.as_str() {
+                "" => None,
+                name => Some(Difficulty::from_name(name).ok_or_else(|| {
+                    ReplayError::Invalid(format!("unknown CPU difficulty '{name}'"))
+                })?),
+            };
+            match side {
+                Side::Left => cpu.left = difficulty,
+                Side::Right => cpu.right = difficulty,
+                Side::Top => cpu.top = difficulty,
+                Side::Bottom => cpu.bottom = difficulty,
+            }
+        }
+        let mut tuning = read_tuning(&mut reader)?;
+        // Spin came in with version 2, and the tuning saved before then would otherwise pick up
+        // its default strength. Without any spin put on the ball it doesn't curve or kick either.
+        if version < 2 {
+            tuning.spin_transfer = 0.0;
+        }
+        check_tuning(&tuning)?;
+        let rules: MatchRules = ron::from_str(&reader.string()?)
+            .map_err(|error| ReplayError::Invalid(format!("bad rules: {error}")))?;
+        let result = reader.string()?;
+
+        let tick_count = reader.u32()? as usize;
+        // Every tick is at least a byte, so a bogus count can't ask for a huge allocation
+        let mut ticks = Vec::with_capacity(tick_count.min(reader.bytes.len()));
+        // People's paddles start in the middle, and players without a paddle never move
+        let mut last = TickInput::default();
+        for side in rules.players().iter().copied() {
+            last.for_side_mut(side).y = cpu.for_side(side).is_none().then_some(0.0);
+        }
+        for _ in 0..tick_count {
+            let flags = reader.u8()?;
+            for (side, moved, serve) in SIDE_FLAGS {
+                let input = last.for_side_mut(side);
+                if flags & moved != 0 {
+                    input.y = Some(reader.f32()?);
+                }
+                input.serve = flags & serve != 0;
+            }
+            ticks.push(last);
+        }
+
+        let mut tuning_changes = Vec::new();
+        if version >= 5 {
+            let change_count = reader.u32()?;
+            for _ in 0..change_count {
+                let tick = reader.u32()? as usize;
+                let changed = read_tuning(&mut reader)?;
+                check_tuning(&changed)?;
+                if tick > ticks.len() || tuning_changes.last().is_some_and(|(last, _)| tick < *last)
+                {
+                    return Err(ReplayError::Invalid(format!(
+                        "a tuning change at tick {tick} is out of order"
+                    )));
+                }
+                tuning_changes.push((tick, changed));
+            }
+        }
+
+        Ok(Replay {
+            seed,
+            timestep,
+            cpu,
+            tuning,
+            rules,
+            result,
+            ticks,
+            tuning_changes,
+        })
+    }
+}
+
+fn read_tuning(reader: &mut ReplayReader) -> Result<GameTuning, ReplayError> {
+    ron::from_str(&reader.string()?)
+        .map_err(|error| ReplayError::Invalid(format!("bad tuning: {error}")))
+}
+
+fn check_tuning(tuning: &GameTuning) -> Result<(), ReplayError> {
+    match tuning.problems().first() {
+        Some(problem) => Err(ReplayError::Invalid(format!("bad tuning: {problem}"))),
+        None => Ok(()),
+    }
+}
+
+fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+struct ReplayReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ReplayReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReplayError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplayError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, ReplayError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| ReplayError::Invalid("text that isn't UTF-8".to_string()))
+    }
+}
+
+// Sent to start watching a replay, from the replays menu or a file dropped on the window
+#[derive(Event)]
+pub struct PlayReplay(pub Replay);
+
+// The match being recorded, from its first tick. Saved when it ends.
+#[derive(Resource, Default)]
+pub struct ReplayRecorder(pub Option<Replay>);
+
+// Settings a replay swapped out, put back once it's over
+struct SettingsBeforeReplay {
+    seed: u64,
+    timestep: Duration,
+    cpu: AiSettings,
+    tuning: GameTuning,
+    rules: MatchRules,
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    // Next tick to play
+    pub tick: usize,
+    pub speed: f32,
+    pub paused: bool,
+    // Where a seek is headed. Carried out once the match is running.
+    pub seek_target: Option<usize>,
+    snapshots: BTreeMap<usize, Snapshot>,
+    before: SettingsBeforeReplay,
+}
+
+impl ReplayPlayback {
+    pub fn seek_to(&mut self, tick: usize) {
+        self.seek_target = Some(tick.min(self.replay.ticks.len()));
+    }
+
+    // Seeks from wherever the last seek was headed, so pressing a key a few times adds up
+    pub fn seek_by(&mut self, secs: f64) {
+        let from = self.seek_target.unwrap_or(self.tick);
+        let step = self.replay.ticks_for_secs(secs.abs());
+        if secs < 0.0 {
+            self.seek_to(from.saturating_sub(step));
+        } else {
+            self.seek_to(from + step);
+        }
+    }
+}
+
+pub fn replaying(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_some()
+}
+
+#[derive(Component)]
+pub struct ReplayHud;
+
+// A saved replay on the replays screen
+#[derive(Component)]
+pub struct ReplayButton(pub String);
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .add_event::<PlayReplay>()
+            .configure_sets(FixedUpdate, PlayerInputSet.run_if(not(replaying)))
+            .add_systems(OnEnter(AppState::Replays), spawn_replays_screen)
+            .add_systems(OnEnter(InGame), (start_recording, spawn_replay_hud))
+            .add_systems(OnEnter(AppState::GameOver), save_recording)
+            .add_systems(OnExit(InGame), end_playback.after(advance_match_seed))
+            .add_systems(
+                FixedUpdate,
+                (
+                    record_tick
+                        .after(PlayerInputSet)
+                        .after(move_paddles_with_ai)
+                        .run_if(not(replaying)),
+                    (
+                        apply_replay_tuning,
+                        take_snapshot.before(move_paddles_with_ai),
+                        play_tick,
+                    )
+                        .chain()
+                        .after(attach_ai_controllers)
+                        .run_if(replaying),
+                )
+                    .in_set(GameplaySet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_replay_buttons.run_if(in_state(AppState::Replays)),
+                    begin_playback.run_if(not(in_state(InGame))),
+                    // A restart is a new match, so it's a new recording too
+                    start_recording
+                        .after(restart_game)
+                        .run_if(in_state(InGame).and(on_event::<RestartRequested>)),
+                    (replay_controls, seek_replay, update_replay_hud)
+                        .chain()
+                        .run_if(in_state(InGame).and(replaying)),
+                ),
+            );
+
+        #[cfg(not(target_family = "wasm"))]
+        app.add_systems(
+            Update,
+            play_dropped_files
+                .before(begin_playback)
+                .run_if(not(in_state(InGame))),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    playback: Option<Res<ReplayPlayback>>,
+    net_session: Option<Res<NetSession>>,
+    watching: Option<Res<Watching>>,
+    rng: Res<GameRng>,
+    fixed_time: Res<Time<Fixed>>,
+    cpu: Res<AiSettings>,
+    tuning: Res<GameTuning>,
+    rules: Res<MatchRules>,
+) {
+    // Network matches roll back and run ticks again, which the recording can't follow,
+    // and spectators never run the ticks at all
+    recorder.0 = (playback.is_none() && net_session.is_none() && watching.is_none()).then(|| {
+        Replay::new(
+            rng.seed,
+            fixed_time.timestep(),
+            *cpu,
+            tuning.clone(),
+            *rules,
+        )
+    });
+}
+
+// Runs after every paddle controller, so it sees where the paddles really ended up
+pub fn record_tick(
+    mut recorder: ResMut<ReplayRecorder>,
+    actions: Res<ActionState>,
+    tuning: Res<GameTuning>,
+    paddles: Query<(&Paddle, &Transform), Without<AiController>>,
+) {
+    let Some(recording) = recorder.0.as_mut() else {
+        return;
+    };
+    // The tuning file was saved since the last tick
+    let tick_number = recording.ticks.len();
+    if *recording.tuning_at(tick_number) != *tuning {
+        recording.tuning_changes.push((tick_number, tuning.clone()));
+    }
+    let mut tick = TickInput::default();
+    for (paddle, transform) in paddles.iter() {
+        let input = tick.for_side_mut(paddle.player());
+        input.y = Some(paddle_position(transform.translation, paddle.side));
+        input.serve = actions.for_side(paddle.player()).pressed(Action::Serve);
+    }
+    recording.ticks.push(tick);
+}
+
+// Saved replays, newest first
+fn saved_replays() -> Vec<(u64, String)> {
+    let mut replays: Vec<(u64, String)> = persist::list(REPLAY_FOLDER)
+        .into_iter()
+        .filter_map(|name| {
+            let id = name
+                .strip_prefix(REPLAY_FOLDER)?
+                .strip_prefix('/')?
+                .strip_suffix(REPLAY_EXTENSION)?
+                .strip_suffix('.')?
+                .parse()
+                .ok()?;
+            Some((id, name))
+        })
+        .collect();
+    replays.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+    replays
+}
+
+fn load_replay(name: &str) -> Result<Replay, String> {
+    let bytes = persist::load_bytes(name).ok_or_else(|| format!("Couldn't read {name}"))?;
+    Replay::decode(&bytes).map_err(|error| format!("Couldn't play {name}: {error}"))
+}
+
+pub fn save_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    rules: Res<MatchRules>,
+    progress: Res<MatchProgress>,
+) {
+    let Some(mut replay) = recorder.0.take() else {
+        return;
+    };
+    let winner = progress.winner.unwrap_or(Side::Left);
+    replay.result = format!(
+        "P{} wins {}",
+        player_number(winner),
+        progress.summary(&rules)
+    );
+
+    let saved = saved_replays();
+    let id = saved.first().map_or(1, |(id, _)| id + 1);
+    let name = format!("{REPLAY_FOLDER}/{id}.{REPLAY_EXTENSION}");
+    persist::save_bytes(&name, &replay.encode());
+    println!("Saved replay {name}");
+    for (_, old) in saved.iter().skip(KEPT_REPLAYS - 1) {
+        persist::remove(old);
+    }
+}
+
+// Swaps in the replay's settings and starts the match
+#[allow(clippy::too_many_arguments)]
+pub fn begin_playback(
+    mut commands: Commands,
+    mut requests: EventReader<PlayReplay>,
+    mut rng: ResMut<GameRng>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut cpu: ResMut<AiSettings>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(PlayReplay(replay)) = requests.read().last() else {
+        return;
+    };
+    let before = SettingsBeforeReplay {
+        seed: rng.seed,
+        timestep: fixed_time.timestep(),
+        cpu: *cpu,
+        tuning: tuning.clone(),
+        rules: *rules,
+    };
+    *rng = GameRng::from_seed(replay.seed);
+    fixed_time.set_timestep(replay.timestep);
+    *cpu = replay.cpu;
+    *tuning = replay.tuning.clone();
+    *rules = replay.rules;
+
+    commands.insert_resource(ReplayPlayback {
+        replay: replay.clone(),
+        tick: 0,
+        speed: 1.0,
+        paused: false,
+        seek_target: None,
+        snapshots: BTreeMap::new(),
+        before,
+    });
+    next_state.set(AppState::Playing);
+}
+
+// Puts everything back the way it was before the replay
+#[allow(clippy::too_many_arguments)]
+pub fn end_playback(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut rng: ResMut<GameRng>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut cpu: ResMut<AiSettings>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+) {
+    let Some(playback) = playback else {
+        return;
+    };
+    let before = &playback.before;
+    *rng = GameRng::from_seed(before.seed);
+    fixed_time.set_timestep(before.timestep);
+    virtual_time.set_relative_speed(1.0);
+    *cpu = before.cpu;
+    *tuning = before.tuning.clone();
+    *rules = before.rules;
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+pub fn take_snapshot(mut playback: ResMut<ReplayPlayback>, state: MatchState) {
+    let tick = playback.tick;
+    let interval = playback.replay.ticks_for_secs(SNAPSHOT_INTERVAL_SECS);
+    if !tick.is_multiple_of(interval) || playback.snapshots.contains_key(&tick) {
+        return;
+    }
+    if let Some(snapshot) = state.snapshot() {
+        playback.snapshots.insert(tick, snapshot);
+    }
+}
+
+// Plays every tick by the tuning it was recorded with, seeks included
+pub fn apply_replay_tuning(playback: Res<ReplayPlayback>, mut tuning: ResMut<GameTuning>) {
+    let wanted = playback.replay.tuning_at(playback.tick);
+    if *tuning != *wanted {
+        *tuning = wanted.clone();
+    }
+}
+
+// Stands in for the people's paddle controllers while a replay plays
+pub fn play_tick(
+    mut playback: ResMut<ReplayPlayback>,
+    mut actions: ResMut<ActionState>,
+    mut paddles: Query<(&Paddle, &mut Transform), Without<AiController>>,
+) {
+    // Past the end there's nothing left to do but wait, the match is already over by then
+    let tick = playback.replay.ticks.get(playback.tick).copied();
+    for side in Side::ALL {
+        let serve = tick.is_some_and(|tick| tick.for_side(side).serve);
+        actions.for_side_mut(side).set_pressed(Action::Serve, serve);
+    }
+    let Some(tick) = tick else {
+        return;
+    };
+    for (paddle, mut transform) in paddles.iter_mut() {
+        if let Some(position) = tick.for_side(paddle.player()).y {
+            set_paddle_position(&mut transform.translation, paddle.side, position);
+        }
+    }
+    playback.tick += 1;
+}
+
+// Space plays and pauses, left and right seek, up and down change the speed, Home goes back to the start
+pub fn replay_controls(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut restarts: EventReader<RestartRequested>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    // Restart from the pause or game over menus watches it again
+    if restarts.read().count() > 0 || keyboard.just_pressed(KeyCode::Home) {
+        playback.seek_to(0);
+    }
+    // The pause menu is in charge while it's up
+    if *state.get() == AppState::Paused {
+        return;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        playback.seek_by(-SEEK_STEP_SECS);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        playback.seek_by(SEEK_STEP_SECS);
+    }
+    if keyboard.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+
+    let speed_index = PLAYBACK_SPEEDS
+        .iter()
+        .position(|speed| *speed == playback.speed)
+        .unwrap_or(2);
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        playback.speed = PLAYBACK_SPEEDS[(speed_index + 1).min(PLAYBACK_SPEEDS.len() - 1)];
+    }
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        playback.speed = PLAYBACK_SPEEDS[speed_index.saturating_sub(1)];
+    }
+
+    let speed = if playback.paused { 0.0 } else { playback.speed };
+    if virtual_time.relative_speed() != speed {
+        virtual_time.set_relative_speed(speed);
+    }
+}
+
+// Jumps to the seek target: back to the last snapshot before it if need be, then straight through
+// the ticks in between by running the gameplay schedule over and over within this frame
+pub fn seek_replay(world: &mut World) {
+    let Some(playback) = world.get_resource::<ReplayPlayback>() else {
+        return;
+    };
+    let Some(target) = playback.seek_target else {
+        return;
+    };
+    let state = *world.resource::<State<AppState>>().get();
+
+    let snapshot = playback
+        .snapshots
+        .range(..=target)
+        .next_back()
+        .filter(|(snapshot_tick, _)| {
+            playback.tick > target || **snapshot_tick > playback.tick || state == AppState::GameOver
+        })
+        .map(|(snapshot_tick, snapshot)| (*snapshot_tick, snapshot.clone()));
+    match snapshot {
+        Some((snapshot_tick, snapshot)) => {
+            restore_snapshot(world, &snapshot);
+            settle_after_jump(world);
+            world.resource_mut::<ReplayPlayback>().tick = snapshot_tick;
+        }
+        // Already past the end and nothing to go back to
+        None if state == AppState::GameOver => {
+            world.resource_mut::<ReplayPlayback>().seek_target = None;
+            return;
+        }
+        None => {}
+    }
+
+    // Ticks only run while playing. The rest of the way is done once the match is back on.
+    if state != AppState::Playing {
+        world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Playing);
+        return;
+    }
+
+    // Transforms hold the blended positions between ticks at this point
+    let mut moving = world.query::<(&mut Transform, &Interpolated)>();
+    for (mut transform, interpolated) in moving.iter_mut(world) {
+        transform.translation = interpolated.current;
+    }
+
+    loop {
+        let playback = world.resource::<ReplayPlayback>();
+        if playback.tick >= target.min(playback.replay.ticks.len())
+            || world.resource::<MatchProgress>().winner.is_some()
+        {
+            break;
+        }
+        run_tick_now(world);
+    }
+
+    settle_after_jump(world);
+    world.resource_mut::<ReplayPlayback>().seek_target = None;
+}
+
+pub fn spawn_replay_hud(mut commands: Commands, playback: Option<Res<ReplayPlayback>>) {
+    if playback.is_none() {
+        return;
+    }
+    commands.spawn((
+        ReplayHud,
+        StateScoped(InGame),
+        Text::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(20.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+    ));
+}
+
+pub fn update_replay_hud(
+    playback: Res<ReplayPlayback>,
+    mut texts: Query<&mut Text, With<ReplayHud>>,
+) {
+    let replay = &playback.replay;
+    let status = if playback.paused { "  Paused" } else { "" };
+    let line = format!(
+        "Replay {} / {}  {}x{status}\nSpace to pause, Left / Right to seek, Up / Down for speed, Home to start over",
+        replay.clock_at(playback.tick.min(replay.ticks.len())),
+        replay.clock_at(replay.ticks.len()),
+        playback.speed,
+    );
+    for mut text in texts.iter_mut() {
+        if text.0 != line {
+            text.0 = line.clone();
+        }
+    }
+}
+
+pub fn spawn_replays_screen(mut commands: Commands) {
+    let replays: Vec<(String, Replay)> = saved_replays()
+        .into_iter()
+        .filter_map(|(_, name)| match load_replay(&name) {
+            Ok(replay) => Some((name, replay)),
+            Err(error) => {
+                eprintln!("{error}");
+                None
+            }
+        })
+        .collect();
+
+    commands
+        .spawn((StateScoped(AppState::Replays), menu_root(Color::NONE)))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Replays"),
+                TextFont {
+                    font_size: 60.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                Node {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                },
+            ));
+
+            if replays.is_empty() {
+                parent.spawn(hint_text(
+                    "No replays yet, every match is saved here when it ends",
+                ));
+            }
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    flex_wrap: FlexWrap::Wrap,
+                    justify_content: JustifyContent::Center,
+                    max_width: Val::Px(1100.0),
+                    ..default()
+                })
+                .with_children(|grid| {
+                    for (name, replay) in replays {
+                        grid.spawn((ReplayButton(name), button_bundle(500.0)))
+                            .with_children(|button_parent| {
+                                button_parent.spawn((
+                                    Text::new(replay.label()),
+                                    TextFont {
+                                        font_size: 20.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::WHITE),
+                                ));
+                            });
+                    }
+                });
+
+            #[cfg(not(target_family = "wasm"))]
+            parent.spawn(hint_text(
+                "Replay files can also be dropped onto the window",
+            ));
+            spawn_button(parent, "Back", MenuButton::MainMenu);
+        });
+}
+
+pub fn handle_replay_buttons(
+    buttons: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+    mut play: EventWriter<PlayReplay>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match load_replay(&button.0) {
+            Ok(replay) => {
+                play.write(PlayReplay(replay));
+            }
+            Err(error) => eprintln!("{error}"),
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn play_dropped_files(
+    mut drops: EventReader<FileDragAndDrop>,
+    mut play: EventWriter<PlayReplay>,
+) {
+    for drop in drops.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = drop else {
+            continue;
+        };
+        let replay = std::fs::read(path_buf)
+            .map_err(|error| error.to_string())
+            .and_then(|bytes| Replay::decode(&bytes).map_err(|error| error.to_string()));
+        match replay {
+            Ok(replay) => {
+                play.write(PlayReplay(replay));
+            }
+            Err(error) => eprintln!("Couldn't play {}: {error}", path_buf.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RulesPreset;
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(
+            42,
+            Duration::from_secs_f64(1.0 / 120.0),
+            AiSettings {
+                left: None,
+                right: Some(Difficulty::Hard),
+                ..default()
+            },
+            GameTuning::default(),
+            MatchRules::default(),
+        );
+        replay.result = "P1 wins 10 - 7".to_string();
+        for i in 0..300 {
+            let y = if i < 100 { 0.0 } else { i as f32 * 0.5 };
+            replay.ticks.push(TickInput {
+                left: PaddleInput {
+                    y: Some(y),
+                    serve: i == 150,
+                },
+                right: PaddleInput {
+                    y: None,
+                    serve: false,
+                },
+                ..default()
+            });
+        }
+        replay
+    }
+
+    #[test]
+    fn four_player_replays_keep_the_top_and_bottom() {
+        let mut replay = replay();
+        replay.rules = RulesPreset::FourPlayers.rules();
+        replay.cpu.bottom = Some(Difficulty::Easy);
+        for (i, tick) in replay.ticks.iter_mut().enumerate() {
+            tick.top = PaddleInput {
+                y: Some(-(i as f32)),
+                serve: i == 10,
+            };
+        }
+        assert_eq!(Replay::decode(&replay.encode()), Ok(replay));
+    }
+
+    #[test]
+    fn replays_from_before_spin_play_without_it() {
+        let mut replay = replay();
+        let mut old = replay.encode();
+        old[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&1u16.to_le_bytes());
+        // Version 1 only had the left and right CPU settings. Top and bottom are both empty here.
+        let top_and_bottom = MAGIC.len() + 2 + 8 + 8 + 4 + 4 + "hard".len();
+        old.drain(top_and_bottom..top_and_bottom + 8);
+
+        replay.tuning.spin_transfer = 0.0;
+        assert_eq!(Replay::decode(&old), Ok(replay));
+    }
+
+    #[test]
+    fn replays_from_before_four_players_keep_their_spin() {
+        let replay = replay();
+        let mut old = replay.encode();
+        old[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&2u16.to_le_bytes());
+        let top_and_bottom = MAGIC.len() + 2 + 8 + 8 + 4 + 4 + "hard".len();
+        old.drain(top_and_bottom..top_and_bottom + 8);
+
+        assert_ne!(replay.tuning.spin_transfer, 0.0);
+        assert_eq!(Replay::decode(&old), Ok(replay));
+    }
+
+    #[test]
+    fn survives_a_round_trip() {
+        let replay = replay();
+        assert_eq!(Replay::decode(&replay.encode()), Ok(replay));
+    }
+
+    #[test]
+    fn tuning_changes_play_from_their_tick() {
+        let mut replay = replay();
+        let faster = GameTuning {
+            keyboard_paddle_speed: 3.0,
+            ..default()
+        };
+        replay.tuning_changes.push((120, faster.clone()));
+        assert_eq!(*replay.tuning_at(119), GameTuning::default());
+        assert_eq!(*replay.tuning_at(120), faster);
+        assert_eq!(Replay::decode(&replay.encode()), Ok(replay));
+    }
+
+    #[test]
+    fn still_ticks_are_a_single_byte() {
+        let mut still = replay();
+        still.ticks.truncate(100);
+        let header = still.encode().len() - 100;
+        still.ticks.extend(still.ticks.clone());
+        assert_eq!(still.encode().len(), header + 200);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert_eq!(
+            Replay::decode(b"(paddle_height: 150.0)"),
+            Err(ReplayError::NotAReplay)
+        );
+        let mut newer = replay().encode();
+        newer[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(REPLAY_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Replay::decode(&newer),
+            Err(ReplayError::TooNew(REPLAY_VERSION + 1))
+        );
+        let encoded = replay().encode();
+        assert_eq!(
+            Replay::decode(&encoded[..encoded.len() - 1]),
+            Err(ReplayError::Truncated)
+        );
+    }
+}
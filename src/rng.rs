@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// All randomness in the game comes from here, so a match can be replayed from its seed.
+// Gameplay (serves, AI mistakes) and cosmetics (particles) draw from separate streams,
+// so adding or removing a particle effect never changes how a match plays out.
+#[derive(Resource)]
+pub struct GameRng {
+    // Seed of the current match
+    pub seed: u64,
+    pub gameplay: ChaCha8Rng,
+    pub cosmetic: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut gameplay = ChaCha8Rng::seed_from_u64(seed);
+        gameplay.set_stream(0);
+        let mut cosmetic = ChaCha8Rng::seed_from_u64(seed);
+        cosmetic.set_stream(1);
+        GameRng {
+            seed,
+            gameplay,
+            cosmetic,
+        }
+    }
+
+    // Reseeds both streams for the next match. The new seed is drawn from the old gameplay
+    // stream, so a whole session still follows from the first seed.
+    pub fn next_match(&mut self) {
+        let seed = self.gameplay.next_u64();
+        *self = GameRng::from_seed(seed);
+    }
+}
+
+pub struct RngPlugin {
+    // None picks a random seed
+    pub seed: Option<u64>,
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        app.insert_resource(GameRng::from_seed(seed));
+    }
+}
+
+// Printed at the start of every match so it can be attached to bug reports
+pub fn log_match_seed(rng: Res<GameRng>) {
+    println!("Match seed: {}", rng.seed);
+}
+
+pub fn advance_match_seed(mut rng: ResMut<GameRng>) {
+    rng.next_match();
+}
+
+// `--seed <u64>` on native builds, or `?seed=<u64>` in the page URL for the wasm build
+pub fn seed_from_args(mut args: impl Iterator<Item = String>) -> Option<u64> {
+    let mut seed = None;
+    while let Some(arg) = args.next() {
+        if arg != "--seed" {
+            continue;
+        }
+        match args.next().and_then(|value| value.parse::<u64>().ok()) {
+            Some(value) => seed = Some(value),
+            None => eprintln!("--seed expects a whole number"),
+        }
+    }
+    seed
+}
+
+#[cfg(target_family = "wasm")]
+pub fn seed_from_url() -> Option<u64> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("seed="))
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+pub fn seed_from_launch_options() -> Option<u64> {
+    #[cfg(target_family = "wasm")]
+    if let Some(seed) = seed_from_url() {
+        return Some(seed);
+    }
+    seed_from_args(std::env::args().skip(1))
+}
@@ -0,0 +1,838 @@
+use crate::simulation::GameplaySet;
+use crate::states::InGame;
+use crate::systems::check_new_goal;
+use crate::{Role, Side};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Most balls there can be in play at once, however they got there
+pub const MAX_BALLS: u32 = 8;
+
+// Named sets of rules the menu cycles through
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RulesPreset {
+    Classic,
+    TableTennis,
+    Sets,
+    Timed,
+    PowerUps,
+    Chaos,
+    FourPlayers,
+    Doubles,
+}
+
+impl RulesPreset {
+    pub const ALL: [RulesPreset; 8] = [
+        RulesPreset::Classic,
+        RulesPreset::TableTennis,
+        RulesPreset::Sets,
+        RulesPreset::Timed,
+        RulesPreset::PowerUps,
+        RulesPreset::Chaos,
+        RulesPreset::FourPlayers,
+        RulesPreset::Doubles,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "classic" => Some(RulesPreset::Classic),
+            "table-tennis" => Some(RulesPreset::TableTennis),
+            "sets" => Some(RulesPreset::Sets),
+            "timed" => Some(RulesPreset::Timed),
+            "power-ups" => Some(RulesPreset::PowerUps),
+            "chaos" => Some(RulesPreset::Chaos),
+            "four-players" => Some(RulesPreset::FourPlayers),
+            "doubles" => Some(RulesPreset::Doubles),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RulesPreset::Classic => "Classic",
+            RulesPreset::TableTennis => "Table Tennis",
+            RulesPreset::Sets => "Sets",
+            RulesPreset::Timed => "Timed",
+            RulesPreset::PowerUps => "Power-Ups",
+            RulesPreset::Chaos => "Chaos",
+            RulesPreset::FourPlayers => "Four Players",
+            RulesPreset::Doubles => "Doubles",
+        }
+    }
+
+    pub fn rules(self) -> MatchRules {
+        let classic = MatchRules::default();
+        match self {
+            RulesPreset::Classic => classic,
+            // First to 11 by two, best of 5, serve swapping every 2 points
+            RulesPreset::TableTennis => MatchRules {
+                points_to_win: 11,
+                win_by_two: true,
+                best_of_games: 5,
+                serve: ServeRule::Alternate { every: 2 },
+                ..classic
+            },
+            RulesPreset::Sets => MatchRules {
+                points_to_win: 5,
+                win_by_two: true,
+                best_of_games: 3,
+                best_of_sets: 3,
+                ..classic
+            },
+            RulesPreset::Timed => MatchRules {
+                time_limit_secs: Some(120.0),
+                ..classic
+            },
+            RulesPreset::PowerUps => MatchRules {
+                power_ups: true,
+                ..classic
+            },
+            // Another ball joins every 4 returns
+            RulesPreset::Chaos => MatchRules {
+                extra_ball_every: Some(4),
+                ..classic
+            },
+            // Everyone against everyone with 3 lives each
+            RulesPreset::FourPlayers => MatchRules {
+                four_players: true,
+                ..classic
+            },
+            // Two against two, otherwise classic
+            RulesPreset::Doubles => MatchRules {
+                doubles: true,
+                ..classic
+            },
+        }
+    }
+}
+
+// Who serves after a point
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ServeRule {
+    // Whoever just conceded
+    LoserServes,
+    // Swaps every `every` points, and every point once a win-by-two game reaches deuce
+    Alternate { every: u32 },
+}
+
+// How a match is scored and when it ends.
+// Scoring and the end of the match go entirely through this.
+#[derive(Resource, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchRules {
+    pub points_to_win: u32,
+    // A game has to be won by two clear points
+    pub win_by_two: bool,
+    // Games in a set. The first to win the majority takes it.
+    pub best_of_games: u32,
+    // Sets in the match
+    pub best_of_sets: u32,
+    pub serve: ServeRule,
+    // Once it runs out whoever is ahead wins. If it's level, the next point wins.
+    pub time_limit_secs: Option<f32>,
+    // Pickups turn up around the center line, see powerups.rs
+    pub power_ups: bool,
+    // Balls in play at the start of the match
+    pub balls: u32,
+    // Another ball is thrown in every this many returns in a rally, up to MAX_BALLS
+    pub extra_ball_every: Option<u32>,
+    // Paddles on all four walls. Each side has `lives` instead of points, and losing the last
+    // of them turns its goal into a wall. The last side left wins.
+    pub four_players: bool,
+    pub lives: u32,
+    // Two paddles a side, a forward one ahead of the usual back one, each with its own player.
+    // Sides still score as teams. Four player matches leave it out.
+    pub doubles: bool,
+}
+
+impl Default for MatchRules {
+    // A single game to 10
+    fn default() -> Self {
+        MatchRules {
+            points_to_win: 10,
+            win_by_two: false,
+            best_of_games: 1,
+            best_of_sets: 1,
+            serve: ServeRule::LoserServes,
+            time_limit_secs: None,
+            power_ups: false,
+            balls: 1,
+            extra_ball_every: None,
+            four_players: false,
+            lives: 3,
+            doubles: false,
+        }
+    }
+}
+
+impl MatchRules {
+    // Parses `--rules <preset>`, then any of `--points <n>`, `--win-by-two`, `--best-of <games>`,
+    // `--sets <n>`, `--serve <loser|alternate[:<n>]>`, `--time-limit <secs>`, `--power-ups`,
+    // `--balls <n>`, `--extra-ball-every <returns>`, `--four-players`, `--lives <n>` and `--doubles`
+    // on top of it
+    pub fn from_args(args: impl Iterator<Item = String>) -> Self {
+        let args: Vec<String> = args.collect();
+        let mut rules = MatchRules::default();
+
+        let mut preset_args = args.iter();
+        while let Some(arg) = preset_args.next() {
+            if arg != "--rules" {
+                continue;
+            }
+            match preset_args
+                .next()
+                .and_then(|name| RulesPreset::from_name(name))
+            {
+                Some(preset) => rules = preset.rules(),
+                None => {
+                    eprintln!(
+                        "--rules expects classic, table-tennis, sets, timed, power-ups, chaos, four-players or doubles"
+                    )
+                }
+            }
+        }
+
+        let count = |value: Option<&String>| value.and_then(|value| value.parse::<u32>().ok());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--points" => match count(args.next()) {
+                    Some(points) if points > 0 => rules.points_to_win = points,
+                    _ => eprintln!("--points expects a positive whole number"),
+                },
+                "--win-by-two" => rules.win_by_two = true,
+                "--best-of" => match count(args.next()) {
+                    Some(games) if games > 0 => rules.best_of_games = games,
+                    _ => eprintln!("--best-of expects a positive whole number"),
+                },
+                "--sets" => match count(args.next()) {
+                    Some(sets) if sets > 0 => rules.best_of_sets = sets,
+                    _ => eprintln!("--sets expects a positive whole number"),
+                },
+                "--serve" => match args.next().map(|value| value.to_ascii_lowercase()) {
+                    Some(value) if value == "loser" => rules.serve = ServeRule::LoserServes,
+                    Some(value) if value.starts_with("alternate") => {
+                        let every = match value.split_once(':') {
+                            Some((_, every)) => {
+                                every.parse::<u32>().ok().filter(|every| *every > 0)
+                            }
+                            None => Some(2),
+                        };
+                        match every {
+                            Some(every) => rules.serve = ServeRule::Alternate { every },
+                            None => eprintln!("Unknown serve rule '{value}'"),
+                        }
+                    }
+                    _ => eprintln!("--serve expects loser or alternate[:<points>]"),
+                },
+                "--time-limit" => match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                    Some(secs) if secs > 0.0 => rules.time_limit_secs = Some(secs),
+                    _ => eprintln!("--time-limit expects a number of seconds"),
+                },
+                "--power-ups" => rules.power_ups = true,
+                "--balls" => match count(args.next()) {
+                    Some(balls) if (1..=MAX_BALLS).contains(&balls) => rules.balls = balls,
+                    _ => eprintln!("--balls expects a whole number from 1 to {MAX_BALLS}"),
+                },
+                "--extra-ball-every" => match count(args.next()) {
+                    Some(returns) if returns > 0 => rules.extra_ball_every = Some(returns),
+                    _ => eprintln!("--extra-ball-every expects a positive whole number"),
+                },
+                "--four-players" => rules.four_players = true,
+                "--lives" => match count(args.next()) {
+                    Some(lives) if lives > 0 => rules.lives = lives,
+                    _ => eprintln!("--lives expects a positive whole number"),
+                },
+                "--doubles" => rules.doubles = true,
+                _ => {}
+            }
+        }
+        rules
+    }
+
+    // The preset these rules match, if they haven't been customised
+    pub fn preset(&self) -> Option<RulesPreset> {
+        RulesPreset::ALL
+            .into_iter()
+            .find(|preset| preset.rules() == *self)
+    }
+
+    // Steps to the next preset. Custom rules go back to the first one.
+    pub fn next_preset(&mut self) {
+        let next = match self.preset() {
+            Some(preset) => {
+                let index = RulesPreset::ALL.iter().position(|p| *p == preset).unwrap();
+                RulesPreset::ALL[(index + 1) % RulesPreset::ALL.len()]
+            }
+            None => RulesPreset::ALL[0],
+        };
+        *self = next.rules();
+    }
+
+    pub fn label(&self) -> String {
+        let name = self.preset().map_or("Custom", RulesPreset::name);
+        format!("Rules: {name}")
+    }
+
+    // The sides with a paddle in the match
+    pub fn sides(&self) -> &'static [Side] {
+        if self.four_players {
+            &Side::ALL
+        } else {
+            &[Side::Left, Side::Right]
+        }
+    }
+
+    // The paddles each side has
+    pub fn roles(&self) -> &'static [Role] {
+        if self.doubles && !self.four_players {
+            &Role::ALL
+        } else {
+            &[Role::Back]
+        }
+    }
+
+    // Everyone with a paddle to move, see Paddle::player
+    pub fn players(&self) -> &'static [Side] {
+        if self.roles().len() > 1 {
+            &Side::ALL
+        } else {
+            self.sides()
+        }
+    }
+
+    pub fn games_to_win(&self) -> u32 {
+        self.best_of_games / 2 + 1
+    }
+
+    pub fn sets_to_win(&self) -> u32 {
+        self.best_of_sets / 2 + 1
+    }
+
+    fn game_won(&self, points: u32, opponent_points: u32) -> bool {
+        points >= self.points_to_win && (!self.win_by_two || points >= opponent_points + 2)
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SideCounts {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl SideCounts {
+    pub fn get(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+            Side::Top => self.top,
+            Side::Bottom => self.bottom,
+        }
+    }
+
+    fn get_mut(&mut self, side: Side) -> &mut u32 {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+}
+
+// Where the current match stands. Reset at the start of every match.
+#[derive(Resource, Clone, Debug)]
+pub struct MatchProgress {
+    // Points in the current game
+    pub points: SideCounts,
+    // Games in the current set
+    pub games: SideCounts,
+    pub sets: SideCounts,
+    // Lives left on each side in a four player match, where there are no points
+    pub lives: Option<SideCounts>,
+    // Who served first this game. The opening serve of a match goes right.
+    first_server: Side,
+    last_conceded: Side,
+    pub time_left_secs: Option<f32>,
+    // Time ran out with the scores level, so the next point takes the match
+    pub sudden_death: bool,
+    pub winner: Option<Side>,
+}
+
+impl MatchProgress {
+    pub fn new(rules: &MatchRules) -> Self {
+        MatchProgress {
+            points: SideCounts::default(),
+            games: SideCounts::default(),
+            sets: SideCounts::default(),
+            lives: rules.four_players.then_some(SideCounts {
+                left: rules.lives,
+                right: rules.lives,
+                top: rules.lives,
+                bottom: rules.lives,
+            }),
+            first_server: Side::Right,
+            last_conceded: Side::Right,
+            time_left_secs: rules.time_limit_secs,
+            sudden_death: false,
+            winner: None,
+        }
+    }
+
+    // Adds a point, rolling it up into games, sets and the match as each is won.
+    // A finished match keeps its final score instead of starting the next game.
+    pub fn award_point(&mut self, rules: &MatchRules, scorer: Side) {
+        if self.winner.is_some() {
+            return;
+        }
+        *self.points.get_mut(scorer) += 1;
+        self.last_conceded = scorer.opposite();
+
+        if self.sudden_death {
+            self.winner = Some(scorer);
+            return;
+        }
+        if !rules.game_won(self.points.get(scorer), self.points.get(scorer.opposite())) {
+            return;
+        }
+
+        *self.games.get_mut(scorer) += 1;
+        if self.games.get(scorer) < rules.games_to_win() {
+            self.next_game();
+            return;
+        }
+
+        *self.sets.get_mut(scorer) += 1;
+        if self.sets.get(scorer) < rules.sets_to_win() {
+            self.games = SideCounts::default();
+            self.next_game();
+            return;
+        }
+        self.winner = Some(scorer);
+    }
+
+    // Takes a life off a side in a four player match. Once only one side has any left it wins,
+    // and after time runs out whoever has the most wins straight away.
+    pub fn lose_life(&mut self, side: Side) {
+        if self.winner.is_some() || self.knocked_out(side) {
+            return;
+        }
+        let Some(lives) = self.lives.as_mut() else {
+            return;
+        };
+        *lives.get_mut(side) -= 1;
+        self.last_conceded = side;
+
+        let mut still_in = Side::ALL
+            .into_iter()
+            .filter(|side| !self.knocked_out(*side));
+        if let (Some(last), None) = (still_in.next(), still_in.next()) {
+            self.winner = Some(last);
+        } else if self.sudden_death {
+            self.winner = self.leader();
+        }
+    }
+
+    // Lost all its lives, so its goal is a wall now
+    pub fn knocked_out(&self, side: Side) -> bool {
+        self.lives.is_some_and(|lives| lives.get(side) == 0)
+    }
+
+    // What a side's number on the HUD shows: lives in a four player match, points otherwise
+    pub fn shown_score(&self, side: Side) -> u32 {
+        self.lives.unwrap_or(self.points).get(side)
+    }
+
+    fn next_game(&mut self) {
+        self.points = SideCounts::default();
+        self.first_server = self.first_server.opposite();
+    }
+
+    // Who serves the next point
+    pub fn server(&self, rules: &MatchRules) -> Side {
+        if self.lives.is_some() {
+            // Whoever lost the last life, or the next side round if that knocked them out
+            let conceded = Side::ALL
+                .iter()
+                .position(|side| *side == self.last_conceded)
+                .unwrap();
+            return (0..Side::ALL.len())
+                .map(|offset| Side::ALL[(conceded + offset) % Side::ALL.len()])
+                .find(|side| !self.knocked_out(*side))
+                .unwrap_or(self.last_conceded);
+        }
+        match rules.serve {
+            ServeRule::LoserServes => self.last_conceded,
+            ServeRule::Alternate { every } => {
+                let played = self.points.left + self.points.right;
+                let deuce_at = if rules.win_by_two {
+                    2 * (rules.points_to_win - 1)
+                } else {
+                    u32::MAX
+                };
+                let turns = if played < deuce_at {
+                    played / every
+                } else {
+                    deuce_at.div_ceil(every) + (played - deuce_at)
+                };
+                if turns % 2 == 0 {
+                    self.first_server
+                } else {
+                    self.first_server.opposite()
+                }
+            }
+        }
+    }
+
+    // Ahead on sets, then games, then points. Or most lives left, if nobody else has as many.
+    fn leader(&self) -> Option<Side> {
+        if let Some(lives) = self.lives {
+            let most = Side::ALL.map(|side| lives.get(side)).into_iter().max();
+            let mut leaders = Side::ALL
+                .into_iter()
+                .filter(|side| Some(lives.get(*side)) == most);
+            return match (leaders.next(), leaders.next()) {
+                (Some(leader), None) => Some(leader),
+                _ => None,
+            };
+        }
+        let standing = |side| {
+            (
+                self.sets.get(side),
+                self.games.get(side),
+                self.points.get(side),
+            )
+        };
+        let (left, right) = (standing(Side::Left), standing(Side::Right));
+        match left.cmp(&right) {
+            std::cmp::Ordering::Greater => Some(Side::Left),
+            std::cmp::Ordering::Less => Some(Side::Right),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn tick_clock(&mut self, delta_secs: f32) {
+        let Some(time_left) = self.time_left_secs.as_mut() else {
+            return;
+        };
+        if self.sudden_death || self.winner.is_some() {
+            return;
+        }
+        *time_left = (*time_left - delta_secs).max(0.0);
+        if *time_left == 0.0 {
+            match self.leader() {
+                Some(leader) => self.winner = Some(leader),
+                None => self.sudden_death = true,
+            }
+        }
+    }
+
+    // Games and sets so far, and the clock, for the HUD
+    pub fn status(&self, rules: &MatchRules) -> String {
+        let mut parts = Vec::new();
+        if rules.best_of_sets > 1 {
+            parts.push(format!("Sets {} - {}", self.sets.left, self.sets.right));
+        }
+        if rules.best_of_games > 1 {
+            parts.push(format!("Games {} - {}", self.games.left, self.games.right));
+        }
+        if self.sudden_death {
+            parts.push("Sudden death".to_string());
+        } else if let Some(time_left) = self.time_left_secs {
+            let seconds = time_left.ceil() as u32;
+            parts.push(format!("{}:{:02}", seconds / 60, seconds % 60));
+        }
+        parts.join("    ")
+    }
+
+    // The final score in whatever unit decided the match
+    pub fn summary(&self, rules: &MatchRules) -> String {
+        if let Some(lives) = self.lives {
+            let left: Vec<String> = Side::ALL.map(|side| lives.get(side).to_string()).into();
+            return format!("Lives {}", left.join(" - "));
+        }
+        let (unit, counts) = if rules.best_of_sets > 1 {
+            ("Sets ", self.sets)
+        } else if rules.best_of_games > 1 {
+            ("Games ", self.games)
+        } else {
+            ("", self.points)
+        };
+        format!("{unit}{} - {}", counts.left, counts.right)
+    }
+}
+
+#[derive(Component)]
+pub struct MatchStatusText;
+
+pub struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        let rules = MatchRules::from_args(std::env::args().skip(1));
+        app.insert_resource(rules)
+            .insert_resource(MatchProgress::new(&rules))
+            .add_systems(OnEnter(InGame), reset_match_progress)
+            .add_systems(
+                FixedUpdate,
+                tick_match_clock
+                    .before(check_new_goal)
+                    .in_set(GameplaySet::Scoring),
+            )
+            .add_systems(Update, update_match_status.run_if(in_state(InGame)));
+    }
+}
+
+pub fn reset_match_progress(rules: Res<MatchRules>, mut progress: ResMut<MatchProgress>) {
+    *progress = MatchProgress::new(&rules);
+}
+
+pub fn tick_match_clock(time: Res<Time>, mut progress: ResMut<MatchProgress>) {
+    progress.tick_clock(time.delta_secs());
+}
+
+pub fn update_match_status(
+    rules: Res<MatchRules>,
+    progress: Res<MatchProgress>,
+    mut texts: Query<&mut Text, With<MatchStatusText>>,
+) {
+    let status = progress.status(&rules);
+    for mut text in texts.iter_mut() {
+        if text.0 != status {
+            text.0.clone_from(&status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(progress: &mut MatchProgress, rules: &MatchRules, points: &[Side]) {
+        for side in points {
+            progress.award_point(rules, *side);
+        }
+    }
+
+    fn play_lives(progress: &mut MatchProgress, lost: &[Side]) {
+        for side in lost {
+            progress.lose_life(*side);
+        }
+    }
+
+    #[test]
+    fn classic_is_first_to_ten() {
+        let rules = MatchRules::default();
+        let mut progress = MatchProgress::new(&rules);
+        play(&mut progress, &rules, &[Side::Left; 9]);
+        assert_eq!(progress.winner, None);
+        progress.award_point(&rules, Side::Left);
+        assert_eq!(progress.winner, Some(Side::Left));
+        assert_eq!(progress.summary(&rules), "10 - 0");
+    }
+
+    #[test]
+    fn win_by_two_plays_on_at_deuce() {
+        let rules = MatchRules {
+            points_to_win: 3,
+            win_by_two: true,
+            ..default()
+        };
+        let mut progress = MatchProgress::new(&rules);
+        play(
+            &mut progress,
+            &rules,
+            &[Side::Left, Side::Left, Side::Right, Side::Right, Side::Left],
+        );
+        assert_eq!(progress.winner, None);
+        progress.award_point(&rules, Side::Left);
+        assert_eq!(progress.winner, Some(Side::Left));
+    }
+
+    #[test]
+    fn games_roll_up_into_sets() {
+        let rules = MatchRules {
+            points_to_win: 1,
+            best_of_games: 3,
+            best_of_sets: 3,
+            ..default()
+        };
+        let mut progress = MatchProgress::new(&rules);
+        play(
+            &mut progress,
+            &rules,
+            &[Side::Left, Side::Right, Side::Left],
+        );
+        assert_eq!(progress.sets.left, 1);
+        assert_eq!(progress.games, SideCounts::default());
+        play(&mut progress, &rules, &[Side::Left]);
+        assert_eq!(progress.winner, None);
+        progress.award_point(&rules, Side::Left);
+        assert_eq!(progress.winner, Some(Side::Left));
+        assert_eq!(progress.summary(&rules), "Sets 2 - 0");
+    }
+
+    #[test]
+    fn loser_serves() {
+        let rules = MatchRules::default();
+        let mut progress = MatchProgress::new(&rules);
+        progress.award_point(&rules, Side::Right);
+        assert_eq!(progress.server(&rules), Side::Left);
+        progress.award_point(&rules, Side::Left);
+        assert_eq!(progress.server(&rules), Side::Right);
+    }
+
+    #[test]
+    fn table_tennis_serve_swaps_every_two_then_every_point_at_deuce() {
+        let rules = RulesPreset::TableTennis.rules();
+        let mut progress = MatchProgress::new(&rules);
+        let mut servers = vec![progress.server(&rules)];
+        for _ in 0..4 {
+            progress.award_point(&rules, Side::Left);
+            servers.push(progress.server(&rules));
+        }
+        assert_eq!(
+            servers,
+            [
+                Side::Right,
+                Side::Right,
+                Side::Left,
+                Side::Left,
+                Side::Right
+            ]
+        );
+
+        progress.points = SideCounts {
+            left: 10,
+            right: 10,
+            ..default()
+        };
+        let at_deuce = progress.server(&rules);
+        progress.points.left = 11;
+        assert_eq!(progress.server(&rules), at_deuce.opposite());
+    }
+
+    #[test]
+    fn time_up_goes_to_the_leader_or_sudden_death() {
+        let rules = RulesPreset::Timed.rules();
+        let mut progress = MatchProgress::new(&rules);
+        progress.award_point(&rules, Side::Right);
+        progress.tick_clock(200.0);
+        assert_eq!(progress.winner, Some(Side::Right));
+
+        let mut progress = MatchProgress::new(&rules);
+        progress.tick_clock(200.0);
+        assert!(progress.sudden_death);
+        assert_eq!(progress.winner, None);
+        progress.award_point(&rules, Side::Left);
+        assert_eq!(progress.winner, Some(Side::Left));
+    }
+
+    #[test]
+    fn args_customise_a_preset() {
+        let args = [
+            "--points",
+            "7",
+            "--rules",
+            "table-tennis",
+            "--serve",
+            "alternate:5",
+        ];
+        let rules = MatchRules::from_args(args.into_iter().map(String::from));
+        assert_eq!(rules.points_to_win, 7);
+        assert_eq!(rules.best_of_games, 5);
+        assert_eq!(rules.serve, ServeRule::Alternate { every: 5 });
+        assert_eq!(rules.label(), "Rules: Custom");
+    }
+
+    #[test]
+    fn ball_counts_stay_in_range() {
+        let args = [
+            "--rules",
+            "chaos",
+            "--balls",
+            "20",
+            "--extra-ball-every",
+            "2",
+        ];
+        let rules = MatchRules::from_args(args.into_iter().map(String::from));
+        assert_eq!(rules.balls, 1);
+        assert_eq!(rules.extra_ball_every, Some(2));
+
+        let args = ["--balls", "3", "--extra-ball-every", "0"];
+        let rules = MatchRules::from_args(args.into_iter().map(String::from));
+        assert_eq!(rules.balls, 3);
+        assert_eq!(rules.extra_ball_every, None);
+    }
+
+    #[test]
+    fn four_players_knock_each_other_out_until_one_is_left() {
+        let rules = MatchRules {
+            lives: 2,
+            ..RulesPreset::FourPlayers.rules()
+        };
+        let mut progress = MatchProgress::new(&rules);
+        progress.lose_life(Side::Top);
+        assert_eq!(progress.server(&rules), Side::Top);
+        progress.lose_life(Side::Top);
+        assert!(progress.knocked_out(Side::Top));
+        // Top is out, so the serve moves round to the next side still in
+        assert_eq!(progress.server(&rules), Side::Bottom);
+        progress.lose_life(Side::Top);
+        assert_eq!(progress.shown_score(Side::Top), 0);
+
+        play_lives(&mut progress, &[Side::Left, Side::Left, Side::Bottom]);
+        assert_eq!(progress.winner, None);
+        progress.lose_life(Side::Bottom);
+        assert_eq!(progress.winner, Some(Side::Right));
+        assert_eq!(progress.summary(&rules), "Lives 0 - 2 - 0 - 0");
+    }
+
+    #[test]
+    fn four_player_time_up_goes_to_most_lives() {
+        let rules = MatchRules {
+            time_limit_secs: Some(60.0),
+            ..RulesPreset::FourPlayers.rules()
+        };
+        let mut progress = MatchProgress::new(&rules);
+        play_lives(&mut progress, &[Side::Left, Side::Right]);
+        progress.tick_clock(100.0);
+        assert!(progress.sudden_death);
+        // Top and bottom are still level, so it carries on until one of them drops a life
+        progress.lose_life(Side::Left);
+        assert_eq!(progress.winner, None);
+        progress.lose_life(Side::Top);
+        assert_eq!(progress.winner, Some(Side::Bottom));
+    }
+
+    #[test]
+    fn doubles_puts_everyone_on_the_left_and_right() {
+        let rules = MatchRules::from_args(["--doubles".to_string()].into_iter());
+        assert_eq!(rules.preset(), Some(RulesPreset::Doubles));
+        assert_eq!(rules.sides(), [Side::Left, Side::Right]);
+        assert_eq!(rules.roles(), Role::ALL);
+        assert_eq!(rules.players(), Side::ALL);
+
+        // Four players already has everyone, one paddle each
+        let rules = MatchRules {
+            four_players: true,
+            ..rules
+        };
+        assert_eq!(rules.roles(), [Role::Back]);
+        assert_eq!(rules.players(), Side::ALL);
+        assert_eq!(MatchRules::default().players(), [Side::Left, Side::Right]);
+    }
+
+    #[test]
+    fn menu_cycles_through_presets() {
+        let mut rules = MatchRules::default();
+        assert_eq!(rules.label(), "Rules: Classic");
+        rules.next_preset();
+        assert_eq!(rules.preset(), Some(RulesPreset::TableTennis));
+        rules.win_by_two = false;
+        rules.next_preset();
+        assert_eq!(rules.preset(), Some(RulesPreset::Classic));
+    }
+}
@@ -1,4 +1,3 @@
-use crate::get_window_dimensions;
 use bevy::winit::WinitWindows;
 use bevy::{
     prelude::*,
@@ -8,11 +7,31 @@ use bevy::{
 use std::io::Cursor;
 use winit::window::Icon;
 
+use crate::ai::AiSettings;
+use crate::arena::{arena_projection, edge_strip, ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH};
+use crate::menu::player_number;
+use crate::powerups::PowerUpHud;
+use crate::rules::{MatchRules, MatchStatusText, MAX_BALLS};
+use crate::simulation::Interpolated;
+use crate::states::InGame;
+use crate::systems::{constrain_paddle, Rally, KNOCKED_OUT_WALL_THICKNESS, NORMAL_BALL_COLOR};
+use crate::tuning::GameTuning;
 use crate::{
-    Ball, Border, HitStreak, Paddle, Score, Side, Velocity, BALL_RADIUS, PADDLE_HEIGHT,
-    PADDLE_MARGIN, PADDLE_WIDTH,
+    Ball, BallNumber, Border, HitStreak, KnockedOutWall, LastTouch, Paddle, PaddleScale, Role,
+    Score, Side, Spin, Velocity,
 };
 
+const SCORE_COLOR: Color = Color::srgb(0.8, 0.8, 0.8); // Light Gray
+
+pub fn paddle_color(side: Side) -> Color {
+    match side {
+        Side::Left => Color::srgb(0.0, 1.0, 1.0),   // Neon Cyan
+        Side::Right => Color::srgb(1.0, 0.0, 1.0),  // Neon Magenta
+        Side::Top => Color::srgb(0.2, 1.0, 0.2),    // Neon Green
+        Side::Bottom => Color::srgb(1.0, 0.6, 0.0), // Neon Orange
+    }
+}
+
 // Sets the icon on windows and X11
 pub fn set_window_icon(
     windows: NonSend<WinitWindows>,
@@ -34,101 +53,244 @@ pub fn set_window_icon(
     };
 }
 
-// Spawns the camera that draws UI
+// Spawns the camera that draws the arena and the UI
 pub fn setup_camera(mut cmd: Commands) {
-    cmd.spawn(Camera2d);
+    cmd.spawn((Camera2d, arena_projection()));
 }
 
-pub fn setup_game(mut commands: Commands, windows: Query<&Window>) {
-    let (half_window_width, half_window_height) =
-        get_window_dimensions(windows.iter().next().unwrap());
-    let paddle_x = half_window_width - PADDLE_MARGIN;
-
-    // Spawn the left paddle
-    const LEFT_PADDLE: Paddle = Paddle { side: Side::Left };
-    const RIGHT_PADDLE: Paddle = Paddle { side: Side::Right };
-
-    commands.spawn((
-        LEFT_PADDLE,
-        Sprite {
-            color: Color::srgb(0.0, 1.0, 1.0), // Neon Cyan
-            custom_size: Some(Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT)),
-            ..default()
-        },
-        Transform::from_xyz(-paddle_x, 0.0, 0.1),
-    ));
-
-    // Spawn the right paddle
-    commands.spawn((
-        RIGHT_PADDLE,
-        Sprite {
-            color: Color::srgb(1.0, 0.0, 1.0), // Neon Magenta
-            custom_size: Some(Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT)),
-            ..default()
-        },
-        Transform::from_xyz(paddle_x, 0.0, 0.1),
-    ));
-
-    // Spawn the ball
-    let quarter_window_width = half_window_width / 2.0;
-    let quarter_window_height = half_window_height / 2.0;
-    commands.spawn((
+// A ball fresh into play, nobody having touched it yet
+pub fn ball_bundle(
+    tuning: &GameTuning,
+    number: BallNumber,
+    translation: Vec3,
+    velocity: Vec2,
+) -> impl Bundle {
+    (
         Ball,
+        number,
+        StateScoped(InGame),
         Sprite {
-            color: Color::srgb(1.0, 1.0, 0.0), // Electric Yellow
-            custom_size: Some(Vec2::new(BALL_RADIUS * 2.0, BALL_RADIUS * 2.0)),
+            color: NORMAL_BALL_COLOR,
+            custom_size: Some(Vec2::splat(tuning.ball_radius * 2.0)),
             ..default()
         },
-        Transform::from_xyz(0.0, 0.0, 0.1),
+        Transform::from_translation(translation),
+        Interpolated::at(translation),
         Velocity {
-            x: quarter_window_width,
-            y: quarter_window_height,
+            x: velocity.x,
+            y: velocity.y,
         },
-        HitStreak::default(), // Add the HitStreak component
-    ));
+        Spin::default(),
+        HitStreak::default(),
+        LastTouch::default(),
+    )
+}
 
-    const LEFT_SCORE: Score = Score {
-        value: 0,
-        side: Side::Left,
-    };
-    // Spawn the left score
-    commands.spawn((
-        LEFT_SCORE,
-        Text::new("0".to_string()),
-        TextFont {
-            font_size: 100.0,
-            ..default()
-        },
-        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
-        Node {
-            position_type: PositionType::Absolute,
-            left: Val::Percent(40.0),
-            top: Val::Px(20.0),
-            ..default()
-        },
-    ));
+// Spawns everything that makes up a match. Every entity is scoped to InGame,
+// so leaving for the main menu cleans the lot up.
+pub fn setup_game(
+    mut commands: Commands,
+    tuning: Res<GameTuning>,
+    rules: Res<MatchRules>,
+    ai: Res<AiSettings>,
+    mut rally: ResMut<Rally>,
+) {
+    // A paddle in the middle of each side in play, or two in doubles
+    for side in rules.sides().iter().copied() {
+        for role in rules.roles().iter().copied() {
+            let paddle = Paddle { side, role };
+            let mut translation = Vec3::new(0.0, 0.0, 0.1);
+            constrain_paddle(&mut translation, &paddle, PaddleScale::default(), &tuning);
+            // Forward paddles are a paler shade of their side's colour
+            let color = match role {
+                Role::Back => paddle_color(side),
+                Role::Forward => paddle_color(side).lighter(0.2),
+            };
+            commands.spawn((
+                paddle,
+                StateScoped(InGame),
+                Sprite {
+                    color,
+                    custom_size: Some(PaddleScale::default().size(side, &tuning)),
+                    ..default()
+                },
+                Transform::from_translation(translation),
+                Interpolated::at(translation),
+            ));
+        }
+    }
+
+    // Spawn the balls, all from the middle. The first heads up and to the right,
+    // and any others go off in the other directions in turn.
+    *rally = Rally::default();
+    let quarter_arena_width = ARENA_HALF_WIDTH / 2.0;
+    let quarter_arena_height = ARENA_HALF_HEIGHT / 2.0;
+    for index in 0..rules.balls.clamp(1, MAX_BALLS) {
+        let x = if index % 2 == 0 { 1.0 } else { -1.0 };
+        let y = if index % 4 < 2 { 1.0 } else { -1.0 };
+        commands.spawn(ball_bundle(
+            &tuning,
+            rally.number_next_ball(),
+            Vec3::new(0.0, 0.0, 0.1),
+            Vec2::new(x * quarter_arena_width, y * quarter_arena_height),
+        ));
+    }
 
-    const RIGHT_SCORE: Score = Score {
-        value: 0,
-        side: Side::Right,
+    // A score for each side. Four players count down their lives instead, each by its own wall
+    // and in its paddle's colour so it's clear whose is whose.
+    let (starting_score, font_size) = if rules.four_players {
+        (rules.lives, 72.0)
+    } else {
+        (0, 100.0)
     };
-    // Spawn the right score
+    for side in rules.sides().iter().copied() {
+        let (node, color) = if rules.four_players {
+            (four_player_score_node(side), paddle_color(side))
+        } else {
+            (
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: if side == Side::Left {
+                        Val::Percent(40.0)
+                    } else {
+                        Val::Auto
+                    },
+                    right: if side == Side::Right {
+                        Val::Percent(40.0)
+                    } else {
+                        Val::Auto
+                    },
+                    top: Val::Px(20.0),
+                    ..default()
+                },
+                SCORE_COLOR,
+            )
+        };
+        commands.spawn((
+            Score {
+                value: starting_score,
+                side,
+            },
+            StateScoped(InGame),
+            Text::new(starting_score.to_string()),
+            TextFont {
+                font_size,
+                ..default()
+            },
+            TextColor(color),
+            node,
+        ));
+    }
+
+    // Whose team is whose in doubles, beside each score
+    if rules.roles().len() > 1 {
+        for side in rules.sides().iter().copied() {
+            let teammates: Vec<String> = rules
+                .roles()
+                .iter()
+                .map(|role| {
+                    let player = Paddle { side, role: *role }.player();
+                    match ai.for_side(player) {
+                        Some(_) => "CPU".to_string(),
+                        None => format!("P{}", player_number(player)),
+                    }
+                })
+                .collect();
+            commands.spawn((
+                StateScoped(InGame),
+                Text::new(teammates.join(" + ")),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(paddle_color(side)),
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: if side == Side::Left {
+                        Val::Percent(25.0)
+                    } else {
+                        Val::Auto
+                    },
+                    right: if side == Side::Right {
+                        Val::Percent(25.0)
+                    } else {
+                        Val::Auto
+                    },
+                    top: Val::Px(60.0),
+                    ..default()
+                },
+            ));
+        }
+    }
+
+    // Games, sets and the clock, filled in by update_match_status. Empty under the classic rules.
     commands.spawn((
-        RIGHT_SCORE,
-        Text::new("0".to_string()),
+        MatchStatusText,
+        StateScoped(InGame),
+        Text::new(""),
         TextFont {
-            font_size: 100.0,
+            font_size: 24.0,
             ..default()
         },
-        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
+        TextColor(SCORE_COLOR),
         Node {
             position_type: PositionType::Absolute,
-            right: Val::Percent(40.0),
-            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            top: Val::Px(130.0),
+            justify_content: JustifyContent::Center,
             ..default()
         },
+        TextLayout::new_with_justify(JustifyText::Center),
     ));
 
+    // Icons for the power-ups acting on each side, in the top corners for left and right
+    // and the bottom ones for top and bottom
+    for side in rules.sides().iter().copied() {
+        let (left, right) = match side {
+            Side::Left | Side::Top => (Val::Px(20.0), Val::Auto),
+            Side::Right | Side::Bottom => (Val::Auto, Val::Px(20.0)),
+        };
+        let (top, bottom) = if side.is_horizontal() {
+            (Val::Auto, Val::Px(20.0))
+        } else {
+            (Val::Px(20.0), Val::Auto)
+        };
+        commands.spawn((
+            PowerUpHud::new(side),
+            StateScoped(InGame),
+            Node {
+                position_type: PositionType::Absolute,
+                left,
+                right,
+                top,
+                bottom,
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+        ));
+    }
+
+    // Walls that go up across the goals of sides knocked out of a four player match,
+    // see show_knocked_out_sides
+    if rules.four_players {
+        for side in Side::ALL {
+            let wall = edge_strip(side, KNOCKED_OUT_WALL_THICKNESS);
+            commands.spawn((
+                KnockedOutWall { side },
+                StateScoped(InGame),
+                Sprite {
+                    color: paddle_color(side).with_alpha(0.5),
+                    custom_size: Some(wall.half_size * 2.0),
+                    ..default()
+                },
+                Transform::from_translation(wall.center.extend(0.05)),
+                Visibility::Hidden,
+            ));
+        }
+        // No center line when there's no halfway
+        return;
+    }
+
     //draw border
     let border_thickness = 5.0;
     let border_color = Color::srgb(0.6, 0.6, 0.6); // Slightly darker gray for border
@@ -136,11 +298,12 @@ pub fn setup_game(mut commands: Commands, windows: Query<&Window>) {
     // Draw the middle dashed line
     let dash_length = 5.0;
     let dash_spacing = 5.0;
-    let num_dashes = (half_window_height * 3.0 / (dash_length + dash_spacing)).ceil() as u32;
+    let num_dashes = (ARENA_HALF_HEIGHT * 2.0 / (dash_length + dash_spacing)).ceil() as u32;
     for i in 0..num_dashes {
-        let y = half_window_height * 1.5 - (i as f32 * (dash_length + dash_spacing));
+        let y = ARENA_HALF_HEIGHT - dash_length / 2.0 - (i as f32 * (dash_length + dash_spacing));
         commands.spawn((
             Border,
+            StateScoped(InGame),
             Sprite {
                 color: border_color,
                 custom_size: Some(Vec2::new(border_thickness, dash_length)),
@@ -150,3 +313,33 @@ pub fn setup_game(mut commands: Commands, windows: Query<&Window>) {
         ));
     }
 }
+
+// Just inside the arena from each side's wall
+fn four_player_score_node(side: Side) -> Node {
+    let node = Node {
+        position_type: PositionType::Absolute,
+        ..default()
+    };
+    match side {
+        Side::Left => Node {
+            left: Val::Percent(10.0),
+            top: Val::Percent(42.0),
+            ..node
+        },
+        Side::Right => Node {
+            right: Val::Percent(10.0),
+            top: Val::Percent(42.0),
+            ..node
+        },
+        Side::Top => Node {
+            left: Val::Percent(48.0),
+            top: Val::Percent(14.0),
+            ..node
+        },
+        Side::Bottom => Node {
+            left: Val::Percent(48.0),
+            bottom: Val::Percent(14.0),
+            ..node
+        },
+    }
+}
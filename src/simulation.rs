@@ -0,0 +1,121 @@
+use crate::states::AppState;
+use bevy::{app::FixedMain, prelude::*};
+
+// Gameplay runs in FixedUpdate at this rate unless `--tick-rate <hz>` says otherwise
+pub const DEFAULT_TICK_RATE_HZ: f64 = 120.0;
+
+// Moving entities are simulated in fixed ticks but drawn every frame, so their Transform
+// is blended between the last two ticks for display.
+// Inside FixedUpdate the Transform always holds the real simulated value.
+#[derive(Component, Clone, Copy)]
+pub struct Interpolated {
+    pub previous: Vec3,
+    pub current: Vec3,
+}
+
+impl Interpolated {
+    pub fn at(translation: Vec3) -> Self {
+        Interpolated {
+            previous: translation,
+            current: translation,
+        }
+    }
+}
+
+// Order of each gameplay tick. Only runs while actually playing.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameplaySet {
+    // Paddle controllers: keyboard, touch and AI
+    Input,
+    // Ball movement and collisions
+    Physics,
+    // Goals and the end of the match
+    Scoring,
+}
+
+// People's paddle controllers (keyboard, touch and gamepad), as opposed to the CPU.
+// Part of GameplaySet::Input. Replays switch it off and feed in the recorded paddles instead.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayerInputSet;
+
+pub struct SimulationPlugin {
+    pub tick_rate_hz: f64,
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(self.tick_rate_hz))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    GameplaySet::Input,
+                    GameplaySet::Physics,
+                    GameplaySet::Scoring,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .configure_sets(FixedUpdate, PlayerInputSet.in_set(GameplaySet::Input))
+            .add_systems(
+                RunFixedMainLoop,
+                (
+                    restore_simulated_translation
+                        .in_set(RunFixedMainLoopSystem::BeforeFixedMainLoop),
+                    interpolate_translation.in_set(RunFixedMainLoopSystem::AfterFixedMainLoop),
+                ),
+            )
+            .add_systems(FixedFirst, remember_previous_translation);
+    }
+}
+
+// Parses `--tick-rate <hz>`
+pub fn tick_rate_from_args(mut args: impl Iterator<Item = String>) -> f64 {
+    let mut tick_rate = DEFAULT_TICK_RATE_HZ;
+    while let Some(arg) = args.next() {
+        if arg != "--tick-rate" {
+            continue;
+        }
+        match args.next().and_then(|value| value.parse::<f64>().ok()) {
+            Some(hz) if hz > 0.0 => tick_rate = hz,
+            _ => eprintln!("--tick-rate expects a positive number of ticks per second"),
+        }
+    }
+    tick_rate
+}
+
+// Runs one whole gameplay tick on the spot, for skipping ahead or catching up within a single frame.
+// Transforms have to hold the simulated positions, not the blended ones.
+pub fn run_tick_now(world: &mut World) {
+    let timestep = world.resource::<Time<Fixed>>().timestep();
+    world.resource_mut::<Time<Fixed>>().advance_by(timestep);
+    let fixed_time = world.resource::<Time<Fixed>>().as_generic();
+    *world.resource_mut::<Time>() = fixed_time;
+    world.run_schedule(FixedMain);
+    let virtual_time = world.resource::<Time<Virtual>>().as_generic();
+    *world.resource_mut::<Time>() = virtual_time;
+}
+
+// Undo last frame's blending so the fixed ticks carry on from the true simulated state
+pub fn restore_simulated_translation(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in query.iter_mut() {
+        transform.translation = interpolated.current;
+    }
+}
+
+pub fn remember_previous_translation(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in query.iter_mut() {
+        interpolated.previous = transform.translation;
+    }
+}
+
+// Save the simulated state, then show a blend of the last two ticks based on how far into the next one we are
+pub fn interpolate_translation(
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&mut Transform, &mut Interpolated)>,
+) {
+    let alpha = fixed_time.overstep_fraction();
+    for (mut transform, mut interpolated) in query.iter_mut() {
+        interpolated.current = transform.translation;
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::ai::AiController;
+use crate::powerups::{pickup_bundle, BonusBall, PowerUp, PowerUpKind, PowerUps};
+use crate::rng::GameRng;
+use crate::rules::MatchProgress;
+use crate::setup::ball_bundle;
+use crate::simulation::Interpolated;
+use crate::sound::PlaySound;
+use crate::spectate::StateFrame;
+use crate::systems::{paddle_position, AwaitingServe, Rally, FRENZY_BALL_COLOR};
+use crate::tuning::GameTuning;
+use crate::{
+    Ball, BallNumber, HitStreak, LastTouch, Paddle, Particle, Role, Score, Side, Spin, Velocity,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand_chacha::ChaCha8Rng;
+
+// The state of the match at the start of a tick. Going back to one and running the same
+// ticks again plays out exactly the same, which replay seeking and rollback netcode rely on.
+#[derive(Clone)]
+pub struct Snapshot {
+    gameplay_rng: ChaCha8Rng,
+    progress: MatchProgress,
+    rally: Rally,
+    // Oldest first
+    balls: Vec<BallState>,
+    paddles: Vec<(Side, Role, Vec3, Option<AiController>)>,
+    power_ups: PowerUps,
+    // In a fixed order, so two copies of the same match hash the same
+    pickups: Vec<(PowerUpKind, Vec2)>,
+}
+
+#[derive(Clone)]
+struct BallState {
+    number: BallNumber,
+    translation: Vec3,
+    velocity: Vec2,
+    spin: f32,
+    hit_streak: u32,
+    last_touch: LastTouch,
+    color: Color,
+    awaiting_serve: Option<AwaitingServe>,
+    bonus: bool,
+}
+
+impl Snapshot {
+    // Cheap fingerprint of everything that decides how the match goes on, for spotting two
+    // copies of a match that have drifted apart. The ball color is left out, it's just for show.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.gameplay_rng.get_seed());
+        hasher.update(&self.gameplay_rng.get_stream().to_le_bytes());
+        hasher.update(&self.gameplay_rng.get_word_pos().to_le_bytes());
+        hasher.update(format!("{:?}", self.progress).as_bytes());
+        hasher.update(format!("{:?}", self.rally).as_bytes());
+        for ball in &self.balls {
+            hasher.update(&ball.number.0.to_le_bytes());
+            for value in [
+                ball.translation.x,
+                ball.translation.y,
+                ball.velocity.x,
+                ball.velocity.y,
+                ball.spin,
+            ] {
+                hasher.update(&value.to_bits().to_le_bytes());
+            }
+            hasher.update(&ball.hit_streak.to_le_bytes());
+            hasher.update(format!("{:?}", ball.last_touch).as_bytes());
+            hasher.update(&[ball.bonus as u8]);
+            if let Some(serve) = &ball.awaiting_serve {
+                hasher.update(&[serve.receiver as u8]);
+                hasher.update(&serve.velocity.x.to_bits().to_le_bytes());
+                hasher.update(&serve.velocity.y.to_bits().to_le_bytes());
+                hasher.update(&serve.timer.elapsed().as_nanos().to_le_bytes());
+            }
+        }
+        // Query order isn't guaranteed to match between two processes
+        for side in Side::ALL {
+            for role in Role::ALL {
+                let Some((_, _, translation, ai)) = self
+                    .paddles
+                    .iter()
+                    .find(|(s, r, _, _)| *s == side && *r == role)
+                else {
+                    continue;
+                };
+                hasher.update(&[side as u8, role as u8]);
+                hasher.update(&paddle_position(*translation, side).to_bits().to_le_bytes());
+                if let Some(ai) = ai {
+                    hasher.update(&ai.target_y.to_bits().to_le_bytes());
+                    hasher.update(&ai.reaction_delay.elapsed().as_nanos().to_le_bytes());
+                }
+            }
+        }
+        hasher.update(&self.power_ups.next_spawn.elapsed().as_nanos().to_le_bytes());
+        hasher.update(
+            &self
+                .power_ups
+                .next_spawn
+                .duration()
+                .as_nanos()
+                .to_le_bytes(),
+        );
+        for effect in &self.power_ups.active {
+            hasher.update(&[effect.kind as u8, effect.owner as u8]);
+            hasher.update(&effect.timer.elapsed().as_nanos().to_le_bytes());
+        }
+        for (kind, position) in &self.pickups {
+            hasher.update(&[*kind as u8]);
+            hasher.update(&position.x.to_bits().to_le_bytes());
+            hasher.update(&position.y.to_bits().to_le_bytes());
+        }
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("hash is 32 bytes"))
+    }
+
+    // What spectators are shown of it. Network matches only ever have the one ball.
+    pub fn frame(&self, tick: u32) -> StateFrame {
+        let ball = &self.balls[0];
+        let paddle_y = |side: Side| {
+            self.paddles
+                .iter()
+                .find(|(s, role, _, _)| *s == side && *role == Role::Back)
+                .map_or(0.0, |(_, _, translation, _)| translation.y)
+        };
+        StateFrame {
+            tick,
+            ball: ball.translation.truncate(),
+            ball_velocity: ball.velocity,
+            paddles: [paddle_y(Side::Left), paddle_y(Side::Right)],
+            points: self.progress.points,
+            games: self.progress.games,
+            sets: self.progress.sets,
+            hit_streak: ball.hit_streak,
+            frenzy: ball.color == FRENZY_BALL_COLOR,
+            winner: self.progress.winner,
+        }
+    }
+}
+
+// Everything a snapshot is taken from
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct MatchState<'w, 's> {
+    rng: Res<'w, GameRng>,
+    progress: Res<'w, MatchProgress>,
+    power_ups: Res<'w, PowerUps>,
+    rally: Res<'w, Rally>,
+    balls: Query<
+        'w,
+        's,
+        (
+            &'static BallNumber,
+            &'static Transform,
+            &'static Velocity,
+            &'static Spin,
+            &'static HitStreak,
+            &'static LastTouch,
+            &'static Sprite,
+            Option<&'static AwaitingServe>,
+            Has<BonusBall>,
+        ),
+        With<Ball>,
+    >,
+    pickups: Query<'w, 's, (&'static PowerUp, &'static Transform)>,
+    paddles: Query<
+        'w,
+        's,
+        (
+            &'static Paddle,
+            &'static Transform,
+            Option<&'static AiController>,
+        ),
+    >,
+}
+
+impl MatchState<'_, '_> {
+    // None until the match has been set up
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        let mut balls: Vec<BallState> = self
+            .balls
+            .iter()
+            .map(
+                |(
+                    number,
+                    transform,
+                    velocity,
+                    spin,
+                    hit_streak,
+                    last_touch,
+                    sprite,
+                    serve,
+                    bonus,
+                )| {
+                    BallState {
+                        number: *number,
+                        translation: transform.translation,
+                        velocity: Vec2::new(velocity.x, velocity.y),
+                        spin: spin.0,
+                        hit_streak: hit_streak.count,
+                        last_touch: *last_touch,
+                        color: sprite.color,
+                        awaiting_serve: serve.cloned(),
+                        bonus,
+                    }
+                },
+            )
+            .collect();
+        if balls.is_empty() {
+            return None;
+        }
+        balls.sort_by_key(|ball| ball.number);
+        let mut pickups: Vec<(PowerUpKind, Vec2)> = self
+            .pickups
+            .iter()
+            .map(|(pickup, transform)| (pickup.kind, transform.translation.truncate()))
+            .collect();
+        pickups.sort_by(|a, b| {
+            (a.0, a.1.x, a.1.y)
+                .partial_cmp(&(b.0, b.1.x, b.1.y))
+                .expect("positions are never NaN")
+        });
+        Some(Snapshot {
+            gameplay_rng: self.rng.gameplay.clone(),
+            progress: self.progress.clone(),
+            rally: self.rally.clone(),
+            balls,
+            paddles: self
+                .paddles
+                .iter()
+                .map(|(paddle, transform, ai)| {
+                    (paddle.side, paddle.role, transform.translation, ai.cloned())
+                })
+                .collect(),
+            power_ups: self.power_ups.clone(),
+            pickups,
+        })
+    }
+}
+
+// Puts the match back the way it was. Transforms have to hold the simulated positions, not the blended ones.
+pub fn restore_snapshot(world: &mut World, snapshot: &Snapshot) {
+    world.resource_mut::<GameRng>().gameplay = snapshot.gameplay_rng.clone();
+    *world.resource_mut::<MatchProgress>() = snapshot.progress.clone();
+    *world.resource_mut::<PowerUps>() = snapshot.power_ups.clone();
+
+    *world.resource_mut::<Rally>() = snapshot.rally.clone();
+    let tuning = world.resource::<GameTuning>().clone();
+
+    // Balls that are still around are put back where they were, the rest are despawned or brought back
+    let mut balls = world.query_filtered::<(Entity, &BallNumber), With<Ball>>();
+    let mut existing = Vec::new();
+    let mut gone = Vec::new();
+    for (entity, number) in balls.iter(world) {
+        if snapshot.balls.iter().any(|ball| ball.number == *number) {
+            existing.push((entity, *number));
+        } else {
+            gone.push(entity);
+        }
+    }
+    for entity in gone {
+        world.despawn(entity);
+    }
+    for ball in &snapshot.balls {
+        let entity = match existing.iter().find(|(_, number)| *number == ball.number) {
+            Some((entity, _)) => *entity,
+            None => world
+                .spawn(ball_bundle(
+                    &tuning,
+                    ball.number,
+                    ball.translation,
+                    ball.velocity,
+                ))
+                .id(),
+        };
+        let mut entity = world.entity_mut(entity);
+        entity
+            .insert((
+                Velocity {
+                    x: ball.velocity.x,
+                    y: ball.velocity.y,
+                },
+                Spin(ball.spin),
+                HitStreak {
+                    count: ball.hit_streak,
+                },
+                ball.last_touch,
+            ))
+            .remove::<(AwaitingServe, BonusBall)>();
+        if let Some(serve) = &ball.awaiting_serve {
+            entity.insert(serve.clone());
+        }
+        if ball.bonus {
+            entity.insert(BonusBall);
+        }
+        if let Some(mut transform) = entity.get_mut::<Transform>() {
+            transform.translation = ball.translation;
+        }
+        if let Some(mut sprite) = entity.get_mut::<Sprite>() {
+            sprite.color = ball.color;
+        }
+    }
+
+    let mut paddles = world.query::<(Entity, &Paddle, &mut Transform)>();
+    let mut controllers = Vec::new();
+    for (entity, paddle, mut transform) in paddles.iter_mut(world) {
+        if let Some((_, _, translation, ai)) = snapshot
+            .paddles
+            .iter()
+            .find(|(side, role, _, _)| *side == paddle.side && *role == paddle.role)
+        {
+            transform.translation = *translation;
+            controllers.push((entity, ai.clone()));
+        }
+    }
+    for (entity, ai) in controllers {
+        if let Some(ai) = ai {
+            world.entity_mut(entity).insert(ai);
+        }
+    }
+
+    // Pickups come and go, so they're put back from scratch
+    let mut leftovers = world.query_filtered::<Entity, With<PowerUp>>();
+    let leftovers: Vec<Entity> = leftovers.iter(world).collect();
+    for entity in leftovers {
+        world.despawn(entity);
+    }
+    for (kind, position) in &snapshot.pickups {
+        world.spawn(pickup_bundle(*kind, *position));
+    }
+
+    let mut scores = world.query::<(&mut Score, &mut Text)>();
+    for (mut score, mut text) in scores.iter_mut(world) {
+        score.value = snapshot.progress.shown_score(score.side);
+        text.0 = score.value.to_string();
+    }
+}
+
+// Nothing should slide across the screen, sound off or burst into particles for all the ticks that were skipped
+pub fn settle_after_jump(world: &mut World) {
+    let mut moving = world.query::<(&Transform, &mut Interpolated)>();
+    for (transform, mut interpolated) in moving.iter_mut(world) {
+        *interpolated = Interpolated::at(transform.translation);
+    }
+    world.resource_mut::<Events<PlaySound>>().clear();
+    let mut particles = world.query_filtered::<Entity, With<Particle>>();
+    let particles: Vec<Entity> = particles.iter(world).collect();
+    for entity in particles {
+        world.despawn(entity);
+    }
+}
@@ -0,0 +1,458 @@
+// Sound effects, synthesized into PCM buffers at startup so there are no audio files to ship.
+// Gameplay just sends `PlaySound` events. They're only heard when built with the `audio` feature,
+// which is on by default.
+use crate::controls::{Action, ActionState};
+use crate::persist;
+use crate::states::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+#[cfg(feature = "audio")]
+use bevy::audio::{AddAudioSource, Decodable, Source, Volume};
+#[cfg(feature = "audio")]
+use std::{sync::Arc, time::Duration};
+
+const SETTINGS_FILE: &str = "audio.ron";
+pub const SAMPLE_RATE: u32 = 44_100;
+// Ball speed that plays hits at their natural pitch, the speed of a fresh serve
+const REFERENCE_BALL_SPEED: f32 = 320.0;
+const MIN_PITCH: f32 = 0.8;
+const MAX_PITCH: f32 = 1.8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoundEffect {
+    PaddleHit,
+    WallBounce,
+    Goal,
+    Frenzy,
+    PowerUp,
+    GameOver,
+}
+
+impl SoundEffect {
+    pub const ALL: [SoundEffect; 6] = [
+        SoundEffect::PaddleHit,
+        SoundEffect::WallBounce,
+        SoundEffect::Goal,
+        SoundEffect::Frenzy,
+        SoundEffect::PowerUp,
+        SoundEffect::GameOver,
+    ];
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlaySound {
+    pub effect: SoundEffect,
+    // Playback speed, 1.0 being the sound as synthesized
+    pub pitch: f32,
+}
+
+impl PlaySound {
+    pub fn new(effect: SoundEffect) -> Self {
+        PlaySound { effect, pitch: 1.0 }
+    }
+
+    // Faster balls make higher pitched hits
+    pub fn at_speed(effect: SoundEffect, ball_velocity: Vec2) -> Self {
+        PlaySound {
+            effect,
+            pitch: pitch_for_speed(ball_velocity.length()),
+        }
+    }
+}
+
+pub fn pitch_for_speed(speed: f32) -> f32 {
+    (speed / REFERENCE_BALL_SPEED)
+        .sqrt()
+        .clamp(MIN_PITCH, MAX_PITCH)
+}
+
+#[derive(Clone, Copy)]
+enum Wave {
+    Square,
+    Sine,
+}
+
+// A single note gliding from one frequency to another, with a quick attack and an exponential decay.
+// The tail is faded out so it never ends with a click.
+fn tone(wave: Wave, from_hz: f32, to_hz: f32, duration_secs: f32, decay: f32) -> Vec<f32> {
+    const AMPLITUDE: f32 = 0.3;
+    const ATTACK_SECS: f32 = 0.002;
+    const RELEASE_SECS: f32 = 0.01;
+
+    let sample_count = (duration_secs * SAMPLE_RATE as f32) as usize;
+    let mut phase = 0.0;
+    (0..sample_count)
+        .map(|index| {
+            let t = index as f32 / SAMPLE_RATE as f32;
+            let progress = index as f32 / sample_count as f32;
+            let frequency = from_hz + (to_hz - from_hz) * progress;
+            phase = (phase + frequency / SAMPLE_RATE as f32).fract();
+
+            let value = match wave {
+                Wave::Square if phase < 0.5 => 1.0,
+                Wave::Square => -1.0,
+                Wave::Sine => (phase * TAU).sin(),
+            };
+            let attack = (t / ATTACK_SECS).min(1.0);
+            let release = ((duration_secs - t) / RELEASE_SECS).min(1.0);
+            value * AMPLITUDE * attack * release * (-t * decay).exp()
+        })
+        .collect()
+}
+
+// Mono samples at SAMPLE_RATE. The blips use the frequencies of the original arcade cabinet.
+pub fn synthesize(effect: SoundEffect) -> Vec<f32> {
+    match effect {
+        SoundEffect::PaddleHit => tone(Wave::Square, 459.0, 459.0, 0.07, 20.0),
+        SoundEffect::WallBounce => tone(Wave::Square, 226.0, 226.0, 0.05, 30.0),
+        SoundEffect::Goal => tone(Wave::Square, 490.0, 490.0, 0.35, 5.0),
+        SoundEffect::Frenzy => tone(Wave::Sine, 300.0, 1200.0, 0.35, 3.0),
+        SoundEffect::PowerUp => tone(Wave::Sine, 700.0, 1400.0, 0.15, 8.0),
+        // Falling C major arpeggio
+        SoundEffect::GameOver => [523.25, 392.0, 261.63]
+            .into_iter()
+            .flat_map(|hz| tone(Wave::Square, hz, hz, 0.2, 6.0))
+            .collect(),
+    }
+}
+
+#[derive(Resource, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 1.0,
+            sfx: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    // Steps the Volume and SFX buttons go through
+    const VOLUME_STEPS: [f32; 4] = [1.0, 0.75, 0.5, 0.25];
+
+    // Saved settings, or the defaults if there aren't any (or they can't be read)
+    pub fn load() -> Self {
+        let Some(saved) = persist::load(SETTINGS_FILE) else {
+            return AudioSettings::default();
+        };
+        ron::from_str(&saved).unwrap_or_else(|error| {
+            eprintln!("Ignoring saved audio settings, couldn't read them: {error}");
+            AudioSettings::default()
+        })
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(serialized) => persist::save(SETTINGS_FILE, &serialized),
+            Err(error) => eprintln!("Couldn't save audio settings: {error}"),
+        }
+    }
+
+    // Parses `--volume <0..1>`, `--sfx-volume <0..1>` and `--mute` over the top of the saved settings
+    pub fn with_args(mut self, mut args: impl Iterator<Item = String>) -> Self {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--volume" | "--sfx-volume" => {
+                    match args.next().and_then(|value| value.parse::<f32>().ok()) {
+                        Some(volume) if (0.0..=1.0).contains(&volume) => {
+                            if arg == "--volume" {
+                                self.master = volume;
+                            } else {
+                                self.sfx = volume;
+                            }
+                        }
+                        _ => eprintln!("{arg} expects a number from 0 to 1"),
+                    }
+                }
+                "--mute" => self.muted = true,
+                _ => {}
+            }
+        }
+        self
+    }
+
+    // How loud sound effects actually play
+    pub fn sfx_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master * self.sfx
+        }
+    }
+
+    // Turns the master volume down a step, wrapping back to full. Unmutes as well.
+    pub fn step_master_volume(&mut self) {
+        self.master = Self::next_step(self.master);
+        self.muted = false;
+    }
+
+    // The same for sound effects on their own
+    pub fn step_sfx_volume(&mut self) {
+        self.sfx = Self::next_step(self.sfx);
+        self.muted = false;
+    }
+
+    fn next_step(volume: f32) -> f32 {
+        let current = Self::VOLUME_STEPS
+            .iter()
+            .position(|step| *step <= volume)
+            .unwrap_or(Self::VOLUME_STEPS.len() - 1);
+        Self::VOLUME_STEPS[(current + 1) % Self::VOLUME_STEPS.len()]
+    }
+
+    pub fn label(&self) -> String {
+        if self.muted {
+            "Volume: Muted".to_string()
+        } else {
+            format!("Volume: {:.0}%", self.master * 100.0)
+        }
+    }
+
+    pub fn sfx_label(&self) -> String {
+        format!("SFX: {:.0}%", self.sfx * 100.0)
+    }
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AudioSettings::load().with_args(std::env::args().skip(1)))
+            .add_event::<PlaySound>()
+            .add_systems(OnEnter(AppState::GameOver), play_game_over_sound)
+            .add_systems(
+                Update,
+                (
+                    // Rebinding Mute shouldn't also toggle it
+                    toggle_mute.run_if(not(in_state(AppState::Controls))),
+                    save_audio_settings,
+                )
+                    .chain(),
+            );
+
+        #[cfg(feature = "audio")]
+        {
+            app.add_audio_source::<PcmSound>()
+                .init_resource::<AudioUnlocked>()
+                .add_systems(Startup, synthesize_sounds)
+                .add_systems(Update, play_sounds.after(toggle_mute));
+            #[cfg(target_family = "wasm")]
+            app.add_systems(Update, unlock_audio.before(play_sounds));
+        }
+    }
+}
+
+pub fn play_game_over_sound(mut sounds: EventWriter<PlaySound>) {
+    sounds.write(PlaySound::new(SoundEffect::GameOver));
+}
+
+pub fn toggle_mute(actions: Res<ActionState>, mut settings: ResMut<AudioSettings>) {
+    if actions.any_just_pressed(Action::Mute) {
+        settings.muted = !settings.muted;
+    }
+}
+
+pub fn save_audio_settings(settings: Res<AudioSettings>) {
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+// A synthesized sound, played through bevy_audio like any other source
+#[cfg(feature = "audio")]
+#[derive(Asset, TypePath, Clone)]
+pub struct PcmSound {
+    samples: Arc<[f32]>,
+}
+
+#[cfg(feature = "audio")]
+pub struct PcmDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+#[cfg(feature = "audio")]
+impl Iterator for PcmDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Source for PcmDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Decodable for PcmSound {
+    type DecoderItem = f32;
+    type Decoder = PcmDecoder;
+
+    fn decoder(&self) -> PcmDecoder {
+        PcmDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+// One handle per effect, in the order of SoundEffect::ALL
+#[cfg(feature = "audio")]
+#[derive(Resource)]
+pub struct SoundBank(Vec<Handle<PcmSound>>);
+
+#[cfg(feature = "audio")]
+pub fn synthesize_sounds(mut commands: Commands, mut sounds: ResMut<Assets<PcmSound>>) {
+    let handles = SoundEffect::ALL
+        .into_iter()
+        .map(|effect| {
+            sounds.add(PcmSound {
+                samples: synthesize(effect).into(),
+            })
+        })
+        .collect();
+    commands.insert_resource(SoundBank(handles));
+}
+
+// Browsers keep audio locked until the page has been interacted with, so the web build
+// drops sounds until then rather than letting them pile up
+#[cfg(feature = "audio")]
+#[derive(Resource)]
+pub struct AudioUnlocked(pub bool);
+
+#[cfg(feature = "audio")]
+impl Default for AudioUnlocked {
+    fn default() -> Self {
+        AudioUnlocked(!cfg!(target_family = "wasm"))
+    }
+}
+
+#[cfg(all(feature = "audio", target_family = "wasm"))]
+pub fn unlock_audio(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    mut unlocked: ResMut<AudioUnlocked>,
+) {
+    if !unlocked.0
+        && (keyboard.get_just_pressed().next().is_some()
+            || mouse.get_just_pressed().next().is_some()
+            || touches.any_just_pressed())
+    {
+        unlocked.0 = true;
+    }
+}
+
+#[cfg(feature = "audio")]
+pub fn play_sounds(
+    mut commands: Commands,
+    mut sounds: EventReader<PlaySound>,
+    bank: Option<Res<SoundBank>>,
+    settings: Res<AudioSettings>,
+    unlocked: Res<AudioUnlocked>,
+) {
+    let volume = settings.sfx_volume();
+    let Some(bank) = bank.filter(|_| unlocked.0 && volume > 0.0) else {
+        sounds.clear();
+        return;
+    };
+    for sound in sounds.read() {
+        let index = SoundEffect::ALL
+            .iter()
+            .position(|effect| *effect == sound.effect)
+            .expect("every effect is in SoundEffect::ALL");
+        commands.spawn((
+            AudioPlayer(bank.0[index].clone()),
+            PlaybackSettings::DESPAWN
+                .with_volume(Volume::Linear(volume))
+                .with_speed(sound.pitch),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sounds_stay_in_range_and_end_silent() {
+        for effect in SoundEffect::ALL {
+            let samples = synthesize(effect);
+            assert!(!samples.is_empty(), "{effect:?} is empty");
+            assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+            assert!(samples.first().unwrap().abs() < 0.01);
+            assert!(samples.last().unwrap().abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn hits_get_higher_as_the_ball_speeds_up() {
+        assert_eq!(pitch_for_speed(REFERENCE_BALL_SPEED), 1.0);
+        assert!(pitch_for_speed(600.0) > pitch_for_speed(400.0));
+        assert_eq!(pitch_for_speed(0.0), MIN_PITCH);
+        assert_eq!(pitch_for_speed(10_000.0), MAX_PITCH);
+    }
+
+    #[test]
+    fn muting_silences_everything() {
+        let mut settings = AudioSettings {
+            master: 0.5,
+            sfx: 0.5,
+            muted: false,
+        };
+        assert_eq!(settings.sfx_volume(), 0.25);
+        settings.muted = true;
+        assert_eq!(settings.sfx_volume(), 0.0);
+    }
+
+    #[test]
+    fn volume_steps_wrap_around() {
+        let mut settings = AudioSettings::default();
+        settings.step_master_volume();
+        assert_eq!(settings.master, 0.75);
+        settings.master = 0.25;
+        settings.muted = true;
+        settings.step_master_volume();
+        assert_eq!(settings.master, 1.0);
+        assert!(!settings.muted);
+    }
+
+    #[test]
+    fn sfx_steps_leave_the_master_volume_alone() {
+        let mut settings = AudioSettings::default();
+        settings.step_sfx_volume();
+        settings.step_sfx_volume();
+        assert_eq!(settings.sfx, 0.5);
+        assert_eq!(settings.master, 1.0);
+        assert_eq!(settings.sfx_volume(), 0.5);
+    }
+}
@@ -0,0 +1,1129 @@
+use crate::menu::player_number;
+use crate::netplay::{
+    check_net_session, netplaying, NetRole, NetSession, NetSettings, PacketReader, MAGIC,
+    NET_VERSION,
+};
+use crate::rules::{MatchProgress, MatchRules, SideCounts};
+use crate::simulation::{GameplaySet, Interpolated};
+use crate::snapshot::MatchState;
+use crate::sound::{PlaySound, SoundEffect};
+use crate::states::{AppState, InGame};
+use crate::systems::{FRENZY_BALL_COLOR, NORMAL_BALL_COLOR};
+use crate::transport::{Transport, UdpTransport};
+use crate::tuning::GameTuning;
+use crate::{Ball, HitStreak, Paddle, Score, ScoreCelebration, Side, Velocity};
+use bevy::{
+    prelude::*,
+    ui::{Node, Val},
+};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{SocketAddr, UdpSocket};
+use std::ops::Range;
+use std::time::Duration;
+
+// Deltas are against the last keyframe, so this is also the longest anyone tuning in waits for a picture
+const KEYFRAME_INTERVAL_TICKS: u32 = 60;
+// Spectators stay this far behind the newest frame they have, to ride out packets arriving unevenly
+const JITTER_BUFFER: Duration = Duration::from_millis(100);
+// Spectators say so this often until the broadcast answers, then every second to stay on the list
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+const KEEP_WATCHING_INTERVAL: Duration = Duration::from_secs(1);
+const BROADCAST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_UPDATES_PER_PACKET: usize = 32;
+// Frames further apart than this aren't blended, something jumped in between
+const MAX_BLEND_TICKS: u32 = 8;
+
+// What spectators see of the match on one tick. Only what's on screen, they don't simulate anything.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct StateFrame {
+    pub tick: u32,
+    pub ball: Vec2,
+    pub ball_velocity: Vec2,
+    // Left then right
+    pub paddles: [f32; 2],
+    pub points: SideCounts,
+    pub games: SideCounts,
+    pub sets: SideCounts,
+    pub hit_streak: u32,
+    pub frenzy: bool,
+    pub winner: Option<Side>,
+}
+
+impl StateFrame {
+    pub fn paddle_y(&self, side: Side) -> f32 {
+        match side {
+            Side::Left => self.paddles[0],
+            Side::Right => self.paddles[1],
+            // Only two player matches go out over the network
+            Side::Top | Side::Bottom => 0.0,
+        }
+    }
+
+    // Positions part way to `next`, everything else as it is now
+    fn blend(&self, next: &StateFrame, tick: u32) -> StateFrame {
+        let t = (tick - self.tick) as f32 / (next.tick - self.tick) as f32;
+        StateFrame {
+            tick,
+            ball: self.ball.lerp(next.ball, t),
+            paddles: [
+                self.paddles[0] + (next.paddles[0] - self.paddles[0]) * t,
+                self.paddles[1] + (next.paddles[1] - self.paddles[1]) * t,
+            ],
+            ..*self
+        }
+    }
+
+    fn standing(&self, side: Side) -> (u32, u32, u32) {
+        (
+            self.sets.get(side),
+            self.games.get(side),
+            self.points.get(side),
+        )
+    }
+}
+
+// Which of PackedFrame's values go together. A group is sent whenever any of its values changed.
+const GROUPS: [Range<usize>; 6] = [0..2, 2..4, 4..6, 6..12, 12..13, 13..14];
+const ALL_GROUPS: u8 = 0b11_1111;
+const FRENZY_FLAG: u16 = 1 << 0;
+const LEFT_WON_FLAG: u16 = 1 << 1;
+const RIGHT_WON_FLAG: u16 = 1 << 2;
+
+// A frame squeezed down for sending: ball, ball velocity, paddles, scores, hit streak and flags.
+// Positions are in sixteenths of a unit and speeds in quarters, plenty to watch by.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PackedFrame([u16; 14]);
+
+impl PackedFrame {
+    pub fn pack(frame: &StateFrame) -> Self {
+        let position = |value: f32| (value * 16.0).round() as i16 as u16;
+        let speed = |value: f32| (value * 4.0).round() as i16 as u16;
+        let count = |value: u32| value.min(u16::MAX as u32) as u16;
+        let mut flags = 0;
+        if frame.frenzy {
+            flags |= FRENZY_FLAG;
+        }
+        match frame.winner {
+            Some(Side::Left) => flags |= LEFT_WON_FLAG,
+            Some(Side::Right) => flags |= RIGHT_WON_FLAG,
+            // Only two player matches go out over the network
+            Some(Side::Top | Side::Bottom) | None => {}
+        }
+        PackedFrame([
+            position(frame.ball.x),
+            position(frame.ball.y),
+            speed(frame.ball_velocity.x),
+            speed(frame.ball_velocity.y),
+            position(frame.paddles[0]),
+            position(frame.paddles[1]),
+            count(frame.points.left),
+            count(frame.points.right),
+            count(frame.games.left),
+            count(frame.games.right),
+            count(frame.sets.left),
+            count(frame.sets.right),
+            count(frame.hit_streak),
+            flags,
+        ])
+    }
+
+    pub fn unpack(&self, tick: u32) -> StateFrame {
+        let values = self.0;
+        let position = |index: usize| values[index] as i16 as f32 / 16.0;
+        let speed = |index: usize| values[index] as i16 as f32 / 4.0;
+        let counts = |index: usize| SideCounts {
+            left: values[index].into(),
+            right: values[index + 1].into(),
+            ..default()
+        };
+        let flags = values[13];
+        StateFrame {
+            tick,
+            ball: Vec2::new(position(0), position(1)),
+            ball_velocity: Vec2::new(speed(2), speed(3)),
+            paddles: [position(4), position(5)],
+            points: counts(6),
+            games: counts(8),
+            sets: counts(10),
+            hit_streak: values[12].into(),
+            frenzy: flags & FRENZY_FLAG != 0,
+            winner: if flags & LEFT_WON_FLAG != 0 {
+                Some(Side::Left)
+            } else if flags & RIGHT_WON_FLAG != 0 {
+                Some(Side::Right)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn changed_since(&self, keyframe: &PackedFrame) -> u8 {
+        GROUPS
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| self.0[(*range).clone()] != keyframe.0[(*range).clone()])
+            .fold(0, |groups, (index, _)| groups | 1 << index)
+    }
+
+    // Takes these groups from `delta`
+    fn merge(&mut self, delta: &PackedFrame, groups: u8) {
+        for (index, range) in GROUPS.iter().enumerate() {
+            if groups & 1 << index != 0 {
+                self.0[range.clone()].copy_from_slice(&delta.0[range.clone()]);
+            }
+        }
+    }
+
+    fn write(&self, groups: u8, bytes: &mut Vec<u8>) {
+        for (index, range) in GROUPS.iter().enumerate() {
+            if groups & 1 << index != 0 {
+                for value in &self.0[range.clone()] {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    fn read(reader: &mut PacketReader, groups: u8) -> Option<Self> {
+        let mut frame = PackedFrame::default();
+        for (index, range) in GROUPS.iter().enumerate() {
+            if groups & 1 << index != 0 {
+                for value in &mut frame.0[range.clone()] {
+                    *value = u16::from_le_bytes(reader.array()?);
+                }
+            }
+        }
+        Some(frame)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameUpdate {
+    // Everything, for tuning in from
+    Key {
+        tick: u32,
+        frame: PackedFrame,
+    },
+    // Only the groups that changed since the keyframe `since` ticks earlier
+    Delta {
+        tick: u32,
+        since: u8,
+        changed: u8,
+        frame: PackedFrame,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum BroadcastPacket {
+    // A spectator tuning in, and then staying on the list
+    Watch {
+        version: u16,
+    },
+    // The broadcast's answer, with what it takes to show the match the way it's being played
+    Header {
+        timestep: Duration,
+        delay: Duration,
+        tuning: GameTuning,
+        rules: MatchRules,
+    },
+    Frames(Vec<FrameUpdate>),
+    // The broadcast is over, or the spectator stopped watching
+    Leave,
+}
+
+// Numbered on from netplay's packets, so neither mistakes the other's for its own
+const WATCH_TAG: u8 = 16;
+const HEADER_TAG: u8 = 17;
+const FRAMES_TAG: u8 = 18;
+const LEAVE_TAG: u8 = 19;
+
+impl BroadcastPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match self {
+            BroadcastPacket::Watch { version } => {
+                bytes.push(WATCH_TAG);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            BroadcastPacket::Header {
+                timestep,
+                delay,
+                tuning,
+                rules,
+            } => {
+                bytes.push(HEADER_TAG);
+                bytes.extend_from_slice(&(timestep.as_nanos() as u64).to_le_bytes());
+                bytes.extend_from_slice(&(delay.as_millis() as u32).to_le_bytes());
+                for text in [
+                    ron::to_string(tuning).expect("tuning always serializes"),
+                    ron::to_string(rules).expect("rules always serialize"),
+                ] {
+                    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(text.as_bytes());
+                }
+            }
+            BroadcastPacket::Frames(updates) => {
+                bytes.push(FRAMES_TAG);
+                bytes.push(updates.len() as u8);
+                for update in updates {
+                    match update {
+                        FrameUpdate::Key { tick, frame } => {
+                            bytes.push(0);
+                            bytes.extend_from_slice(&tick.to_le_bytes());
+                            frame.write(ALL_GROUPS, &mut bytes);
+                        }
+                        FrameUpdate::Delta {
+                            tick,
+                            since,
+                            changed,
+                            frame,
+                        } => {
+                            bytes.push(1);
+                            bytes.extend_from_slice(&tick.to_le_bytes());
+                            bytes.extend_from_slice(&[*since, *changed]);
+                            frame.write(*changed, &mut bytes);
+                        }
+                    }
+                }
+            }
+            BroadcastPacket::Leave => bytes.push(LEAVE_TAG),
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<BroadcastPacket> {
+        let mut reader = PacketReader::new(bytes)?;
+        let packet = match reader.u8()? {
+            WATCH_TAG => BroadcastPacket::Watch {
+                version: u16::from_le_bytes(reader.array()?),
+            },
+            HEADER_TAG => BroadcastPacket::Header {
+                timestep: Duration::from_nanos(u64::from_le_bytes(reader.array()?)),
+                delay: Duration::from_millis(reader.u32()?.into()),
+                tuning: ron::from_str(&reader.string()?).ok()?,
+                rules: ron::from_str(&reader.string()?).ok()?,
+            },
+            FRAMES_TAG => {
+                let count = reader.u8()?;
+                let mut updates = Vec::with_capacity(count.into());
+                for _ in 0..count {
+                    let update = match reader.u8()? {
+                        0 => FrameUpdate::Key {
+                            tick: reader.u32()?,
+                            frame: PackedFrame::read(&mut reader, ALL_GROUPS)?,
+                        },
+                        _ => {
+                            let tick = reader.u32()?;
+                            let since = reader.u8()?;
+                            let changed = reader.u8()?;
+                            FrameUpdate::Delta {
+                                tick,
+                                since,
+                                changed,
+                                frame: PackedFrame::read(&mut reader, changed)?,
+                            }
+                        }
+                    };
+                    updates.push(update);
+                }
+                BroadcastPacket::Frames(updates)
+            }
+            LEAVE_TAG => BroadcastPacket::Leave,
+            _ => return None,
+        };
+        Some(packet)
+    }
+}
+
+// Turns frames into updates, starting a new keyframe whenever the last one's too old for a delta
+#[derive(Default)]
+pub struct FrameEncoder {
+    keyframe: Option<(u32, PackedFrame)>,
+}
+
+impl FrameEncoder {
+    pub fn encode(&mut self, frame: &StateFrame) -> FrameUpdate {
+        let packed = PackedFrame::pack(frame);
+        match self.keyframe {
+            Some((key_tick, keyframe))
+                if frame.tick >= key_tick && frame.tick - key_tick < KEYFRAME_INTERVAL_TICKS =>
+            {
+                FrameUpdate::Delta {
+                    tick: frame.tick,
+                    since: (frame.tick - key_tick) as u8,
+                    changed: packed.changed_since(&keyframe),
+                    frame: packed,
+                }
+            }
+            _ => {
+                self.keyframe = Some((frame.tick, packed));
+                FrameUpdate::Key {
+                    tick: frame.tick,
+                    frame: packed,
+                }
+            }
+        }
+    }
+}
+
+// Turns updates back into frames. Deltas whose keyframe never arrived are dropped.
+#[derive(Default)]
+pub struct FrameDecoder {
+    keyframes: BTreeMap<u32, PackedFrame>,
+}
+
+impl FrameDecoder {
+    pub fn decode(&mut self, update: &FrameUpdate) -> Option<StateFrame> {
+        match *update {
+            FrameUpdate::Key { tick, frame } => {
+                self.keyframes.insert(tick, frame);
+                // A few spare in case packets arrive out of order
+                while self.keyframes.len() > 4 {
+                    self.keyframes.pop_first();
+                }
+                Some(frame.unpack(tick))
+            }
+            FrameUpdate::Delta {
+                tick,
+                since,
+                changed,
+                frame,
+            } => {
+                let mut full = *self.keyframes.get(&tick.checked_sub(since.into())?)?;
+                full.merge(&frame, changed);
+                Some(full.unpack(tick))
+            }
+        }
+    }
+}
+
+// This end's broadcast of the match to spectators, opened with `--spectators <port>`
+#[derive(Resource)]
+pub struct Broadcast {
+    socket: UdpSocket,
+    header: BroadcastPacket,
+    delay: Duration,
+    // Everyone watching, and when they were last heard from
+    spectators: HashMap<SocketAddr, Duration>,
+    // Frames held back by the broadcast delay, with when they're due to go
+    queued: VecDeque<(Duration, StateFrame)>,
+    last_tick: Option<u32>,
+    encoder: FrameEncoder,
+}
+
+impl Broadcast {
+    fn send_to_all(&self, packet: &BroadcastPacket) {
+        let bytes = packet.encode();
+        for spectator in self.spectators.keys() {
+            // A full send buffer is just more packet loss
+            let _ = self.socket.send_to(&bytes, spectator);
+        }
+    }
+
+    fn send_frames(&mut self, frames: Vec<StateFrame>) {
+        let updates: Vec<FrameUpdate> = frames
+            .iter()
+            .map(|frame| self.encoder.encode(frame))
+            .collect();
+        for chunk in updates.chunks(MAX_UPDATES_PER_PACKET) {
+            self.send_to_all(&BroadcastPacket::Frames(chunk.to_vec()));
+        }
+    }
+}
+
+// How the rallies have gone so far, from what spectators have seen
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct RallyStats {
+    // Returns in the rally that's on now
+    pub current: u32,
+    pub longest: u32,
+    pub points_played: u32,
+    pub total_returns: u32,
+    pub frenzies: u32,
+    pub top_speed: f32,
+}
+
+impl RallyStats {
+    pub fn record(&mut self, previous: &StateFrame, shown: &StateFrame) {
+        if returned(previous, shown) {
+            self.current += 1;
+            self.longest = self.longest.max(self.current);
+        }
+        if shown.frenzy && !previous.frenzy {
+            self.frenzies += 1;
+        }
+        if scorer(previous, shown).is_some() {
+            self.points_played += 1;
+            self.total_returns += self.current;
+            self.current = 0;
+        }
+        self.top_speed = self.top_speed.max(shown.ball_velocity.length());
+    }
+
+    pub fn average(&self) -> f32 {
+        if self.points_played == 0 {
+            return 0.0;
+        }
+        self.total_returns as f32 / self.points_played as f32
+    }
+}
+
+// A paddle sent the ball back
+pub fn returned(previous: &StateFrame, shown: &StateFrame) -> bool {
+    previous.ball_velocity.x * shown.ball_velocity.x < 0.0
+}
+
+// Whoever won a point, a game or a set between the two frames
+pub fn scorer(previous: &StateFrame, shown: &StateFrame) -> Option<Side> {
+    [Side::Left, Side::Right]
+        .into_iter()
+        .find(|side| shown.standing(*side) > previous.standing(*side))
+}
+
+// What would have been heard on the way from one frame to the next
+fn sounds_between(previous: &StateFrame, shown: &StateFrame) -> Vec<SoundEffect> {
+    let mut sounds = Vec::new();
+    if scorer(previous, shown).is_some() {
+        sounds.push(SoundEffect::Goal);
+    } else if returned(previous, shown) {
+        sounds.push(SoundEffect::PaddleHit);
+    } else if previous.ball_velocity.y * shown.ball_velocity.y < 0.0 {
+        sounds.push(SoundEffect::WallBounce);
+    }
+    if shown.frenzy && !previous.frenzy {
+        sounds.push(SoundEffect::Frenzy);
+    }
+    sounds
+}
+
+// Watching someone else's match, started with `--spectate <ip:port>`
+#[derive(Resource)]
+pub struct Watching {
+    pub address: String,
+    transport: UdpTransport,
+    last_heard: Duration,
+    last_watch: Option<Duration>,
+    // Settings the header swapped out, put back once done watching
+    before: Option<(Duration, GameTuning, MatchRules)>,
+    pub delay: Duration,
+    decoder: FrameDecoder,
+    frames: BTreeMap<u32, StateFrame>,
+    playhead: Option<u32>,
+    shown: Option<StateFrame>,
+    // The broadcast's over, so what's left plays out and that's it
+    ended: bool,
+    pub stats: RallyStats,
+    pub show_stats: bool,
+}
+
+pub fn spectating(watching: Option<Res<Watching>>) -> bool {
+    watching.is_some()
+}
+
+#[derive(Component)]
+pub struct WatchHud;
+
+#[derive(Component)]
+pub struct RallyStatsPanel;
+
+pub struct SpectatePlugin;
+
+impl Plugin for SpectatePlugin {
+    fn build(&self, app: &mut App) {
+        // Spectators are shown the match, they don't run it
+        app.configure_sets(
+            FixedUpdate,
+            (
+                GameplaySet::Input,
+                GameplaySet::Physics,
+                GameplaySet::Scoring,
+            )
+                .run_if(not(spectating)),
+        )
+        .add_systems(OnEnter(AppState::Connecting), start_watching)
+        .add_systems(OnEnter(InGame), (start_broadcast, spawn_watch_hud))
+        .add_systems(OnEnter(AppState::MainMenu), (end_broadcast, stop_watching))
+        .add_systems(
+            Update,
+            (
+                receive_broadcast.run_if(spectating),
+                (toggle_rally_stats, update_watch_hud)
+                    .chain()
+                    .run_if(in_state(InGame).and(spectating)),
+            ),
+        )
+        .add_systems(
+            RunFixedMainLoop,
+            broadcast_match
+                .after(check_net_session)
+                .in_set(RunFixedMainLoopSystem::BeforeFixedMainLoop)
+                .run_if(resource_exists::<Broadcast>.and(netplaying)),
+        )
+        .add_systems(
+            FixedUpdate,
+            play_broadcast.run_if(in_state(AppState::Playing).and(spectating)),
+        );
+    }
+}
+
+pub fn start_broadcast(
+    mut commands: Commands,
+    settings: Res<NetSettings>,
+    session: Option<Res<NetSession>>,
+    fixed_time: Res<Time<Fixed>>,
+    tuning: Res<GameTuning>,
+    rules: Res<MatchRules>,
+) {
+    let (Some(port), Some(_)) = (settings.spectator_port, session) else {
+        return;
+    };
+    let socket = match UdpSocket::bind(("0.0.0.0", port)).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => socket,
+        Err(error) => {
+            eprintln!("Couldn't open port {port} for spectators: {error}");
+            return;
+        }
+    };
+    println!("Spectators can watch on port {port}");
+    commands.insert_resource(Broadcast {
+        socket,
+        header: BroadcastPacket::Header {
+            timestep: fixed_time.timestep(),
+            delay: settings.broadcast_delay,
+            tuning: tuning.clone(),
+            rules: *rules,
+        },
+        delay: settings.broadcast_delay,
+        spectators: HashMap::new(),
+        queued: VecDeque::new(),
+        last_tick: None,
+        encoder: FrameEncoder::default(),
+    });
+}
+
+// Sends spectators what both ends are sure of, once the broadcast delay's gone by
+pub fn broadcast_match(
+    mut broadcast: ResMut<Broadcast>,
+    session: Res<NetSession>,
+    state: MatchState,
+    real_time: Res<Time<Real>>,
+) {
+    let now = real_time.elapsed();
+    let mut buffer = [0; 2048];
+    while let Ok((len, from)) = broadcast.socket.recv_from(&mut buffer) {
+        match BroadcastPacket::decode(&buffer[..len]) {
+            Some(BroadcastPacket::Watch { version }) if version == NET_VERSION => {
+                if broadcast.spectators.insert(from, now).is_none() {
+                    println!("{from} is watching");
+                }
+                let _ = broadcast.socket.send_to(&broadcast.header.encode(), from);
+            }
+            Some(BroadcastPacket::Leave) if broadcast.spectators.remove(&from).is_some() => {
+                println!("{from} stopped watching");
+            }
+            _ => {}
+        }
+    }
+    broadcast
+        .spectators
+        .retain(|_, last_heard| now.saturating_sub(*last_heard) <= BROADCAST_TIMEOUT);
+
+    if let Some((tick, snapshot)) = session.settled_snapshot(&state) {
+        let tick = tick as u32;
+        if broadcast.last_tick.is_none_or(|last| tick > last) {
+            let due = now + broadcast.delay;
+            broadcast.queued.push_back((due, snapshot.frame(tick)));
+            broadcast.last_tick = Some(tick);
+        }
+    }
+
+    let mut frames = Vec::new();
+    while broadcast.queued.front().is_some_and(|(due, _)| *due <= now) {
+        frames.extend(broadcast.queued.pop_front().map(|(_, frame)| frame));
+    }
+    broadcast.send_frames(frames);
+}
+
+// The match is over, so there's no reason to hold anything back any more
+pub fn end_broadcast(mut commands: Commands, broadcast: Option<ResMut<Broadcast>>) {
+    let Some(mut broadcast) = broadcast else {
+        return;
+    };
+    let frames = broadcast.queued.drain(..).map(|(_, frame)| frame).collect();
+    broadcast.send_frames(frames);
+    broadcast.send_to_all(&BroadcastPacket::Leave);
+    commands.remove_resource::<Broadcast>();
+}
+
+pub fn start_watching(
+    mut commands: Commands,
+    settings: Res<NetSettings>,
+    real_time: Res<Time<Real>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(NetRole::Spectate { address }) = &settings.role else {
+        return;
+    };
+    match UdpTransport::join(address) {
+        Ok(transport) => commands.insert_resource(Watching {
+            address: address.clone(),
+            transport,
+            last_heard: real_time.elapsed(),
+            last_watch: None,
+            before: None,
+            delay: Duration::ZERO,
+            decoder: FrameDecoder::default(),
+            frames: BTreeMap::new(),
+            playhead: None,
+            shown: None,
+            ended: false,
+            stats: RallyStats::default(),
+            show_stats: false,
+        }),
+        Err(error) => {
+            eprintln!("Couldn't open a network connection: {error}");
+            next_state.set(AppState::MainMenu);
+        }
+    }
+}
+
+// Keeps up with the broadcast, and starts showing the match once there's a keyframe to go from
+#[allow(clippy::too_many_arguments)]
+pub fn receive_broadcast(
+    mut watching: ResMut<Watching>,
+    real_time: Res<Time<Real>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let now = real_time.elapsed();
+    let watching = &mut *watching;
+    // UDP never fails for good, it just goes quiet
+    let packets = watching.transport.receive().unwrap_or_default();
+    for packet in packets
+        .iter()
+        .filter_map(|bytes| BroadcastPacket::decode(bytes))
+    {
+        watching.last_heard = now;
+        match packet {
+            BroadcastPacket::Header {
+                timestep,
+                delay,
+                tuning: match_tuning,
+                rules: match_rules,
+            } if watching.before.is_none() => {
+                watching.before = Some((fixed_time.timestep(), tuning.clone(), *rules));
+                fixed_time.set_timestep(timestep);
+                *tuning = match_tuning;
+                *rules = match_rules;
+                watching.delay = delay;
+            }
+            // Nothing to show them against until the header's in
+            BroadcastPacket::Frames(updates) if watching.before.is_some() => {
+                for update in &updates {
+                    if let Some(frame) = watching.decoder.decode(update) {
+                        // Anything older than what's on screen is too late
+                        if watching
+                            .playhead
+                            .is_none_or(|playhead| frame.tick > playhead)
+                        {
+                            watching.frames.insert(frame.tick, frame);
+                        }
+                    }
+                }
+            }
+            BroadcastPacket::Leave => watching.ended = true,
+            _ => {}
+        }
+    }
+
+    let interval = if watching.before.is_some() {
+        KEEP_WATCHING_INTERVAL
+    } else {
+        WATCH_INTERVAL
+    };
+    if !watching.ended
+        && watching
+            .last_watch
+            .is_none_or(|last| now >= last + interval)
+    {
+        let watch = BroadcastPacket::Watch {
+            version: NET_VERSION,
+        };
+        watching.transport.send(&watch.encode());
+        watching.last_watch = Some(now);
+    }
+
+    match state.get() {
+        AppState::Connecting if watching.ended => {
+            eprintln!("The match being broadcast is over");
+            next_state.set(AppState::MainMenu);
+        }
+        AppState::Connecting if !watching.frames.is_empty() => {
+            println!("Watching the match at {}", watching.address);
+            next_state.set(AppState::Playing);
+        }
+        AppState::Playing if now.saturating_sub(watching.last_heard) > BROADCAST_TIMEOUT => {
+            eprintln!("Lost the broadcast");
+            next_state.set(AppState::MainMenu);
+        }
+        _ => {}
+    }
+}
+
+// Shows the frame for this tick, a little behind the newest one to keep things smooth
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn play_broadcast(
+    mut commands: Commands,
+    mut watching: ResMut<Watching>,
+    fixed_time: Res<Time<Fixed>>,
+    mut balls: Query<
+        (
+            &mut Transform,
+            &mut Interpolated,
+            &mut Velocity,
+            &mut HitStreak,
+            &mut Sprite,
+        ),
+        With<Ball>,
+    >,
+    mut paddles: Query<(&Paddle, &mut Transform, &mut Interpolated), Without<Ball>>,
+    mut scores: Query<(Entity, &mut Score, &mut Text, &TextColor)>,
+    mut progress: ResMut<MatchProgress>,
+    mut sounds: EventWriter<PlaySound>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let watching = &mut *watching;
+    let (Some(&first), Some(&latest)) = (
+        watching.frames.keys().next(),
+        watching.frames.keys().next_back(),
+    ) else {
+        return;
+    };
+    let buffer = (JITTER_BUFFER.as_secs_f64() / fixed_time.timestep().as_secs_f64()).ceil() as u32;
+    let (playhead, jumped) = match watching.playhead {
+        Some(playhead) if latest <= playhead + 3 * buffer => ((playhead + 1).min(latest), false),
+        // Just tuned in, or fell way behind
+        _ => (latest.saturating_sub(buffer).max(first), true),
+    };
+    watching.playhead = Some(playhead);
+
+    let Some((&at, &frame)) = watching.frames.range(..=playhead).next_back() else {
+        return;
+    };
+    let shown = match watching.frames.range(playhead + 1..).next() {
+        Some((_, next))
+            if next.tick - frame.tick <= MAX_BLEND_TICKS && scorer(&frame, next).is_none() =>
+        {
+            frame.blend(next, playhead)
+        }
+        _ => frame,
+    };
+    watching.frames.retain(|tick, _| *tick >= at);
+
+    for (mut transform, mut interpolated, mut velocity, mut hit_streak, mut sprite) in
+        balls.iter_mut()
+    {
+        transform.translation = shown.ball.extend(transform.translation.z);
+        velocity.x = shown.ball_velocity.x;
+        velocity.y = shown.ball_velocity.y;
+        hit_streak.count = shown.hit_streak;
+        sprite.color = if shown.frenzy {
+            FRENZY_BALL_COLOR
+        } else {
+            NORMAL_BALL_COLOR
+        };
+        if jumped {
+            *interpolated = Interpolated::at(transform.translation);
+        }
+    }
+    for (paddle, mut transform, mut interpolated) in paddles.iter_mut() {
+        transform.translation.y = shown.paddle_y(paddle.side);
+        if jumped {
+            *interpolated = Interpolated::at(transform.translation);
+        }
+    }
+
+    let previous = watching.shown.replace(shown);
+    let scored = previous.and_then(|previous| scorer(&previous, &shown));
+    if let Some(previous) = previous {
+        watching.stats.record(&previous, &shown);
+        for sound in sounds_between(&previous, &shown) {
+            sounds.write(PlaySound::new(sound));
+        }
+    }
+    if previous.is_none_or(|previous| previous.standing(Side::Left) != shown.standing(Side::Left))
+        || previous
+            .is_none_or(|previous| previous.standing(Side::Right) != shown.standing(Side::Right))
+    {
+        progress.points = shown.points;
+        progress.games = shown.games;
+        progress.sets = shown.sets;
+        for (entity, mut score, mut text, text_color) in scores.iter_mut() {
+            score.value = shown.points.get(score.side);
+            text.0 = score.value.to_string();
+            if scored == Some(score.side) {
+                commands.entity(entity).insert(ScoreCelebration {
+                    timer: Timer::from_seconds(
+                        crate::effects_system::CELEBRATION_DURATION_SECS,
+                        TimerMode::Once,
+                    ),
+                    original_color: text_color.0,
+                    scored_side: score.side,
+                });
+            }
+        }
+    }
+
+    if shown.winner.is_some() {
+        progress.winner = shown.winner;
+        next_state.set(AppState::GameOver);
+    } else if watching.ended && playhead == latest {
+        eprintln!("The broadcast ended before the match did");
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+pub fn stop_watching(
+    mut commands: Commands,
+    watching: Option<ResMut<Watching>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut tuning: ResMut<GameTuning>,
+    mut rules: ResMut<MatchRules>,
+) {
+    let Some(mut watching) = watching else {
+        return;
+    };
+    watching.transport.send(&BroadcastPacket::Leave.encode());
+    if let Some((timestep, before_tuning, before_rules)) = watching.before.take() {
+        fixed_time.set_timestep(timestep);
+        *tuning = before_tuning;
+        *rules = before_rules;
+    }
+    commands.remove_resource::<Watching>();
+}
+
+pub fn spawn_watch_hud(mut commands: Commands, watching: Option<Res<Watching>>) {
+    if watching.is_none() {
+        return;
+    }
+    commands.spawn((
+        WatchHud,
+        StateScoped(InGame),
+        Text::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.8, 0.8, 0.8)), // Light Gray
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(20.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+    ));
+    commands.spawn((
+        RallyStatsPanel,
+        StateScoped(InGame),
+        Text::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(20.0),
+            top: Val::Px(100.0),
+            padding: UiRect::all(Val::Px(10.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+pub fn toggle_rally_stats(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut watching: ResMut<Watching>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyH) {
+        watching.show_stats = !watching.show_stats;
+    }
+}
+
+pub fn update_watch_hud(
+    watching: Res<Watching>,
+    mut hud: Query<&mut Text, (With<WatchHud>, Without<RallyStatsPanel>)>,
+    mut panels: Query<(&mut Text, &mut Visibility), With<RallyStatsPanel>>,
+) {
+    let line = format!(
+        "Watching {}, {:.1} s behind  H for rally stats, M to leave",
+        watching.address,
+        watching.delay.as_secs_f32()
+    );
+    for mut text in hud.iter_mut() {
+        if text.0 != line {
+            text.0 = line.clone();
+        }
+    }
+
+    let stats = &watching.stats;
+    let mut lines = vec![
+        format!("This rally: {} returns", stats.current),
+        format!("Longest rally: {}", stats.longest),
+        format!(
+            "Average rally: {:.1} over {} points",
+            stats.average(),
+            stats.points_played
+        ),
+        format!("Frenzies: {}", stats.frenzies),
+        format!("Top speed: {:.0}", stats.top_speed),
+    ];
+    if let Some(shown) = &watching.shown {
+        lines.push(format!("Ball speed: {:.0}", shown.ball_velocity.length()));
+        if shown.hit_streak > 0 {
+            lines.push(format!("Hit streak: {}", shown.hit_streak));
+        }
+        if let Some(winner) = shown.winner {
+            lines.push(format!("P{} wins", player_number(winner)));
+        }
+    }
+    let stats_text = lines.join("\n");
+    let visibility = if watching.show_stats {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (mut text, mut panel_visibility) in panels.iter_mut() {
+        if text.0 != stats_text {
+            text.0 = stats_text.clone();
+        }
+        panel_visibility.set_if_neq(visibility);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tick: u32) -> StateFrame {
+        StateFrame {
+            tick,
+            ball: Vec2::new(120.5, -33.25),
+            ball_velocity: Vec2::new(400.0, -150.75),
+            paddles: [10.0, -250.0625],
+            points: SideCounts {
+                left: 3,
+                right: 7,
+                ..default()
+            },
+            hit_streak: 2,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn frames_survive_packing() {
+        let mut original = frame(9);
+        original.frenzy = true;
+        original.winner = Some(Side::Right);
+        assert_eq!(PackedFrame::pack(&original).unpack(9), original);
+        // Rounded to a sixteenth
+        let mut off_grid = frame(9);
+        off_grid.ball.x = 0.3;
+        assert_eq!(PackedFrame::pack(&off_grid).unpack(9).ball.x, 0.3125);
+    }
+
+    #[test]
+    fn deltas_only_carry_what_changed() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+        let first = frame(100);
+        let mut second = frame(102);
+        second.ball.x += 8.0;
+
+        let key = encoder.encode(&first);
+        assert!(matches!(key, FrameUpdate::Key { .. }));
+        let delta = encoder.encode(&second);
+        assert!(matches!(delta, FrameUpdate::Delta { changed: 1, .. }));
+        let key_len = BroadcastPacket::Frames(vec![key]).encode().len();
+        let delta_len = BroadcastPacket::Frames(vec![delta]).encode().len();
+        assert!(delta_len < key_len / 2, "{delta_len} vs {key_len}");
+
+        let packet = BroadcastPacket::Frames(vec![key, delta]).encode();
+        let Some(BroadcastPacket::Frames(updates)) = BroadcastPacket::decode(&packet) else {
+            panic!("frames didn't decode");
+        };
+        let decoded: Vec<_> = updates.iter().filter_map(|u| decoder.decode(u)).collect();
+        assert_eq!(decoded, vec![first, second]);
+
+        // A fresh keyframe once the last one's too old
+        let later = frame(100 + KEYFRAME_INTERVAL_TICKS);
+        assert!(matches!(encoder.encode(&later), FrameUpdate::Key { .. }));
+        // Tuning in partway through, deltas are no use until a keyframe turns up
+        assert_eq!(FrameDecoder::default().decode(&delta), None);
+    }
+
+    #[test]
+    fn header_survives_a_round_trip() {
+        let header = BroadcastPacket::Header {
+            timestep: Duration::from_nanos(8_333_333),
+            delay: Duration::from_millis(1500),
+            tuning: GameTuning::default(),
+            rules: MatchRules::default(),
+        };
+        for packet in [
+            header,
+            BroadcastPacket::Watch { version: 1 },
+            BroadcastPacket::Leave,
+        ] {
+            assert_eq!(BroadcastPacket::decode(&packet.encode()), Some(packet));
+        }
+        // The players' own packets aren't mistaken for a broadcast
+        assert_eq!(
+            BroadcastPacket::decode(&crate::netplay::Packet::Bye.encode()),
+            None
+        );
+    }
+
+    #[test]
+    fn rallies_are_counted_from_returns() {
+        let mut stats = RallyStats::default();
+        let serve = frame(0);
+        let mut returned = serve;
+        returned.ball_velocity.x = -400.0;
+        let mut back = returned;
+        back.ball_velocity.x = 500.0;
+        stats.record(&serve, &returned);
+        stats.record(&returned, &back);
+        assert_eq!(
+            sounds_between(&returned, &back),
+            vec![SoundEffect::PaddleHit]
+        );
+        assert_eq!(stats.current, 2);
+
+        let mut point = back;
+        point.points.left += 1;
+        assert_eq!(scorer(&back, &point), Some(Side::Left));
+        assert_eq!(sounds_between(&back, &point), vec![SoundEffect::Goal]);
+        stats.record(&back, &point);
+        assert_eq!(stats.current, 0);
+        assert_eq!(stats.longest, 2);
+        assert_eq!(stats.average(), 2.0);
+
+        // Winning a game puts the points back to nothing, which is still a point to the winner
+        let mut game = point;
+        game.points = SideCounts::default();
+        game.games.right = 1;
+        assert_eq!(scorer(&point, &game), Some(Side::Right));
+    }
+}
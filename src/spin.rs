@@ -0,0 +1,131 @@
+// Spin, or English. A paddle moving as it returns the ball sets it turning, a turning ball
+// curves through the air and kicks sideways off the walls, and the sprite
+// turns with it so it can be seen coming.
+use crate::simulation::Interpolated;
+use crate::tuning::GameTuning;
+use crate::{Ball, Paddle, PaddleVelocity, Spin};
+use bevy::prelude::*;
+
+// Works out how fast each paddle moved this tick, from where it was at the start of it.
+// Runs after every paddle controller, so the keyboard, touch, gamepads, the CPU and the
+// network all count the same.
+pub fn track_paddle_velocity(
+    time: Res<Time>,
+    mut paddles: Query<(&Transform, &Interpolated, &mut PaddleVelocity), With<Paddle>>,
+) {
+    let delta_secs = time.delta_secs();
+    if delta_secs <= 0.0 {
+        return;
+    }
+    for (transform, interpolated, mut velocity) in paddles.iter_mut() {
+        velocity.0 = (transform.translation - interpolated.previous).truncate() / delta_secs;
+    }
+}
+
+// Spin after a return. The paddle drags the side of the ball it touched along with it, so a
+// share of its speed becomes the speed the ball's surface turns at.
+// `normal` points from the paddle towards the ball.
+pub fn spin_from_paddle(
+    spin: f32,
+    normal: Vec2,
+    paddle_velocity: Vec2,
+    tuning: &GameTuning,
+) -> f32 {
+    // Spinning faster than the ball can move would just look broken
+    let max_spin = tuning.max_ball_speed_y / tuning.ball_radius;
+    let surface_speed = -normal.perp_dot(paddle_velocity) * tuning.spin_transfer;
+    (spin + surface_speed / tuning.ball_radius).clamp(-max_spin, max_spin)
+}
+
+// Bends the ball's path sideways to the way it's going, towards the side its front is turning to,
+// and lets the spin die away a little. Returns the new velocity and spin.
+pub fn curve(velocity: Vec2, spin: f32, delta_secs: f32, tuning: &GameTuning) -> (Vec2, f32) {
+    let bent = velocity + velocity.perp() * spin * tuning.magnus_strength * delta_secs;
+    let velocity = Vec2::new(
+        bent.x
+            .clamp(-tuning.max_ball_speed_x, tuning.max_ball_speed_x),
+        bent.y
+            .clamp(-tuning.max_ball_speed_y, tuning.max_ball_speed_y),
+    );
+    (velocity, spin * (-tuning.spin_decay * delta_secs).exp())
+}
+
+// Bounce off a wall. The ball's surface grips the wall, so some of the spin is
+// spent pushing the ball along it, the same way topspin kicks a tennis ball on forward.
+// `normal` points from the wall towards the ball. Returns the new velocity and spin.
+pub fn wall_bounce(velocity: Vec2, spin: f32, normal: Vec2, tuning: &GameTuning) -> (Vec2, f32) {
+    let reflected = velocity - 2.0 * velocity.dot(normal) * normal;
+    // How fast the side of the ball touching the wall slides along it because it's turning
+    let kick = normal.perp() * spin * tuning.ball_radius * tuning.wall_spin_grip;
+    let bounced = reflected + kick;
+    let velocity = Vec2::new(
+        bounced
+            .x
+            .clamp(-tuning.max_ball_speed_x, tuning.max_ball_speed_x),
+        bounced
+            .y
+            .clamp(-tuning.max_ball_speed_y, tuning.max_ball_speed_y),
+    );
+    (velocity, spin * (1.0 - tuning.wall_spin_grip))
+}
+
+// Turns each ball's sprite as it spins. Just for show, so it goes by the frame time.
+pub fn show_spin(time: Res<Time>, mut balls: Query<(&Spin, &mut Transform), With<Ball>>) {
+    for (spin, mut transform) in balls.iter_mut() {
+        transform.rotate_z(spin.0 * time.delta_secs());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paddle_moving_up_spins_the_ball_away_from_it() {
+        let tuning = GameTuning::default();
+        // The left paddle drags the ball's left side up, turning it clockwise
+        let up = Vec2::new(0.0, 300.0);
+        let left = spin_from_paddle(0.0, Vec2::X, up, &tuning);
+        assert!(left < 0.0);
+        // The right paddle drags its right side up, anticlockwise
+        let right = spin_from_paddle(0.0, Vec2::NEG_X, up, &tuning);
+        assert_eq!(right, -left);
+        // The bottom paddle moving right drags the ball's bottom along, anticlockwise
+        let bottom = spin_from_paddle(0.0, Vec2::Y, Vec2::new(300.0, 0.0), &tuning);
+        assert_eq!(bottom, right);
+        // Standing still leaves the spin alone
+        assert_eq!(spin_from_paddle(left, Vec2::X, Vec2::ZERO, &tuning), left);
+    }
+
+    #[test]
+    fn spin_curves_the_ball_without_spin_going_straight() {
+        let tuning = GameTuning::default();
+        let velocity = Vec2::new(400.0, 0.0);
+        assert_eq!(curve(velocity, 0.0, 0.1, &tuning).0, velocity);
+
+        // Anticlockwise while heading right, so it drifts up
+        let (curved, spin) = curve(velocity, 50.0, 0.1, &tuning);
+        assert!(curved.y > 0.0);
+        assert!(spin < 50.0 && spin > 0.0);
+    }
+
+    #[test]
+    fn topspin_kicks_the_ball_on_off_the_floor() {
+        let tuning = GameTuning::default();
+        // Heading right and turning clockwise, dropping onto the bottom wall
+        let (velocity, spin) = wall_bounce(Vec2::new(300.0, -200.0), -50.0, Vec2::Y, &tuning);
+        assert!(velocity.x > 300.0);
+        assert_eq!(velocity.y, 200.0);
+        assert!(spin > -50.0 && spin < 0.0);
+
+        // No spin bounces the way it always has
+        let (velocity, spin) = wall_bounce(Vec2::new(300.0, -200.0), 0.0, Vec2::Y, &tuning);
+        assert_eq!(velocity, Vec2::new(300.0, 200.0));
+        assert_eq!(spin, 0.0);
+
+        // A side wall left where a player was knocked out works the same way, turned round
+        let (velocity, _) = wall_bounce(Vec2::new(-200.0, 300.0), -50.0, Vec2::X, &tuning);
+        assert_eq!(velocity.x, 200.0);
+        assert!(velocity.y < 300.0);
+    }
+}
@@ -0,0 +1,35 @@
+use bevy::prelude::*;
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[states(scoped_entities)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    // The rebinding screen, reached from the main menu
+    Controls,
+    // Saved replays, also reached from the main menu
+    Replays,
+    // Hosting or joining a match over the network, until the other player turns up
+    Connecting,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+// Exists whenever a match is on screen, whether it's running, paused or finished.
+// Match entities are scoped to this so they're cleaned up on the way back to the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = AppState;
+
+    fn compute(sources: AppState) -> Option<Self> {
+        match sources {
+            AppState::MainMenu | AppState::Controls | AppState::Replays | AppState::Connecting => {
+                None
+            }
+            AppState::Playing | AppState::Paused | AppState::GameOver => Some(InGame),
+        }
+    }
+}
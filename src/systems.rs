@@ -1,194 +1,461 @@
+use crate::ai::{AiController, AiSettings};
+use crate::arena::{edge_strip, ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH};
+use crate::collision::{reflect, sweep_circle_aabb, sweep_circle_walls, Aabb, ContactKind};
+use crate::controls::{Action, ActionState, PlayerActions};
 use crate::effects_system::spawn_particle_burst;
+use crate::menu::RestartRequested;
+use crate::powerups::{shield_wall, BonusBall, ControlsReversed, PowerUpKind, PowerUps};
+use crate::rng::GameRng;
+use crate::rules::{MatchProgress, MatchRules, MAX_BALLS};
+use crate::setup::{ball_bundle, setup_game};
+use crate::simulation::Interpolated;
+use crate::sound::{PlaySound, SoundEffect};
+use crate::spin::{curve, spin_from_paddle, wall_bounce};
+use crate::states::{AppState, InGame};
+use crate::tuning::GameTuning;
 use crate::{
-    get_window_dimensions, setup::setup_game, Ball, Border, HitStreak, Paddle, Score,
-    ScoreCelebration, Side, Velocity, BALL_RADIUS, PADDLE_HEIGHT, PADDLE_MARGIN, PADDLE_WIDTH,
-};
-use bevy::{
-    input::touch::TouchPhase,
-    prelude::*,
-    ui::{Node, UiRect, Val},
+    Ball, BallNumber, HitStreak, KnockedOutWall, LastTouch, Paddle, PaddleScale, PaddleVelocity,
+    Role, Score, ScoreCelebration, Side, Spin, Velocity,
 };
+use bevy::{input::touch::TouchPhase, prelude::*};
 use rand::Rng;
 
-const FRENZY_HIT_COUNT: u32 = 3;
-const FRENZY_SPEED_MULTIPLIER: f32 = 1.5;
-const KEYBOARD_PADDLE_SPEED_MULTIPLIER: f32 = 0.03;
-const FRENZY_BALL_COLOR: Color = Color::srgb(1.0, 1.0, 1.0); // Bright White
-const NORMAL_BALL_COLOR: Color = Color::srgb(1.0, 1.0, 0.0); // Electric Yellow
+pub const FRENZY_BALL_COLOR: Color = Color::srgb(1.0, 1.0, 1.0); // Bright White
+pub const NORMAL_BALL_COLOR: Color = Color::srgb(1.0, 1.0, 0.0); // Electric Yellow
 
-pub fn move_paddles_with_keyboard(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut Transform, &Paddle)>,
-    windows: Query<&Window>,
+// Snaps a paddle to its side of the arena and keeps it fully inside.
+// Every paddle controller (keyboard, touch, AI) goes through this so they all play by the same rules.
+pub fn constrain_paddle(
+    translation: &mut Vec3,
+    paddle: &Paddle,
+    scale: PaddleScale,
+    tuning: &GameTuning,
 ) {
-    let (half_window_width, half_window_height) =
-        get_window_dimensions(windows.iter().next().unwrap());
-    let paddle_x = half_window_width - PADDLE_MARGIN;
-
-    for (mut transform, paddle) in query.iter_mut() {
-        let mut direction = 0.0;
-        if paddle.side == Side::Left {
-            transform.translation.x = -paddle_x;
-            if keyboard_input.pressed(KeyCode::KeyW) {
-                direction += 1.0;
-            }
-            if keyboard_input.pressed(KeyCode::KeyS) {
-                direction -= 1.0;
-            }
-        } else {
-            transform.translation.x = paddle_x;
-            if keyboard_input.pressed(KeyCode::ArrowUp) {
-                direction += 1.0;
-            }
-            if keyboard_input.pressed(KeyCode::ArrowDown) {
-                direction -= 1.0;
-            }
-        }
+    let side = paddle.side;
+    let half_length = scale.half_length(tuning);
+    let margin = match paddle.role {
+        Role::Back => tuning.paddle_margin,
+        Role::Forward => tuning.forward_paddle_margin,
+    };
+    // Top and bottom paddles stop short of the left and right ones
+    let (depth, max_position) = if side.is_horizontal() {
+        (
+            ARENA_HALF_HEIGHT - margin,
+            ARENA_HALF_WIDTH - tuning.paddle_margin - tuning.paddle_width - half_length,
+        )
+    } else {
+        (ARENA_HALF_WIDTH - margin, ARENA_HALF_HEIGHT - half_length)
+    };
+    let max_position = max_position.max(0.0);
+    let position = paddle_position(*translation, side).clamp(-max_position, max_position);
+    let placed = -side.normal() * depth + side.along() * position;
+    translation.x = placed.x;
+    translation.y = placed.y;
+}
 
-        let half_paddle_height = PADDLE_HEIGHT / 2.0;
-        let max_paddle_y = half_window_height - half_paddle_height;
-        transform.translation.y +=
-            direction * half_window_height * KEYBOARD_PADDLE_SPEED_MULTIPLIER;
-        transform.translation.y = transform.translation.y.clamp(-max_paddle_y, max_paddle_y);
-    }
+// How far along its track a paddle is: up for the left and right ones, right for the top and bottom
+pub fn paddle_position(translation: Vec3, side: Side) -> f32 {
+    translation.truncate().dot(side.along())
 }
 
-#[allow(clippy::type_complexity)]
-pub fn move_ball(
-    mut commands: Commands, // Added Commands
+pub fn set_paddle_position(translation: &mut Vec3, side: Side, position: f32) {
+    let moved =
+        translation.truncate() + side.along() * (position - paddle_position(*translation, side));
+    translation.x = moved.x;
+    translation.y = moved.y;
+}
+
+// Keyboard, mouse and gamepad buttons, through whatever each player has them bound to
+pub fn move_paddles_with_actions(
     time: Res<Time>,
-    mut ball_query: Query<(&mut Transform, &mut Velocity, &mut HitStreak, &mut Sprite), With<Ball>>,
-    paddle_query: Query<(&Transform, &Sprite), (With<Paddle>, Without<Ball>)>, // Added &Sprite for paddle color
-    windows: Query<&Window>,
+    actions: Res<ActionState>,
+    tuning: Res<GameTuning>,
+    mut query: Query<
+        (&mut Transform, &Paddle, &PaddleScale, Has<ControlsReversed>),
+        Without<AiController>,
+    >,
 ) {
-    let (_half_window_width, half_window_height) =
-        get_window_dimensions(windows.iter().next().unwrap());
-    let ball_max_y = half_window_height - BALL_RADIUS;
+    for (mut transform, paddle, scale, reversed) in query.iter_mut() {
+        let mut player = actions.for_side(paddle.player());
+        if reversed {
+            player = player.with_paddle_reversed();
+        }
+        step_paddle(
+            &mut transform.translation,
+            paddle,
+            *scale,
+            player,
+            &tuning,
+            time.delta_secs(),
+        );
+    }
+}
 
-    // Define a maximum speed for the ball
-    const MAX_BALL_SPEED_X: f32 = 800.0;
-    const MAX_BALL_SPEED_Y: f32 = 800.0;
-    // Define how much the ball accelerates with each paddle hit
-    const BALL_ACCELERATION_MULTIPLIER: f32 = 1.1; // 10% speed increase
+// Moves a paddle for however long Paddle Up / Paddle Down were held
+pub fn step_paddle(
+    translation: &mut Vec3,
+    paddle: &Paddle,
+    scale: PaddleScale,
+    player: PlayerActions,
+    tuning: &GameTuning,
+    delta_secs: f32,
+) {
+    let mut direction = 0.0;
+    if player.pressed(Action::PaddleUp) {
+        direction += 1.0;
+    }
+    if player.pressed(Action::PaddleDown) {
+        direction -= 1.0;
+    }
 
-    for (mut ball_transform, mut ball_velocity, mut hit_streak, mut sprite) in ball_query.iter_mut()
-    {
-        ball_transform.translation.x += ball_velocity.x * time.delta_secs();
-        ball_transform.translation.y += ball_velocity.y * time.delta_secs();
-
-        // Bounce the ball off the top and bottom of the screen
-        if ball_transform.translation.y < -ball_max_y || ball_transform.translation.y > ball_max_y {
-            ball_velocity.y = -ball_velocity.y;
-            // Ensure the ball doesn't get stuck outside the screen
-            ball_transform.translation.y =
-                ball_transform.translation.y.clamp(-ball_max_y, ball_max_y);
-            // Reset hit streak if ball hits top/bottom walls
-            if hit_streak.count != 0 {
-                // Avoid redundant color change if already normal
-                sprite.color = NORMAL_BALL_COLOR;
-            }
-            hit_streak.count = 0;
+    let step = direction * ARENA_HALF_HEIGHT * tuning.keyboard_paddle_speed * delta_secs;
+    *translation += (paddle.side.along() * step).extend(0.0);
+    constrain_paddle(translation, paddle, scale, tuning);
+}
 
-            // Spawn particles for wall collision
-            spawn_particle_burst(
-                &mut commands,
-                ball_transform.translation.truncate(), // Current ball position
-                Color::srgb(0.7, 0.7, 0.7),            // Gray sparks
-                5,                                     // Number of particles
-                0.3,                                   // Base lifetime
-            );
+// New ball velocity after touching a paddle, depending on which part of it was hit.
+// `normal` is the contact normal from the sweep, pointing from the paddle towards the ball.
+// Everything is in paddle space, see Side::to_paddle_space.
+pub fn paddle_bounce(
+    velocity: Vec2,
+    ball_y: f32,
+    paddle_y: f32,
+    half_paddle_height: f32,
+    normal: Vec2,
+    tuning: &GameTuning,
+) -> Vec2 {
+    let max_x = tuning.max_ball_speed_x;
+    let max_y = tuning.max_ball_speed_y;
+    match ContactKind::from_normal(normal) {
+        ContactKind::Face => {
+            // Reverse and accelerate ball's x velocity
+            let x = (velocity.x * -tuning.ball_acceleration)
+                // Cap the ball's x speed
+                .clamp(-max_x, max_x);
+
+            // Calculate the offset from the center of the paddle
+            // Offset is between -1.0 (top of paddle) and 1.0 (bottom of paddle)
+            let offset = ((paddle_y - ball_y) / half_paddle_height).clamp(-1.0, 1.0);
+
+            // Modify y velocity based on where the ball hit the paddle
+            // Max y deflection angle (e.g. 60 degrees), converting to a multiplier for y velocity
+            // A higher offset results in a larger change in y velocity.
+            // The current y velocity is also taken into account and slightly amplified.
+            let y = (velocity.y * 0.5 - offset * (max_y * 0.75)).clamp(-max_y, max_y);
+            Vec2::new(x, y)
         }
+        ContactKind::Edge => {
+            // The ball glances off the end of the paddle and carries on towards the goal,
+            // knocked firmly up or down so it doesn't skim along the paddle
+            let y = normal.y.signum() * velocity.y.abs().max(tuning.edge_deflection_speed());
+            Vec2::new(velocity.x, y.clamp(-max_y, max_y))
+        }
+        ContactKind::Corner => {
+            // Mirror about the corner's normal, so the angle out depends on where the corner was caught
+            let reflected = reflect(velocity, normal) * tuning.ball_acceleration;
+            Vec2::new(
+                reflected.x.clamp(-max_x, max_x),
+                reflected.y.clamp(-max_y, max_y),
+            )
+        }
+    }
+}
 
-        for (paddle_transform, paddle_sprite) in paddle_query.iter() {
-            // Destructure to get paddle_sprite
-            let paddle_x = paddle_transform.translation.x;
-            let paddle_y = paddle_transform.translation.y;
-            let half_paddle_height = PADDLE_HEIGHT / 2.0;
+// What the ball ran into while sweeping along its path
+enum Contact {
+    Wall,
+    // The shield power-up's wall across a goal
+    Shield,
+    Paddle {
+        side: Side,
+        // Along its track, see paddle_position
+        position: f32,
+        half_length: f32,
+        velocity: Vec2,
+        color: Color,
+    },
+}
 
-            // Collision detection logic
-            let ball_collides_with_paddle_x =
-                (ball_transform.translation.x - paddle_x).abs() < PADDLE_WIDTH / 2.0 + BALL_RADIUS;
+// How thick the wall left across a knocked out side's goal is
+pub const KNOCKED_OUT_WALL_THICKNESS: f32 = 12.0;
 
-            let ball_collides_with_paddle_y = (ball_transform.translation.y
-                >= paddle_y - half_paddle_height - BALL_RADIUS)
-                && (ball_transform.translation.y <= paddle_y + half_paddle_height + BALL_RADIUS);
+// Stops one frame from bouncing back and forth forever, e.g. a ball wedged between a paddle and a wall
+const MAX_COLLISIONS_PER_FRAME: usize = 4;
 
-            // Check if the ball is moving towards the paddle
-            let ball_moving_towards_paddle = (ball_velocity.x > 0.0 && paddle_x > 0.0) || // Ball moving right, right paddle
-                (ball_velocity.x < 0.0 && paddle_x < 0.0); // Ball moving left, left paddle
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn move_ball(
+    mut commands: Commands, // Added Commands
+    time: Res<Time>,
+    mut ball_query: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut Spin,
+            &mut HitStreak,
+            &mut LastTouch,
+            &mut Sprite,
+        ),
+        With<Ball>,
+    >,
+    paddle_query: Query<
+        (
+            &Paddle,
+            &PaddleScale,
+            &PaddleVelocity,
+            &Transform,
+            &Sprite, // Added &Sprite for paddle color
+        ),
+        Without<Ball>,
+    >,
+    mut rng: ResMut<GameRng>,
+    mut sounds: EventWriter<PlaySound>,
+    tuning: Res<GameTuning>,
+    power_ups: Res<PowerUps>,
+    mut rally: ResMut<Rally>,
+    rules: Res<MatchRules>,
+    progress: Res<MatchProgress>,
+) {
+    let ball_max_y = ARENA_HALF_HEIGHT - tuning.ball_radius;
+    let shields: Vec<Aabb> = Side::ALL
+        .into_iter()
+        .filter(|side| power_ups.shielded(*side))
+        .map(shield_wall)
+        .collect();
+    // The top and bottom are open goals in a four player match, and each goal is walled up
+    // once its side is out
+    let knocked_out_walls: Vec<Aabb> = Side::ALL
+        .into_iter()
+        .filter(|side| progress.knocked_out(*side))
+        .map(|side| edge_strip(side, KNOCKED_OUT_WALL_THICKNESS))
+        .collect();
+
+    for (
+        mut ball_transform,
+        mut ball_velocity,
+        mut spin,
+        mut hit_streak,
+        mut last_touch,
+        mut sprite,
+    ) in ball_query.iter_mut()
+    {
+        // Slow-mo just gives the ball less of the tick to move in
+        let mut remaining_secs = time.delta_secs() * power_ups.ball_time_scale();
+
+        // Spin bends the path a little every tick
+        let (curved, decayed) = curve(
+            Vec2::new(ball_velocity.x, ball_velocity.y),
+            spin.0,
+            remaining_secs,
+            &tuning,
+        );
+        ball_velocity.x = curved.x;
+        ball_velocity.y = curved.y;
+        spin.0 = decayed;
+
+        // Sweep the ball along its path instead of checking for overlap after moving it,
+        // so a fast ball or a long frame can't carry it straight through a paddle.
+        // Each bounce uses up part of the frame, and the rest is spent moving away from it.
+        for _ in 0..MAX_COLLISIONS_PER_FRAME {
+            let position = ball_transform.translation.truncate();
+            let motion = Vec2::new(ball_velocity.x, ball_velocity.y) * remaining_secs;
+
+            let wall_hits = knocked_out_walls
+                .iter()
+                .filter_map(|wall| sweep_circle_aabb(position, motion, tuning.ball_radius, *wall))
+                .chain(
+                    (!rules.four_players)
+                        .then(|| sweep_circle_walls(position, motion, ball_max_y))
+                        .flatten(),
+                );
+            let paddle_hits = paddle_query
+                .iter()
+                .filter(|(paddle, ..)| !progress.knocked_out(paddle.side))
+                .filter_map(
+                    |(paddle, scale, paddle_velocity, paddle_transform, paddle_sprite)| {
+                        // Only contacts the ball is moving into are reported, whichever side of the paddle
+                        let bounds = Aabb {
+                            center: paddle_transform.translation.truncate(),
+                            half_size: scale.half_size(paddle.side, &tuning),
+                        };
+                        let hit = sweep_circle_aabb(position, motion, tuning.ball_radius, bounds)?;
+                        // Only the front of a paddle stops the ball. Coming from behind, e.g. a
+                        // return by the back paddle in doubles, it carries on through.
+                        if hit.normal.dot(paddle.side.normal()) < 0.0 {
+                            return None;
+                        }
+                        Some((
+                            hit,
+                            Contact::Paddle {
+                                side: paddle.side,
+                                position: paddle_position(
+                                    paddle_transform.translation,
+                                    paddle.side,
+                                ),
+                                half_length: scale.half_length(&tuning),
+                                velocity: paddle_velocity.0,
+                                color: paddle_sprite.color,
+                            },
+                        ))
+                    },
+                );
+            let shield_hits = shields.iter().filter_map(|shield| {
+                let hit = sweep_circle_aabb(position, motion, tuning.ball_radius, *shield)?;
+                Some((hit, Contact::Shield))
+            });
+
+            // Whichever comes first this frame, paddles winning ties
+            let first_contact = paddle_hits
+                .chain(shield_hits)
+                .chain(wall_hits.map(|hit| (hit, Contact::Wall)))
+                .min_by(|a, b| a.0.time.total_cmp(&b.0.time));
+            let Some((hit, contact)) = first_contact else {
+                ball_transform.translation += motion.extend(0.0);
+                break;
+            };
 
-            if ball_collides_with_paddle_x
-                && ball_collides_with_paddle_y
-                && ball_moving_towards_paddle
-            {
-                // Prevent ball from passing through paddle by adjusting its position
-                if ball_velocity.x > 0.0 {
-                    // Moving right
-                    ball_transform.translation.x = paddle_x - PADDLE_WIDTH / 2.0 - BALL_RADIUS;
-                } else {
-                    // Moving left
-                    ball_transform.translation.x = paddle_x + PADDLE_WIDTH / 2.0 + BALL_RADIUS;
-                }
+            // Move up to the point of contact
+            ball_transform.translation += (motion * hit.time).extend(0.0);
+            remaining_secs *= 1.0 - hit.time;
 
-                // Reverse and accelerate ball's x velocity
-                ball_velocity.x *= -BALL_ACCELERATION_MULTIPLIER;
-                // Cap the ball's x speed
-                ball_velocity.x = ball_velocity.x.clamp(-MAX_BALL_SPEED_X, MAX_BALL_SPEED_X);
-
-                // Calculate the offset from the center of the paddle
-                // Offset is between -1.0 (top of paddle) and 1.0 (bottom of paddle)
-                let offset = (paddle_y - ball_transform.translation.y) / half_paddle_height;
-
-                // Modify y velocity based on where the ball hit the paddle
-                // Max y deflection angle (e.g. 60 degrees), converting to a multiplier for y velocity
-                // A higher offset results in a larger change in y velocity.
-                // The current y velocity is also taken into account and slightly amplified.
-                let new_y_velocity = ball_velocity.y * 0.5 - offset * (MAX_BALL_SPEED_Y * 0.75);
-                ball_velocity.y = new_y_velocity.clamp(-MAX_BALL_SPEED_Y, MAX_BALL_SPEED_Y);
-
-                // Accelerate ball's y velocity (less than x to maintain some control)
-                // ball_velocity.y *= BALL_ACCELERATION_MULTIPLIER * 0.9; // slightly less acceleration for y
-                // Cap the ball's y speed
-                // ball_velocity.y = ball_velocity.y.clamp(-MAX_BALL_SPEED_Y, MAX_BALL_SPEED_Y);
-
-                // Handle Hit Streak for Frenzy Ball
-                hit_streak.count += 1;
-                if hit_streak.count >= FRENZY_HIT_COUNT {
-                    ball_velocity.x *= FRENZY_SPEED_MULTIPLIER;
-                    ball_velocity.y *= FRENZY_SPEED_MULTIPLIER;
-                    // Cap speeds after frenzy boost
-                    ball_velocity.x = ball_velocity.x.clamp(-MAX_BALL_SPEED_X, MAX_BALL_SPEED_X);
-                    ball_velocity.y = ball_velocity.y.clamp(-MAX_BALL_SPEED_Y, MAX_BALL_SPEED_Y);
-
-                    sprite.color = FRENZY_BALL_COLOR; // Set frenzy color
-                    hit_streak.count = 0; // Reset streak after frenzy activates (for burst effect)
-                                          // Color will remain FRENZY_BALL_COLOR until next wall hit or goal
-                } else if hit_streak.count == 0 && sprite.color == FRENZY_BALL_COLOR {
-                    // This case handles when frenzy was just activated (count became 0)
-                    // and ensures normal color if no new frenzy is immediately triggered by this same hit (which is impossible)
-                    // More simply: if streak is 0, color should be normal.
-                    // This 'else if' is mostly for clarity; wall/goal resets handle long-term.
-                    // However, this ensures if frenzy activates, and then it's a normal hit, it reverts.
-                    // This will be handled by the wall/goal reset mostly.
-                    // The primary place to reset to NORMAL_BALL_COLOR is when hit_streak.count becomes 0.
+            if let Contact::Shield = contact {
+                // Straight back off the shield, without it counting as a return
+                let bounced = reflect(Vec2::new(ball_velocity.x, ball_velocity.y), hit.normal);
+                ball_velocity.x = bounced.x;
+                ball_velocity.y = bounced.y;
+                sounds.write(PlaySound::at_speed(SoundEffect::WallBounce, bounced));
+                spawn_particle_burst(
+                    &mut commands,
+                    &mut rng.cosmetic,
+                    ball_transform.translation.truncate(),
+                    PowerUpKind::Shield.color(),
+                    5,
+                    0.3,
+                );
+                continue;
+            }
+
+            let Contact::Paddle {
+                side: paddle_side,
+                position: paddle_along,
+                half_length,
+                velocity: paddle_velocity,
+                color: paddle_color,
+            } = contact
+            else {
+                // Bounce the ball off the wall, kicked along by any spin
+                let (bounced, gripped) = wall_bounce(
+                    Vec2::new(ball_velocity.x, ball_velocity.y),
+                    spin.0,
+                    hit.normal,
+                    &tuning,
+                );
+                ball_velocity.x = bounced.x;
+                ball_velocity.y = bounced.y;
+                spin.0 = gripped;
+                // Ensure the ball doesn't get stuck outside the screen
+                if !rules.four_players {
+                    ball_transform.translation.y =
+                        ball_transform.translation.y.clamp(-ball_max_y, ball_max_y);
                 }
-                // If hit_streak.count > 0 but < FRENZY_HIT_COUNT, color should be normal.
-                if hit_streak.count > 0
-                    && hit_streak.count < FRENZY_HIT_COUNT
-                    && sprite.color == FRENZY_BALL_COLOR
-                {
+                // Reset hit streak if ball hits a wall
+                if hit_streak.count != 0 {
+                    // Avoid redundant color change if already normal
                     sprite.color = NORMAL_BALL_COLOR;
                 }
+                hit_streak.count = 0;
+                sounds.write(PlaySound::at_speed(
+                    SoundEffect::WallBounce,
+                    Vec2::new(ball_velocity.x, ball_velocity.y),
+                ));
 
-                // Spawn particles for paddle collision
+                // Spawn particles for wall collision
                 spawn_particle_burst(
                     &mut commands,
+                    &mut rng.cosmetic,
                     ball_transform.translation.truncate(), // Current ball position
-                    paddle_sprite.color,                   // Use paddle's color
-                    7,                                     // Number of particles
-                    0.4,                                   // Base lifetime
+                    Color::srgb(0.7, 0.7, 0.7),            // Gray sparks
+                    5,                                     // Number of particles
+                    0.3,                                   // Base lifetime
+                );
+                continue;
+            };
+
+            last_touch.0 = Some(paddle_side);
+            // Top and bottom paddles bounce the ball the same way, on their side
+            let to_paddle_space = |v: Vec2| paddle_side.to_paddle_space(v);
+            let contact_kind = ContactKind::from_normal(to_paddle_space(hit.normal));
+            let new_velocity = to_paddle_space(paddle_bounce(
+                to_paddle_space(Vec2::new(ball_velocity.x, ball_velocity.y)),
+                paddle_position(ball_transform.translation, paddle_side),
+                paddle_along,
+                half_length,
+                to_paddle_space(hit.normal),
+                &tuning,
+            ));
+            ball_velocity.x = new_velocity.x;
+            ball_velocity.y = new_velocity.y;
+            // A moving paddle sets the ball spinning
+            spin.0 = spin_from_paddle(spin.0, hit.normal, paddle_velocity, &tuning);
+            sounds.write(PlaySound::at_speed(SoundEffect::PaddleHit, new_velocity));
+
+            if contact_kind == ContactKind::Edge {
+                // Clipping the end of the paddle isn't a return, so it doesn't count towards frenzy
+                spawn_particle_burst(
+                    &mut commands,
+                    &mut rng.cosmetic,
+                    ball_transform.translation.truncate(),
+                    paddle_color,
+                    3,
+                    0.3,
                 );
+                continue;
             }
+
+            rally.returns += 1;
+
+            // Handle Hit Streak for Frenzy Ball
+            hit_streak.count += 1;
+            if hit_streak.count >= tuning.frenzy_hit_count {
+                ball_velocity.x *= tuning.frenzy_speed_multiplier;
+                ball_velocity.y *= tuning.frenzy_speed_multiplier;
+                // Cap speeds after frenzy boost
+                ball_velocity.x = ball_velocity
+                    .x
+                    .clamp(-tuning.max_ball_speed_x, tuning.max_ball_speed_x);
+                ball_velocity.y = ball_velocity
+                    .y
+                    .clamp(-tuning.max_ball_speed_y, tuning.max_ball_speed_y);
+
+                sprite.color = FRENZY_BALL_COLOR; // Set frenzy color
+                sounds.write(PlaySound::new(SoundEffect::Frenzy));
+                hit_streak.count = 0; // Reset streak after frenzy activates (for burst effect)
+                                      // Color will remain FRENZY_BALL_COLOR until next wall hit or goal
+            } else if hit_streak.count == 0 && sprite.color == FRENZY_BALL_COLOR {
+                // This case handles when frenzy was just activated (count became 0)
+                // and ensures normal color if no new frenzy is immediately triggered by this same hit (which is impossible)
+                // More simply: if streak is 0, color should be normal.
+                // This 'else if' is mostly for clarity; wall/goal resets handle long-term.
+                // However, this ensures if frenzy activates, and then it's a normal hit, it reverts.
+                // This will be handled by the wall/goal reset mostly.
+                // The primary place to reset to NORMAL_BALL_COLOR is when hit_streak.count becomes 0.
+            }
+            // If hit_streak.count > 0 but < frenzy_hit_count, color should be normal.
+            if hit_streak.count > 0
+                && hit_streak.count < tuning.frenzy_hit_count
+                && sprite.color == FRENZY_BALL_COLOR
+            {
+                sprite.color = NORMAL_BALL_COLOR;
+            }
+
+            // Spawn particles for paddle collision
+            spawn_particle_burst(
+                &mut commands,
+                &mut rng.cosmetic,
+                ball_transform.translation.truncate(), // Current ball position
+                paddle_color,                          // Use paddle's color
+                7,                                     // Number of particles
+                0.4,                                   // Base lifetime
+            );
         }
     }
 }
@@ -197,191 +464,355 @@ pub fn move_ball(
 // but CELEBRATION_TEXT_COLOR is what's used by manage_score_celebration.
 // NORMAL_SCORE_COLOR is implicitly the one set during setup.
 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn check_new_goal(
     mut commands: Commands,
     mut score_display_query: Query<(Entity, &mut Score, &mut Text, &TextColor)>, // Changed to &mut Text
-    mut ball_query: Query<(&mut Transform, &mut Velocity, &mut HitStreak, &mut Sprite), With<Ball>>,
-    windows: Query<&Window>,
+    mut ball_query: Query<
+        (
+            Entity,
+            &BallNumber,
+            &mut Transform,
+            &mut Interpolated,
+            &mut Velocity,
+            &mut Spin,
+            &mut HitStreak,
+            &mut LastTouch,
+            &mut Sprite,
+        ),
+        With<Ball>,
+    >,
+    mut rng: ResMut<GameRng>,
+    mut sounds: EventWriter<PlaySound>,
+    mut goals: EventWriter<GoalScored>,
+    rules: Res<MatchRules>,
+    mut progress: ResMut<MatchProgress>,
+    mut rally: ResMut<Rally>,
+    tuning: Res<GameTuning>,
 ) {
-    let (half_window_width, half_window_height) =
-        get_window_dimensions(windows.iter().next().unwrap());
-    let ball_limit = half_window_width + PADDLE_MARGIN; // How far the ball has to go to score
+    // How far the ball has to go to score. Only the left and right are open in a two player match.
+    let ball_limit = Vec2::new(ARENA_HALF_WIDTH, ARENA_HALF_HEIGHT) + tuning.paddle_margin;
 
-    for (mut ball_transform, mut ball_velocity, mut hit_streak, mut ball_sprite) in
-        ball_query.iter_mut()
-    {
-        let mut player_scored: Option<Side> = None;
+    // Oldest first, so two balls going in on the same tick count the same way everywhere
+    let mut scored: Vec<(BallNumber, Entity, Side)> = ball_query
+        .iter()
+        .filter_map(|(entity, number, ball_transform, ..)| {
+            let position = ball_transform.translation;
+            let conceded = if position.x < -ball_limit.x {
+                Side::Left
+            } else if position.x > ball_limit.x {
+                Side::Right
+            } else if position.y > ball_limit.y {
+                Side::Top
+            } else if position.y < -ball_limit.y {
+                Side::Bottom
+            } else {
+                return None;
+            };
+            Some((*number, entity, conceded))
+        })
+        .collect();
+    scored.sort_by_key(|(number, _, _)| *number);
+    let still_in_play = ball_query.iter().count() - scored.len();
+
+    for (index, (_, ball_entity, conceded)) in scored.iter().copied().enumerate() {
+        // The rally carries on as long as any ball is still in play
+        let rally_over = still_in_play == 0 && index == scored.len() - 1;
+        sounds.write(PlaySound::new(SoundEffect::Goal));
+        goals.write(GoalScored {
+            conceded,
+            rally_over,
+        });
+        // With four players it just costs whoever let it in a life
+        let actual_winner_side = if rules.four_players {
+            progress.lose_life(conceded);
+            None
+        } else {
+            progress.award_point(&rules, conceded.opposite());
+            Some(conceded.opposite())
+        };
+
+        // Points go back to 0 when a game is won, so every side is refreshed
+        for (text_entity, mut score_component, mut text, text_color) in
+            score_display_query.iter_mut()
+        {
+            score_component.value = progress.shown_score(score_component.side);
+            text.0 = score_component.value.to_string();
+
+            if Some(score_component.side) == actual_winner_side {
+                // Add ScoreCelebration component to the text_entity
+                let original_color = text_color.0; // Get current color before changing
+                commands.entity(text_entity).insert(ScoreCelebration {
+                    timer: Timer::from_seconds(
+                        crate::effects_system::CELEBRATION_DURATION_SECS,
+                        TimerMode::Once,
+                    ),
+                    original_color,
+                    scored_side: score_component.side,
+                });
+                // The manage_score_celebration system will now handle changing to CELEBRATION_TEXT_COLOR
+            }
+        }
 
-        if ball_transform.translation.x < -ball_limit {
-            player_scored = Some(Side::Right);
-        } else if ball_transform.translation.x > ball_limit {
-            player_scored = Some(Side::Left);
+        // Extra balls just leave, only the last one out is served again
+        if !rally_over {
+            commands.entity(ball_entity).despawn();
+            continue;
         }
+        rally.end();
+
+        let Ok((
+            _,
+            _,
+            mut ball_transform,
+            mut interpolated,
+            mut ball_velocity,
+            mut spin,
+            mut hit_streak,
+            mut last_touch,
+            mut ball_sprite,
+        )) = ball_query.get_mut(ball_entity)
+        else {
+            continue;
+        };
 
-        if let Some(actual_winner_side) = player_scored {
-            // Update score and trigger celebration
-            for (text_entity, mut score_component, mut text, text_color) in
-                score_display_query.iter_mut()
-            {
-                // text_object is &mut Text
-                if score_component.side == actual_winner_side {
-                    score_component.value += 1;
-
-                    // Update the displayed text value directly
-                    text.0 = score_component.value.to_string();
-
-                    // Add ScoreCelebration component to the text_entity
-                    let original_color = text_color.0; // Get current color before changing
-                    commands.entity(text_entity).insert(ScoreCelebration {
-                        timer: Timer::from_seconds(
-                            crate::effects_system::CELEBRATION_DURATION_SECS,
-                            TimerMode::Once,
-                        ),
-                        original_color,
-                        scored_side: actual_winner_side,
-                    });
-                    // The manage_score_celebration system will now handle changing to CELEBRATION_TEXT_COLOR
-                    break;
-                }
-            }
+        // Reset ball position to center
+        ball_transform.translation = Vec3::new(0.0, 0.0, 0.1);
+        // Jump straight there instead of sliding across the screen
+        interpolated.previous = ball_transform.translation;
 
-            // Reset ball position to center
-            ball_transform.translation = Vec3::new(0.0, 0.0, 0.1);
+        // The ball heads for whoever the rules say serves next
+        let server = progress.server(&rules);
 
-            // Determine serve direction (towards the player who didn't score)
-            let serve_direction_x = match actual_winner_side {
-                Side::Left => 1.0,   // Serve to the right (towards right player)
-                Side::Right => -1.0, // Serve to the left (towards left player)
-            };
+        let initial_ball_speed = ARENA_HALF_WIDTH / 2.0;
+        let initial_ball_speed_across_range = ARENA_HALF_HEIGHT / 2.0;
+
+        // Random speed across for variety, but less than towards the server to make it receivable
+        let serve_velocity = -server.normal() * initial_ball_speed
+            + server.along()
+                * rng.gameplay.gen_range(
+                    -initial_ball_speed_across_range * 0.5..=initial_ball_speed_across_range * 0.5,
+                );
+        // The ball waits in the middle until the player it's heading for serves it
+        ball_velocity.x = 0.0;
+        ball_velocity.y = 0.0;
+        spin.0 = 0.0;
+        commands
+            .entity(ball_entity)
+            .insert(AwaitingServe {
+                receiver: server,
+                velocity: serve_velocity,
+                timer: Timer::from_seconds(SERVE_TIMEOUT_SECS, TimerMode::Once),
+            })
+            // Whatever it was before, it's the match ball now
+            .remove::<BonusBall>();
+
+        // Reset hit streak on goal
+        hit_streak.count = 0;
+        last_touch.0 = None;
+        // Reset ball color on goal
+        ball_sprite.color = NORMAL_BALL_COLOR;
+    }
+}
 
-            let initial_ball_speed_x = half_window_width / 2.0;
-            let initial_ball_speed_y_range = half_window_height / 2.0;
+// Sent by check_new_goal for every point, for anything else that has to be undone when one is scored
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GoalScored {
+    // Whose goal it went in
+    pub conceded: Side,
+    // It was the last ball in play, so it's about to be served again
+    pub rally_over: bool,
+}
 
-            let mut rng = rand::thread_rng();
-            ball_velocity.x = serve_direction_x * initial_ball_speed_x;
-            // Random initial y speed for variety, but less than x to make it receivable
-            ball_velocity.y =
-                rng.gen_range(-initial_ball_speed_y_range * 0.5..=initial_ball_speed_y_range * 0.5);
+// How long the current rally has gone on, and the number the next ball into play gets
+#[derive(Resource, Clone, Default, Debug)]
+pub struct Rally {
+    // Paddle returns since the last serve, by any ball
+    pub returns: u32,
+    // Balls thrown in under the chaos rules since the last serve
+    pub extra_balls: u32,
+    next_ball: u32,
+}
 
-            // Reset hit streak on goal
-            hit_streak.count = 0;
-            // Reset ball color on goal
-            ball_sprite.color = NORMAL_BALL_COLOR;
-        }
+impl Rally {
+    pub fn number_next_ball(&mut self) -> BallNumber {
+        let number = BallNumber(self.next_ball);
+        self.next_ball += 1;
+        number
+    }
+
+    fn end(&mut self) {
+        self.returns = 0;
+        self.extra_balls = 0;
     }
 }
 
-//Stop ball from moving, reset score, despawn paddles and ball, display game over text, then totally reset
-pub fn game_over(
-    score: Query<&Score>,
+// Under the chaos rules another ball is thrown in from the middle every so many returns
+pub fn add_chaos_balls(
     mut commands: Commands,
-    text_query: Query<Entity, With<Text>>,
-    paddle_query: Query<Entity, With<Paddle>>,
-    ball_query: Query<Entity, With<Ball>>,
-    border_query: Query<Entity, With<Border>>,
-    windows: Query<&Window>,
+    rules: Res<MatchRules>,
+    mut rally: ResMut<Rally>,
+    mut rng: ResMut<GameRng>,
+    mut sounds: EventWriter<PlaySound>,
+    tuning: Res<GameTuning>,
+    balls: Query<(), With<Ball>>,
 ) {
-    let (half_window_width, half_window_height) =
-        get_window_dimensions(windows.iter().next().unwrap());
-    for score in score.iter() {
-        if score.value == 10 {
-            for entity in text_query.iter() {
-                commands.entity(entity).despawn();
-            }
-            for entity in paddle_query.iter() {
-                commands.entity(entity).despawn();
-            }
-            for entity in ball_query.iter() {
-                commands.entity(entity).despawn();
-            }
-            for entity in border_query.iter() {
-                commands.entity(entity).despawn();
-            }
+    let Some(every) = rules.extra_ball_every else {
+        return;
+    };
+    if rally.returns / every.max(1) <= rally.extra_balls {
+        return;
+    }
+    // Counted even when the arena is full, so a ball leaving isn't replaced straight away
+    rally.extra_balls += 1;
+    if balls.iter().count() as u32 >= MAX_BALLS {
+        return;
+    }
 
-            commands.spawn((
-                Text::new(format!("P{} wins!", u8::from(score.side == Side::Left) + 1)),
-                TextFont {
-                    font_size: 40.0,
-                    ..default()
-                },
-                TextColor(Color::WHITE),
-                Node {
-                    position_type: PositionType::Absolute,
-                    margin: UiRect {
-                        left: Val::Px(half_window_width - 200.0),
-                        right: Val::Px(0.0),
-                        bottom: Val::Px(0.0),
-                        top: Val::Px(half_window_height - 100.0),
-                    },
-                    ..Default::default()
-                },
-            ));
+    let direction_x = if rng.gameplay.gen_bool(0.5) {
+        1.0
+    } else {
+        -1.0
+    };
+    let speed_y = ARENA_HALF_HEIGHT / 4.0;
+    let velocity = Vec2::new(
+        direction_x * ARENA_HALF_WIDTH / 2.0,
+        rng.gameplay.gen_range(-speed_y..=speed_y),
+    );
+    let number = rally.number_next_ball();
+    commands.spawn(ball_bundle(
+        &tuning,
+        number,
+        Vec3::new(0.0, 0.0, 0.1),
+        velocity,
+    ));
+    sounds.write(PlaySound::new(SoundEffect::Frenzy));
+    spawn_particle_burst(
+        &mut commands,
+        &mut rng.cosmetic,
+        Vec2::ZERO,
+        NORMAL_BALL_COLOR,
+        12,
+        0.5,
+    );
+}
 
-            commands.spawn((
-                Text::new("Press R to restart".to_string()),
-                TextFont {
-                    font_size: 40.0,
-                    ..default()
-                },
-                TextColor(Color::WHITE),
-                Node {
-                    position_type: PositionType::Absolute,
-                    margin: UiRect {
-                        left: Val::Px(half_window_width - 200.0),
-                        right: Val::Px(0.0),
-                        bottom: Val::Px(0.0),
-                        top: Val::Px(half_window_height - 50.0),
-                    },
-                    ..Default::default()
-                },
-            ));
+// How long a person gets to press Serve before the ball goes anyway
+pub const SERVE_TIMEOUT_SECS: f32 = 2.0;
+// CPU players don't need the whole time
+const CPU_SERVE_DELAY_SECS: f32 = 0.5;
+
+// A ball sat in the middle after a goal, waiting for the receiving player to serve
+#[derive(Component, Clone)]
+pub struct AwaitingServe {
+    pub receiver: Side,
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+pub fn serve_ball(
+    mut commands: Commands,
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    mut balls: Query<(Entity, &mut Velocity, &mut AwaitingServe), With<Ball>>,
+    paddles: Query<(&Paddle, Has<AiController>)>,
+) {
+    for (entity, mut velocity, mut serve) in balls.iter_mut() {
+        serve.timer.tick(time.delta());
+        // Either player on a doubles team can serve, and the CPU only does if it's on its own
+        let mut receivers = paddles
+            .iter()
+            .filter(|(paddle, _)| paddle.side == serve.receiver);
+        let served = if receivers.clone().all(|(_, cpu)| cpu) {
+            serve.timer.elapsed_secs() >= CPU_SERVE_DELAY_SECS
+        } else {
+            receivers.any(|(paddle, cpu)| {
+                !cpu && actions.for_side(paddle.player()).pressed(Action::Serve)
+            })
+        };
+
+        if served || serve.timer.finished() {
+            velocity.x = serve.velocity.x;
+            velocity.y = serve.velocity.y;
+            commands.entity(entity).remove::<AwaitingServe>();
         }
     }
 }
 
-//Unavoidable when using Bevy queries
+// Ends the match as soon as the rules have a winner
+pub fn game_over(progress: Res<MatchProgress>, mut next_state: ResMut<NextState<AppState>>) {
+    if progress.winner.is_some() {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+// Throws away everything belonging to the current match and starts a fresh one
 #[allow(clippy::too_many_arguments)]
 pub fn restart_game(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    touch_input: Res<Touches>,
+    mut restart_requests: EventReader<RestartRequested>,
     mut commands: Commands,
-    score_query: Query<&Score>,
-    score_entties: Query<Entity, With<Score>>,
-    text_entities: Query<Entity, With<Text>>,
-    paddle_entities: Query<Entity, With<Paddle>>,
-    ball_entities: Query<Entity, With<Ball>>,
-    windows: Query<&Window>,
+    match_entities: Query<Entity, With<StateScoped<InGame>>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut rng: ResMut<GameRng>,
+    rules: Res<MatchRules>,
+    mut progress: ResMut<MatchProgress>,
+    mut power_ups: ResMut<PowerUps>,
+    rally: ResMut<Rally>,
+    tuning: Res<GameTuning>,
+    ai: Res<AiSettings>,
 ) {
-    let entities = score_entties
-        .iter()
-        .chain(text_entities.iter())
-        .chain(paddle_entities.iter())
-        .chain(ball_entities.iter());
+    if restart_requests.read().count() == 0 {
+        return;
+    }
 
-    let restart_triggered = {
-        keyboard_input.just_pressed(KeyCode::KeyR)
-            || (touch_input.any_just_pressed() && score_query.iter().any(|score| score.value == 10))
-    };
+    rng.next_match();
+    *progress = MatchProgress::new(&rules);
+    *power_ups = PowerUps::default();
+
+    for entity in match_entities.iter() {
+        commands.entity(entity).despawn();
+    }
+    setup_game(commands, tuning, rules, ai, rally);
+    next_state.set(AppState::Playing);
+}
 
-    if restart_triggered {
-        for entity in entities.into_iter() {
-            commands.entity(entity).despawn();
+// Hides a knocked out side's paddle and shows the wall across its goal instead
+pub fn show_knocked_out_sides(
+    progress: Res<MatchProgress>,
+    mut paddles: Query<(&Paddle, &mut Visibility)>,
+    mut walls: Query<(&KnockedOutWall, &mut Visibility), Without<Paddle>>,
+) {
+    let shown = |visible: bool| {
+        if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
         }
-        setup_game(commands, windows);
+    };
+    for (paddle, mut visibility) in paddles.iter_mut() {
+        visibility.set_if_neq(shown(!progress.knocked_out(paddle.side)));
+    }
+    for (wall, mut visibility) in walls.iter_mut() {
+        visibility.set_if_neq(shown(progress.knocked_out(wall.side)));
     }
 }
 
-pub fn move_paddles_with_touch(
+// Latest finger position (in arena Y) over each half of the arena.
+// Touches arrive every frame but paddles only move on fixed ticks, so they're parked here in between.
+#[derive(Resource, Default)]
+pub struct TouchTargets {
+    pub left: Option<f32>,
+    pub right: Option<f32>,
+}
+
+pub fn read_touch_input(
     mut touch_input: EventReader<TouchInput>,
-    mut paddles_query: Query<(&Paddle, &mut Transform)>,
-    windows: Query<&Window>,
+    mut targets: ResMut<TouchTargets>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
 ) {
-    let window = windows.iter().next().unwrap();
-    let (_half_window_width, half_window_height) = get_window_dimensions(window);
-
-    // Calculate paddle clamping limits
-    let half_paddle_height = PADDLE_HEIGHT / 2.0;
-    let max_paddle_y = half_window_height - half_paddle_height;
+    let Ok((camera, camera_transform)) = cameras.single() else {
+        return;
+    };
 
     for touch_event in touch_input.read() {
         // We only care about moved touches for paddle control
@@ -389,27 +820,126 @@ pub fn move_paddles_with_touch(
             continue;
         }
 
-        // Convert touch position (origin top-left) to Bevy world coordinates (origin center)
-        // Touch Y increases downwards, Bevy Y increases upwards.
-        let touch_y_bevy = half_window_height - touch_event.position.y;
+        // Touches are in window pixels (origin top-left, Y down), the camera maps them
+        // onto the scaled and letterboxed arena
+        let Ok(touch_world) = camera.viewport_to_world_2d(camera_transform, touch_event.position)
+        else {
+            continue;
+        };
 
-        // Determine which paddle to move based on touch X position
-        // (0,0) is top-left for touch_event.position.x, window.width() is right edge
-        if touch_event.position.x < window.width() / 2.0 {
-            // Touch is on the left half of the screen, move left paddle
-            if let Some((_paddle, mut transform)) =
-                paddles_query.iter_mut().find(|(p, _)| p.side == Side::Left)
-            {
-                transform.translation.y = touch_y_bevy.clamp(-max_paddle_y, max_paddle_y);
-            }
+        // Determine which paddle to move based on which half of the arena was touched
+        if touch_world.x < 0.0 {
+            targets.left = Some(touch_world.y);
         } else {
-            // Touch is on the right half of the screen, move right paddle
-            if let Some((_paddle, mut transform)) = paddles_query
-                .iter_mut()
-                .find(|(p, _)| p.side == Side::Right)
-            {
-                transform.translation.y = touch_y_bevy.clamp(-max_paddle_y, max_paddle_y);
-            }
+            targets.right = Some(touch_world.y);
+        }
+    }
+}
+
+pub fn move_paddles_with_touch(
+    mut targets: ResMut<TouchTargets>,
+    tuning: Res<GameTuning>,
+    mut paddles_query: Query<
+        (&Paddle, &PaddleScale, Has<ControlsReversed>, &mut Transform),
+        Without<AiController>,
+    >,
+) {
+    // CPU paddles aren't in the query, so touches on their half are ignored
+    for (paddle, scale, reversed, mut transform) in paddles_query.iter_mut() {
+        let target = match paddle.player() {
+            Side::Left => targets.left.take(),
+            Side::Right => targets.right.take(),
+            // Only the two halves of the screen are told apart, so that's just players one and two
+            Side::Top | Side::Bottom => None,
+        };
+        if let Some(touch_y) = target {
+            // Reversed controls mirror the finger about the middle of the arena
+            transform.translation.y = if reversed { -touch_y } else { touch_y };
+            constrain_paddle(&mut transform.translation, paddle, *scale, &tuning);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::sweep_circle_aabb;
+
+    fn paddle() -> Aabb {
+        Aabb {
+            center: Vec2::ZERO,
+            half_size: GameTuning::default().paddle_half_size(),
+        }
+    }
+
+    // Sweeps a ball into the paddle at the origin and bounces it off whatever it touched
+    fn bounce(start: Vec2, velocity: Vec2) -> (ContactKind, Vec2) {
+        let motion = velocity * 0.5;
+        let tuning = GameTuning::default();
+        let hit =
+            sweep_circle_aabb(start, motion, tuning.ball_radius, paddle()).expect("should hit");
+        let contact = start + motion * hit.time;
+        (
+            ContactKind::from_normal(hit.normal),
+            paddle_bounce(
+                velocity,
+                contact.y,
+                0.0,
+                tuning.paddle_height / 2.0,
+                hit.normal,
+                &tuning,
+            ),
+        )
+    }
+
+    #[test]
+    fn face_hit_in_the_middle_returns_the_ball() {
+        let (kind, velocity) = bounce(Vec2::new(100.0, 0.0), Vec2::new(-400.0, 0.0));
+        assert_eq!(kind, ContactKind::Face);
+        assert_eq!(velocity, Vec2::new(440.0, 0.0));
+    }
+
+    #[test]
+    fn face_hit_near_the_top_deflects_upwards() {
+        let (kind, velocity) = bounce(Vec2::new(100.0, 40.0), Vec2::new(-400.0, 0.0));
+        assert_eq!(kind, ContactKind::Face);
+        assert!(velocity.x > 0.0);
+        assert!(velocity.y > 0.0);
+    }
+
+    #[test]
+    fn top_edge_hit_keeps_heading_for_the_goal() {
+        // Dropping onto the top of the paddle while still moving left
+        let (kind, velocity) = bounce(Vec2::new(2.0, 100.0), Vec2::new(-20.0, -300.0));
+        assert_eq!(kind, ContactKind::Edge);
+        let edge_speed = GameTuning::default().edge_deflection_speed();
+        assert_eq!(velocity, Vec2::new(-20.0, edge_speed));
+    }
+
+    #[test]
+    fn bottom_edge_hit_goes_down() {
+        let (kind, velocity) = bounce(Vec2::new(-1.0, -100.0), Vec2::new(10.0, 700.0));
+        assert_eq!(kind, ContactKind::Edge);
+        assert_eq!(velocity, Vec2::new(10.0, -700.0));
+    }
+
+    #[test]
+    fn corner_hit_reflects_along_the_normal() {
+        // Straight at the top-right corner along the diagonal, so it comes straight back
+        let corner = GameTuning::default().paddle_half_size();
+        let start = corner + Vec2::new(40.0, 40.0);
+        let (kind, velocity) = bounce(start, Vec2::new(-200.0, -200.0));
+        assert_eq!(kind, ContactKind::Corner);
+        let expected = 200.0 * GameTuning::default().ball_acceleration;
+        assert!((velocity.x - expected).abs() < 1e-3);
+        assert!((velocity.y - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bounces_never_exceed_max_speed() {
+        let (_, velocity) = bounce(Vec2::new(100.0, 45.0), Vec2::new(-800.0, -800.0));
+        let tuning = GameTuning::default();
+        assert!(velocity.x.abs() <= tuning.max_ball_speed_x);
+        assert!(velocity.y.abs() <= tuning.max_ball_speed_y);
+    }
+}
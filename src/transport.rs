@@ -0,0 +1,437 @@
+// How network play's packets get to the other end. Plain UDP between two machines that can
+// reach each other, or WebSockets through a relay (see relay/) for the browser build, which
+// can't open sockets of its own. Anything else that moves packets, like a WebRTC data channel,
+// only has to implement Transport.
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeerRole {
+    // Waits to be said hello to, and plays on the left
+    Host,
+    // Says hello, and plays on the right
+    Join,
+}
+
+pub trait Transport {
+    // None until it's known, a relay only says once the other player turns up
+    fn role(&self) -> Option<PeerRole>;
+    // Best effort, a packet that can't go is just lost
+    fn send(&mut self, bytes: &[u8]);
+    // Everything that's arrived since last time, or why the connection is gone for good
+    fn receive(&mut self) -> Result<Vec<Vec<u8>>, String>;
+    // Whoever's at the other end isn't wanted, let someone else in instead
+    fn forget_peer(&mut self) {}
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    role: PeerRole,
+    // Known from the start when joining, from the first packet when hosting
+    peer: Option<SocketAddr>,
+}
+
+impl UdpTransport {
+    pub fn host(port: u16) -> io::Result<Self> {
+        Self::open(UdpSocket::bind(("0.0.0.0", port))?, PeerRole::Host, None)
+    }
+
+    pub fn join(address: &str) -> io::Result<Self> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the address didn't resolve"))?;
+        let any = if peer.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        Self::open(UdpSocket::bind(any)?, PeerRole::Join, Some(peer))
+    }
+
+    fn open(socket: UdpSocket, role: PeerRole, peer: Option<SocketAddr>) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport { socket, role, peer })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn role(&self) -> Option<PeerRole> {
+        Some(self.role)
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        if let Some(peer) = self.peer {
+            // A full send buffer is just more packet loss
+            let _ = self.socket.send_to(bytes, peer);
+        }
+    }
+
+    // Packets from anyone but the peer are ignored. A host takes the first sender it hears from.
+    fn receive(&mut self) -> Result<Vec<Vec<u8>>, String> {
+        let mut packets = Vec::new();
+        let mut buffer = [0; 2048];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buffer) {
+            if self.peer.is_some_and(|peer| peer != from) {
+                continue;
+            }
+            self.peer = Some(from);
+            packets.push(buffer[..len].to_vec());
+        }
+        Ok(packets)
+    }
+
+    fn forget_peer(&mut self) {
+        if self.role == PeerRole::Host {
+            self.peer = None;
+        }
+    }
+}
+
+// The relay's URL for a room, e.g. `ws://localhost:9000` and `abc` make `ws://localhost:9000/abc`
+pub fn room_url(relay: &str, room: &str) -> String {
+    format!("{}/{}", relay.trim_end_matches('/'), room)
+}
+
+// What the relay has to say
+enum RelayEvent {
+    Role(PeerRole),
+    Packet(Vec<u8>),
+    Closed(String),
+}
+
+impl RelayEvent {
+    // The relay tells each player which end they're on in a text message, see relay/src/lib.rs
+    fn from_text(text: &str) -> Option<RelayEvent> {
+        match text {
+            "host" => Some(RelayEvent::Role(PeerRole::Host)),
+            "join" => Some(RelayEvent::Role(PeerRole::Join)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub use native::WebSocketTransport;
+#[cfg(target_family = "wasm")]
+pub use web::WebSocketTransport;
+
+// tungstenite on a thread of its own, so a slow connection never holds up a frame
+#[cfg(not(target_family = "wasm"))]
+mod native {
+    use super::{PeerRole, RelayEvent, Transport};
+    use std::net::TcpStream;
+    use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+    use std::time::Duration;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::{Message, WebSocket};
+
+    // How long a read waits before checking for packets to send
+    const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+    pub struct WebSocketTransport {
+        outgoing: Sender<Vec<u8>>,
+        incoming: Receiver<RelayEvent>,
+        role: Option<PeerRole>,
+    }
+
+    impl WebSocketTransport {
+        // Connects in the background, anything that goes wrong comes out of receive()
+        pub fn connect(url: &str) -> Self {
+            let (outgoing, to_send) = channel();
+            let (arrived, incoming) = channel();
+            let url = url.to_string();
+            std::thread::spawn(move || {
+                let reason = match open(&url) {
+                    Ok(mut socket) => run(&mut socket, &to_send, &arrived),
+                    Err(error) => format!("Couldn't reach the relay at {url}: {error}"),
+                };
+                let _ = arrived.send(RelayEvent::Closed(reason));
+            });
+            WebSocketTransport {
+                outgoing,
+                incoming,
+                role: None,
+            }
+        }
+    }
+
+    // Plain `ws://` only, put the relay behind a proxy for `wss://`
+    fn open(url: &str) -> Result<WebSocket<TcpStream>, String> {
+        let request = url.into_client_request().map_err(|e| e.to_string())?;
+        let host = request.uri().host().ok_or("the URL has no host")?;
+        let port = request.uri().port_u16().unwrap_or(80);
+        let stream = TcpStream::connect((host, port)).map_err(|e| e.to_string())?;
+        let (socket, _) = tungstenite::client(request, stream).map_err(|e| e.to_string())?;
+        socket
+            .get_ref()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|e| e.to_string())?;
+        Ok(socket)
+    }
+
+    // Passes packets both ways until the connection closes, and says why it did
+    fn run(
+        socket: &mut WebSocket<TcpStream>,
+        to_send: &Receiver<Vec<u8>>,
+        arrived: &Sender<RelayEvent>,
+    ) -> String {
+        loop {
+            loop {
+                match to_send.try_recv() {
+                    Ok(bytes) => {
+                        if let Err(error) = socket.send(Message::binary(bytes)) {
+                            return error.to_string();
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // The transport was dropped
+                    Err(TryRecvError::Disconnected) => {
+                        let _ = socket.close(None);
+                        let _ = socket.flush();
+                        return String::new();
+                    }
+                }
+            }
+
+            let event = match socket.read() {
+                Ok(Message::Binary(bytes)) => RelayEvent::Packet(bytes.to_vec()),
+                Ok(Message::Text(text)) => match RelayEvent::from_text(&text) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Ok(Message::Close(frame)) => {
+                    return frame
+                        .map(|frame| frame.reason.to_string())
+                        .filter(|reason| !reason.is_empty())
+                        .unwrap_or_else(|| "The relay closed the connection".to_string());
+                }
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(error))
+                    if matches!(
+                        error.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(error) => return error.to_string(),
+            };
+            if arrived.send(event).is_err() {
+                return String::new();
+            }
+        }
+    }
+
+    impl Transport for WebSocketTransport {
+        fn role(&self) -> Option<PeerRole> {
+            self.role
+        }
+
+        fn send(&mut self, bytes: &[u8]) {
+            let _ = self.outgoing.send(bytes.to_vec());
+        }
+
+        fn receive(&mut self) -> Result<Vec<Vec<u8>>, String> {
+            let mut packets = Vec::new();
+            loop {
+                match self.incoming.try_recv() {
+                    Ok(RelayEvent::Role(role)) => self.role = Some(role),
+                    Ok(RelayEvent::Packet(bytes)) => packets.push(bytes),
+                    Ok(RelayEvent::Closed(reason)) => return Err(reason),
+                    Err(TryRecvError::Empty) => return Ok(packets),
+                    Err(TryRecvError::Disconnected) => {
+                        return Err("The relay closed the connection".to_string())
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The browser's own WebSocket, which hands messages over through callbacks
+#[cfg(target_family = "wasm")]
+mod web {
+    use super::{PeerRole, RelayEvent, Transport};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use wasm_bindgen::{closure::Closure, JsCast};
+    use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+    pub struct WebSocketTransport {
+        socket: Option<WebSocket>,
+        incoming: Rc<RefCell<VecDeque<RelayEvent>>>,
+        role: Option<PeerRole>,
+        // The browser calls these for as long as the socket's open
+        _on_message: Option<Closure<dyn FnMut(MessageEvent)>>,
+        _on_close: Option<Closure<dyn FnMut(CloseEvent)>>,
+    }
+
+    impl WebSocketTransport {
+        pub fn connect(url: &str) -> Self {
+            let incoming = Rc::new(RefCell::new(VecDeque::new()));
+            let Ok(socket) = WebSocket::new(url) else {
+                incoming.borrow_mut().push_back(RelayEvent::Closed(format!(
+                    "Couldn't reach the relay at {url}"
+                )));
+                return WebSocketTransport {
+                    socket: None,
+                    incoming,
+                    role: None,
+                    _on_message: None,
+                    _on_close: None,
+                };
+            };
+            socket.set_binary_type(BinaryType::Arraybuffer);
+
+            let queue = incoming.clone();
+            let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let data = event.data();
+                let event = if let Some(text) = data.as_string() {
+                    RelayEvent::from_text(&text)
+                } else if let Ok(buffer) = data.dyn_into::<js_sys::ArrayBuffer>() {
+                    Some(RelayEvent::Packet(
+                        js_sys::Uint8Array::new(&buffer).to_vec(),
+                    ))
+                } else {
+                    None
+                };
+                if let Some(event) = event {
+                    queue.borrow_mut().push_back(event);
+                }
+            });
+            socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+            let queue = incoming.clone();
+            let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+                let reason = event.reason();
+                let reason = if reason.is_empty() {
+                    "The relay closed the connection".to_string()
+                } else {
+                    reason
+                };
+                queue.borrow_mut().push_back(RelayEvent::Closed(reason));
+            });
+            socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+            WebSocketTransport {
+                socket: Some(socket),
+                incoming,
+                role: None,
+                _on_message: Some(on_message),
+                _on_close: Some(on_close),
+            }
+        }
+    }
+
+    impl Transport for WebSocketTransport {
+        fn role(&self) -> Option<PeerRole> {
+            self.role
+        }
+
+        fn send(&mut self, bytes: &[u8]) {
+            if let Some(socket) = &self.socket {
+                if socket.ready_state() == WebSocket::OPEN {
+                    let _ = socket.send_with_u8_array(bytes);
+                }
+            }
+        }
+
+        fn receive(&mut self) -> Result<Vec<Vec<u8>>, String> {
+            let mut packets = Vec::new();
+            while let Some(event) = self.incoming.borrow_mut().pop_front() {
+                match event {
+                    RelayEvent::Role(role) => self.role = Some(role),
+                    RelayEvent::Packet(bytes) => packets.push(bytes),
+                    RelayEvent::Closed(reason) => return Err(reason),
+                }
+            }
+            Ok(packets)
+        }
+    }
+
+    impl Drop for WebSocketTransport {
+        fn drop(&mut self) {
+            if let Some(socket) = &self.socket {
+                socket.set_onmessage(None);
+                socket.set_onclose(None);
+                let _ = socket.close();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    // Keeps receiving until `done` is happy with what came in
+    fn receive_until(
+        transport: &mut dyn Transport,
+        mut done: impl FnMut(&dyn Transport, &[Vec<u8>]) -> bool,
+    ) -> Vec<Vec<u8>> {
+        let started = Instant::now();
+        let mut received = Vec::new();
+        while started.elapsed() < Duration::from_secs(5) {
+            received.extend(transport.receive().expect("connection stays open"));
+            if done(transport, &received) {
+                return received;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("gave up waiting, got {received:?}");
+    }
+
+    #[test]
+    fn relay_pairs_two_players_by_room() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let relay = format!("ws://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || pong_relay::serve(listener));
+
+        let mut first = WebSocketTransport::connect(&room_url(&relay, "test"));
+        // The relay answers in order, so wait for the first to be in before the second comes along
+        std::thread::sleep(Duration::from_millis(100));
+        let mut second = WebSocketTransport::connect(&room_url(&relay, "test"));
+        receive_until(&mut second, |t, _| t.role().is_some());
+        receive_until(&mut first, |t, _| t.role().is_some());
+        assert_eq!(first.role(), Some(PeerRole::Host));
+        assert_eq!(second.role(), Some(PeerRole::Join));
+
+        second.send(b"hello");
+        let received = receive_until(&mut first, |_, packets| !packets.is_empty());
+        assert_eq!(received, vec![b"hello".to_vec()]);
+        first.send(b"welcome");
+        let received = receive_until(&mut second, |_, packets| !packets.is_empty());
+        assert_eq!(received, vec![b"welcome".to_vec()]);
+
+        drop(second);
+        let started = Instant::now();
+        let reason = loop {
+            match first.receive() {
+                Err(reason) => break reason,
+                Ok(_) if started.elapsed() > Duration::from_secs(5) => panic!("never heard"),
+                Ok(_) => std::thread::sleep(Duration::from_millis(5)),
+            }
+        };
+        assert_eq!(reason, "The other player left");
+    }
+
+    #[test]
+    fn udp_host_takes_the_first_to_say_hello() {
+        let mut host = UdpTransport::host(0).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let mut joiner = UdpTransport::join(&format!("127.0.0.1:{port}")).unwrap();
+        // Nobody to send to yet
+        host.send(b"too early");
+
+        joiner.send(b"hello");
+        let received = receive_until(&mut host, |_, packets| !packets.is_empty());
+        assert_eq!(received, vec![b"hello".to_vec()]);
+        host.send(b"welcome");
+        let received = receive_until(&mut joiner, |_, packets| !packets.is_empty());
+        assert_eq!(received, vec![b"welcome".to_vec()]);
+    }
+}
@@ -0,0 +1,445 @@
+use crate::arena::{ARENA_HALF_HEIGHT, ARENA_HALF_WIDTH};
+use crate::menu::RestartRequested;
+use crate::netplay::netplaying;
+use crate::replay::{replaying, start_recording};
+use crate::setup::setup_game;
+use crate::spectate::spectating;
+use crate::states::InGame;
+use crate::systems::restart_game;
+use crate::{Ball, Paddle, PaddleScale};
+use bevy::{
+    asset::{
+        io::{AssetReaderError, Reader},
+        AssetLoadError, AssetLoadFailedEvent, AssetLoader, LoadContext,
+    },
+    prelude::*,
+    ui::{Node, Val},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const TUNING_PATH: &str = "tuning.ron";
+
+// How the game feels. Loaded from assets/tuning.ron and reloaded whenever the file is saved,
+// so it can be tuned while playing. Anything left out of the file keeps its default.
+#[derive(Asset, Resource, TypePath, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameTuning {
+    pub paddle_width: f32,
+    pub paddle_height: f32,
+    // Gap between a paddle and the edge of the arena behind it
+    pub paddle_margin: f32,
+    // The same for the forward paddles in doubles, which have to be further in than the back ones
+    pub forward_paddle_margin: f32,
+    pub ball_radius: f32,
+    // Half arena heights per second, for keyboard and gamepad buttons. Full stick tilt matches it.
+    pub keyboard_paddle_speed: f32,
+    pub max_ball_speed_x: f32,
+    pub max_ball_speed_y: f32,
+    // Speed gained on every paddle hit, 1.1 is 10% faster
+    pub ball_acceleration: f32,
+    // Returns in a row, without touching a wall, before the ball goes into a frenzy
+    pub frenzy_hit_count: u32,
+    pub frenzy_speed_multiplier: f32,
+    // Share of the paddle's speed a return turns into spin, 0.0 for none
+    pub spin_transfer: f32,
+    // How hard spin curves the ball's path
+    pub magnus_strength: f32,
+    // How quickly spin dies away on its own, per second
+    pub spin_decay: f32,
+    // How much of its spin the ball trades for sideways speed when it bounces off the top or bottom, from 0.0 to 1.0
+    pub wall_spin_grip: f32,
+}
+
+impl Default for GameTuning {
+    fn default() -> Self {
+        GameTuning {
+            paddle_width: 10.0,
+            paddle_height: 100.0,
+            paddle_margin: 50.0,
+            forward_paddle_margin: 250.0,
+            ball_radius: 5.0,
+            keyboard_paddle_speed: 1.8,
+            max_ball_speed_x: 800.0,
+            max_ball_speed_y: 800.0,
+            ball_acceleration: 1.1,
+            frenzy_hit_count: 3,
+            frenzy_speed_multiplier: 1.5,
+            spin_transfer: 0.5,
+            magnus_strength: 0.01,
+            spin_decay: 0.5,
+            wall_spin_grip: 0.3,
+        }
+    }
+}
+
+impl GameTuning {
+    pub fn paddle_half_size(&self) -> Vec2 {
+        Vec2::new(self.paddle_width / 2.0, self.paddle_height / 2.0)
+    }
+
+    // Smallest vertical speed the ball leaves with after clipping the top or bottom of a paddle
+    pub fn edge_deflection_speed(&self) -> f32 {
+        self.max_ball_speed_y * 0.5
+    }
+
+    // Everything that would break the game, in a form that can be shown to whoever is editing the file
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut positive = |name: &str, value: f32| {
+            if value.is_nan() || value <= 0.0 {
+                problems.push(format!("{name} has to be above 0"));
+            }
+        };
+        positive("paddle_width", self.paddle_width);
+        positive("paddle_height", self.paddle_height);
+        positive("ball_radius", self.ball_radius);
+        positive("keyboard_paddle_speed", self.keyboard_paddle_speed);
+        positive("max_ball_speed_x", self.max_ball_speed_x);
+        positive("max_ball_speed_y", self.max_ball_speed_y);
+        positive("ball_acceleration", self.ball_acceleration);
+        positive("frenzy_speed_multiplier", self.frenzy_speed_multiplier);
+
+        if self.paddle_height >= ARENA_HALF_HEIGHT * 2.0 {
+            problems.push(format!(
+                "paddle_height has to be less than the arena height ({})",
+                ARENA_HALF_HEIGHT * 2.0
+            ));
+        }
+        if !(self.paddle_width / 2.0..ARENA_HALF_WIDTH).contains(&self.paddle_margin) {
+            problems.push(format!(
+                "paddle_margin has to be at least half the paddle_width and less than {ARENA_HALF_WIDTH}"
+            ));
+        }
+        // Leaving the ball room to get between them
+        if !(self.paddle_margin + self.paddle_width + self.ball_radius * 2.0..ARENA_HALF_WIDTH)
+            .contains(&self.forward_paddle_margin)
+        {
+            problems.push(format!(
+                "forward_paddle_margin has to leave room for the ball behind it and be less than {ARENA_HALF_WIDTH}"
+            ));
+        }
+        if self.ball_radius * 2.0 >= self.paddle_height {
+            problems.push("ball_radius has to be less than half the paddle_height".to_string());
+        }
+        if self.frenzy_hit_count == 0 {
+            problems.push("frenzy_hit_count has to be at least 1".to_string());
+        }
+        for (name, value) in [
+            ("spin_transfer", self.spin_transfer),
+            ("magnus_strength", self.magnus_strength),
+            ("spin_decay", self.spin_decay),
+        ] {
+            if value.is_nan() || value < 0.0 {
+                problems.push(format!("{name} can't be below 0"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.wall_spin_grip) {
+            problems.push("wall_spin_grip has to be from 0 to 1".to_string());
+        }
+        problems
+    }
+}
+
+#[derive(Debug)]
+pub enum TuningLoadError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for TuningLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TuningLoadError::Io(error) => write!(f, "couldn't read it: {error}"),
+            TuningLoadError::Parse(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for TuningLoadError {}
+
+#[derive(Default)]
+pub struct GameTuningLoader;
+
+impl AssetLoader for GameTuningLoader {
+    type Asset = GameTuning;
+    type Settings = ();
+    type Error = TuningLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<GameTuning, TuningLoadError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(TuningLoadError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(TuningLoadError::Parse)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct TuningHandle(pub Handle<GameTuning>);
+
+// What's wrong with the tuning file, if anything. Shown on screen until it's fixed.
+#[derive(Resource, Default)]
+pub struct TuningError(pub Option<String>);
+
+#[derive(Component)]
+pub struct TuningErrorText;
+
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<GameTuning>()
+            .init_asset_loader::<GameTuningLoader>()
+            .init_resource::<GameTuning>()
+            .init_resource::<TuningError>()
+            .add_systems(Startup, (load_tuning, spawn_tuning_error_text))
+            .add_systems(
+                Update,
+                (
+                    // Network matches play by the host's tuning until they're over, spectators
+                    // included, and replays by the tuning they were recorded with
+                    apply_tuning.run_if(not(netplaying).and(not(spectating)).and(not(replaying))),
+                    report_tuning_load_failures,
+                    resize_to_tuning,
+                    update_tuning_error_text,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                apply_tuning_between_matches
+                    .before(setup_game)
+                    .before(start_recording)
+                    .run_if(not(netplaying).and(not(spectating)).and(not(replaying))),
+            )
+            // A restart is a new match as well
+            .add_systems(
+                Update,
+                apply_tuning_between_matches.before(restart_game).run_if(
+                    on_event::<RestartRequested>
+                        .and(in_state(InGame))
+                        .and(not(replaying))
+                        .and(not(netplaying))
+                        .and(not(spectating)),
+                ),
+            );
+
+        #[cfg(not(target_family = "wasm"))]
+        app.init_resource::<TuningFileWatch>()
+            .add_systems(Update, watch_tuning_file.before(apply_tuning));
+    }
+}
+
+pub fn load_tuning(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TuningHandle(asset_server.load(TUNING_PATH)));
+}
+
+// The file watcher that comes with bevy needs its multi-threaded executor, so this just checks
+// when the file was last written every so often and asks for a reload when that changes
+#[cfg(not(target_family = "wasm"))]
+#[derive(Resource)]
+pub struct TuningFileWatch {
+    path: std::path::PathBuf,
+    modified: Option<std::time::SystemTime>,
+    timer: Timer,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Default for TuningFileWatch {
+    fn default() -> Self {
+        let path = bevy::asset::io::file::FileAssetReader::get_base_path()
+            .join("assets")
+            .join(TUNING_PATH);
+        TuningFileWatch {
+            modified: last_modified(&path),
+            path,
+            timer: Timer::from_seconds(0.5, TimerMode::Repeating),
+        }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn last_modified(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn watch_tuning_file(
+    time: Res<Time<Real>>,
+    mut watch: ResMut<TuningFileWatch>,
+    asset_server: Res<AssetServer>,
+) {
+    if !watch.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = last_modified(&watch.path);
+    if modified != watch.modified {
+        watch.modified = modified;
+        asset_server.reload(TUNING_PATH);
+    }
+}
+
+// Swaps in the file's tuning whenever it loads or changes, as long as it makes sense.
+// Replays record the changes as they happen, see record_tick.
+pub fn apply_tuning(
+    mut events: EventReader<AssetEvent<GameTuning>>,
+    handle: Res<TuningHandle>,
+    assets: Res<Assets<GameTuning>>,
+    mut tuning: ResMut<GameTuning>,
+    mut error: ResMut<TuningError>,
+) {
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        let Some(loaded) = assets.get(&handle.0) else {
+            continue;
+        };
+        let problems = loaded.problems();
+        if problems.is_empty() {
+            if *tuning != *loaded {
+                *tuning = loaded.clone();
+                println!("Loaded {TUNING_PATH}");
+            }
+            error.0 = None;
+        } else {
+            error.0 = Some(format!(
+                "{TUNING_PATH} wasn't applied:\n{}",
+                problems.join("\n")
+            ));
+        }
+    }
+}
+
+// Catches up on changes to the file that were missed while watching a replay, playing over the
+// network or spectating
+pub fn apply_tuning_between_matches(
+    handle: Res<TuningHandle>,
+    assets: Res<Assets<GameTuning>>,
+    mut tuning: ResMut<GameTuning>,
+) {
+    let Some(loaded) = assets.get(&handle.0) else {
+        return;
+    };
+    if loaded.problems().is_empty() && *tuning != *loaded {
+        *tuning = loaded.clone();
+        println!("Loaded {TUNING_PATH}");
+    }
+}
+
+pub fn report_tuning_load_failures(
+    mut failures: EventReader<AssetLoadFailedEvent<GameTuning>>,
+    mut error: ResMut<TuningError>,
+) {
+    for failure in failures.read() {
+        match &failure.error {
+            // Not having a tuning file at all is fine, the defaults are used
+            AssetLoadError::AssetReaderError(AssetReaderError::NotFound(_)) => {
+                println!("No {TUNING_PATH} found, using the built-in tuning");
+            }
+            other => error.0 = Some(format!("{TUNING_PATH} couldn't be loaded:\n{other}")),
+        }
+    }
+}
+
+// Paddles and the ball are drawn at the tuned size, grown or shrunk by any power-ups
+#[allow(clippy::type_complexity)]
+pub fn resize_to_tuning(
+    tuning: Res<GameTuning>,
+    mut paddles: Query<(&Paddle, &PaddleScale, &mut Sprite), Without<Ball>>,
+    mut balls: Query<&mut Sprite, With<Ball>>,
+) {
+    if !tuning.is_changed() {
+        return;
+    }
+    for (paddle, scale, mut sprite) in paddles.iter_mut() {
+        sprite.custom_size = Some(scale.size(paddle.side, &tuning));
+    }
+    for mut sprite in balls.iter_mut() {
+        sprite.custom_size = Some(Vec2::splat(tuning.ball_radius * 2.0));
+    }
+}
+
+pub fn spawn_tuning_error_text(mut commands: Commands) {
+    commands.spawn((
+        TuningErrorText,
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::srgb(1.0, 0.3, 0.3)),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            ..default()
+        },
+        // Above the menus and overlays
+        GlobalZIndex(10),
+    ));
+}
+
+pub fn update_tuning_error_text(
+    error: Res<TuningError>,
+    mut texts: Query<&mut Text, With<TuningErrorText>>,
+) {
+    if !error.is_changed() {
+        return;
+    }
+    for mut text in texts.iter_mut() {
+        text.0 = error.0.clone().unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(GameTuning::default().problems().is_empty());
+    }
+
+    #[test]
+    fn bundled_file_matches_the_defaults() {
+        let bundled: GameTuning = ron::from_str(include_str!("../assets/tuning.ron")).unwrap();
+        assert_eq!(bundled, GameTuning::default());
+    }
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let tuning: GameTuning = ron::from_str("(paddle_height: 150.0)").unwrap();
+        assert_eq!(tuning.paddle_height, 150.0);
+        assert_eq!(tuning.ball_radius, GameTuning::default().ball_radius);
+    }
+
+    #[test]
+    fn nonsense_is_reported() {
+        let tuning = GameTuning {
+            paddle_height: 0.0,
+            frenzy_hit_count: 0,
+            ..default()
+        };
+        let problems = tuning.problems();
+        assert!(problems
+            .iter()
+            .any(|problem| problem.contains("paddle_height")));
+        assert!(problems
+            .iter()
+            .any(|problem| problem.contains("frenzy_hit_count")));
+    }
+}
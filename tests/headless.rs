@@ -0,0 +1,703 @@
+// Whole matches run headless, one fixed tick per update, with paddles driven by script or the CPU
+use bevy::{
+    ecs::system::RunSystemOnce, prelude::*, time::TimeUpdateStrategy, window::FileDragAndDrop,
+};
+use pong::{
+    ai::{AiSettings, Difficulty},
+    arena::ARENA_HALF_HEIGHT,
+    controls::{Action, ActionState},
+    menu::RestartRequested,
+    netplay::{NetRole, NetSession, NetSettings, NetplayPlugin},
+    powerups::{PowerUpKind, PowerUps},
+    replay::{PlayReplay, Replay, ReplayPlayback, ReplayPlugin, ReplayRecorder},
+    rules::{MatchProgress, MatchRules, RulesPreset},
+    simulation::{Interpolated, DEFAULT_TICK_RATE_HZ},
+    snapshot::MatchState,
+    sound::{PlaySound, SoundEffect},
+    states::AppState,
+    systems::AwaitingServe,
+    tuning::GameTuning,
+    Ball, BallNumber, Paddle, PongPlugin, Role, Score, Side, Velocity,
+};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+// Every sound the match asked for, which is how bounces and goals show up from outside
+#[derive(Resource, Default)]
+struct Heard(Vec<SoundEffect>);
+
+fn listen(mut sounds: EventReader<PlaySound>, mut heard: ResMut<Heard>) {
+    heard.0.extend(sounds.read().map(|sound| sound.effect));
+}
+
+fn headless_app(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(PongPlugin::headless(seed))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / DEFAULT_TICK_RATE_HZ,
+        )))
+        .init_resource::<Heard>()
+        .add_systems(Update, listen);
+    app
+}
+
+fn start_match(app: &mut App) {
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Playing);
+    app.update();
+}
+
+fn state(app: &App) -> AppState {
+    *app.world().resource::<State<AppState>>().get()
+}
+
+fn ball(app: &mut App) -> (Vec3, Vec2, bool) {
+    let world = app.world_mut();
+    let (entity, transform, velocity) = world
+        .query_filtered::<(Entity, &Transform, &Velocity), With<Ball>>()
+        .single(world)
+        .expect("one ball");
+    let waiting = world.get::<AwaitingServe>(entity).is_some();
+    (
+        transform.translation,
+        Vec2::new(velocity.x, velocity.y),
+        waiting,
+    )
+}
+
+fn paddle_y(app: &mut App, side: Side) -> f32 {
+    let world = app.world_mut();
+    world
+        .query::<(&Transform, &Paddle)>()
+        .iter(world)
+        .find(|(_, paddle)| paddle.side == side && paddle.role == Role::Back)
+        .expect("both paddles")
+        .0
+        .translation
+        .y
+}
+
+fn shown_score(app: &mut App, side: Side) -> u32 {
+    let world = app.world_mut();
+    world
+        .query::<&Score>()
+        .iter(world)
+        .find(|score| score.side == side)
+        .expect("both scores")
+        .value
+}
+
+fn press(app: &mut App, side: Side, action: Action, pressed: bool) {
+    app.world_mut()
+        .resource_mut::<ActionState>()
+        .for_side_mut(side)
+        .set_pressed(action, pressed);
+}
+
+// Runs until `done` or panics after `max_ticks`
+fn run_until(app: &mut App, max_ticks: u32, mut done: impl FnMut(&mut App) -> bool) {
+    for _ in 0..max_ticks {
+        app.update();
+        if done(app) {
+            return;
+        }
+    }
+    panic!("gave up after {max_ticks} ticks");
+}
+
+#[test]
+fn scripted_paddles_move_and_stay_in_the_arena() {
+    let mut app = headless_app(2);
+    start_match(&mut app);
+    let start = paddle_y(&mut app, Side::Left);
+
+    press(&mut app, Side::Left, Action::PaddleUp, true);
+    press(&mut app, Side::Right, Action::PaddleDown, true);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(paddle_y(&mut app, Side::Left) > start);
+    assert!(paddle_y(&mut app, Side::Right) < start);
+
+    // Held long enough to hit the walls
+    for _ in 0..(DEFAULT_TICK_RATE_HZ as u32 * 5) {
+        app.update();
+    }
+    let top = paddle_y(&mut app, Side::Left);
+    let bottom = paddle_y(&mut app, Side::Right);
+    assert!(top < ARENA_HALF_HEIGHT && top > 0.0);
+    assert_eq!(top, -bottom);
+    app.update();
+    assert_eq!(paddle_y(&mut app, Side::Left), top);
+}
+
+#[test]
+fn an_unreturned_ball_is_a_point_and_waits_for_the_serve() {
+    let mut app = headless_app(3);
+    start_match(&mut app);
+    assert_eq!(state(&app), AppState::Playing);
+    // The opening ball is already on its way
+    assert!(!ball(&mut app).2);
+    // Both paddles out of the way at the top
+    press(&mut app, Side::Left, Action::PaddleUp, true);
+    press(&mut app, Side::Right, Action::PaddleUp, true);
+
+    run_until(&mut app, 2000, |app| {
+        app.world()
+            .resource::<Heard>()
+            .0
+            .contains(&SoundEffect::Goal)
+    });
+    let points = app.world().resource::<MatchProgress>().points;
+    assert_eq!(points.left + points.right, 1);
+    assert_eq!(shown_score(&mut app, Side::Left), points.left);
+    assert_eq!(shown_score(&mut app, Side::Right), points.right);
+
+    // Back in the middle until the player it's heading for serves
+    for _ in 0..10 {
+        app.update();
+    }
+    let (position, velocity, waiting) = ball(&mut app);
+    assert!(waiting);
+    assert_eq!(position.truncate(), Vec2::ZERO);
+    assert_eq!(velocity, Vec2::ZERO);
+    let receiver = {
+        let world = app.world_mut();
+        world
+            .query::<&AwaitingServe>()
+            .single(world)
+            .expect("one ball waiting")
+            .receiver
+    };
+    press(&mut app, receiver, Action::Serve, true);
+    app.update();
+    let (_, velocity, waiting) = ball(&mut app);
+    assert!(!waiting);
+    let heading = if velocity.x < 0.0 {
+        Side::Left
+    } else {
+        Side::Right
+    };
+    assert_eq!(heading, receiver);
+}
+
+#[test]
+fn the_ball_bounces_off_walls_and_paddles() {
+    let mut app = headless_app(4);
+    app.insert_resource(AiSettings {
+        left: Some(Difficulty::Impossible),
+        right: Some(Difficulty::Impossible),
+        ..default()
+    });
+    start_match(&mut app);
+
+    for _ in 0..(DEFAULT_TICK_RATE_HZ as u32 * 30) {
+        app.update();
+        let (position, _, _) = ball(&mut app);
+        assert!(
+            position.y.abs() < ARENA_HALF_HEIGHT,
+            "ball left the arena at {position}"
+        );
+    }
+    let heard = &app.world().resource::<Heard>().0;
+    assert!(heard.contains(&SoundEffect::PaddleHit));
+    assert!(heard.contains(&SoundEffect::WallBounce));
+}
+
+#[test]
+fn the_match_ends_once_someone_wins_and_can_be_restarted() {
+    let mut app = headless_app(5);
+    app.insert_resource(MatchRules {
+        points_to_win: 3,
+        ..default()
+    })
+    .insert_resource(AiSettings {
+        left: Some(Difficulty::Impossible),
+        right: None,
+        ..default()
+    });
+    start_match(&mut app);
+    // The right paddle hides in the top corner
+    press(&mut app, Side::Right, Action::PaddleUp, true);
+
+    run_until(&mut app, 60 * DEFAULT_TICK_RATE_HZ as u32, |app| {
+        state(app) == AppState::GameOver
+    });
+    let progress = app.world().resource::<MatchProgress>();
+    assert_eq!(progress.winner, Some(Side::Left));
+    assert_eq!(progress.points.left, 3);
+    assert_eq!(shown_score(&mut app, Side::Left), 3);
+
+    app.world_mut().send_event(RestartRequested);
+    app.update();
+    app.update();
+    assert_eq!(state(&app), AppState::Playing);
+    assert_eq!(app.world().resource::<MatchProgress>().winner, None);
+    assert_eq!(shown_score(&mut app, Side::Left), 0);
+}
+
+#[test]
+fn the_same_seed_plays_the_same_match() {
+    let mut apps = [headless_app(6), headless_app(6)];
+    for app in &mut apps {
+        app.insert_resource(AiSettings {
+            left: Some(Difficulty::Hard),
+            right: Some(Difficulty::Normal),
+            ..default()
+        });
+        start_match(app);
+        for _ in 0..(DEFAULT_TICK_RATE_HZ as u32 * 20) {
+            app.update();
+        }
+    }
+    let [first, second] = &mut apps;
+    assert_eq!(ball(first), ball(second));
+    let [first, second] = [first, second].map(|app| app.world().resource::<MatchProgress>());
+    assert_eq!(first.points, second.points);
+}
+
+// Where the ball and every paddle are, exactly
+fn transforms(app: &mut App) -> Vec<Transform> {
+    let world = app.world_mut();
+    let mut transforms: Vec<_> = world
+        .query::<(&Transform, &Paddle)>()
+        .iter(world)
+        .map(|(transform, paddle)| (paddle.side as u8, *transform))
+        .collect();
+    transforms.sort_by_key(|(side, _)| *side);
+    let ball = *world
+        .query_filtered::<&Transform, With<Ball>>()
+        .single(world)
+        .expect("one ball");
+    transforms
+        .into_iter()
+        .map(|(_, transform)| transform)
+        .chain([ball])
+        .collect()
+}
+
+#[test]
+fn the_same_inputs_give_bit_identical_ticks() {
+    let mut apps = [headless_app(8), headless_app(8)];
+    for app in &mut apps {
+        start_match(app);
+    }
+    for tick in 0..(DEFAULT_TICK_RATE_HZ as u32 * 10) {
+        for app in &mut apps {
+            // Each paddle sweeps up and down on a rhythm of its own
+            press(app, Side::Left, Action::PaddleUp, tick % 90 < 45);
+            press(app, Side::Left, Action::PaddleDown, tick % 90 >= 45);
+            press(app, Side::Right, Action::PaddleUp, tick % 70 >= 35);
+            press(app, Side::Right, Action::PaddleDown, tick % 70 < 35);
+            app.update();
+        }
+        let [first, second] = &mut apps;
+        assert_eq!(transforms(first), transforms(second), "tick {tick}");
+    }
+}
+
+#[test]
+fn power_ups_are_picked_up_the_same_way_every_time() {
+    let mut apps = [headless_app(7), headless_app(7)];
+    for app in &mut apps {
+        app.insert_resource(MatchRules {
+            power_ups: true,
+            ..default()
+        })
+        .insert_resource(AiSettings {
+            left: Some(Difficulty::Hard),
+            right: Some(Difficulty::Hard),
+            ..default()
+        });
+        start_match(app);
+        run_until(app, 120 * DEFAULT_TICK_RATE_HZ as u32, |app| {
+            app.world()
+                .resource::<Heard>()
+                .0
+                .contains(&SoundEffect::PowerUp)
+        });
+    }
+    let [first, second] = &mut apps;
+    let picked_up = |app: &App| {
+        let power_ups = app.world().resource::<PowerUps>();
+        power_ups
+            .active
+            .iter()
+            .map(|effect| (effect.kind, effect.owner))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(picked_up(first).len(), 1);
+    assert_eq!(picked_up(first), picked_up(second));
+    assert_eq!(ball(first), ball(second));
+}
+
+#[test]
+fn reverse_sends_the_cpu_the_wrong_way() {
+    let mut apps = [headless_app(13), headless_app(13)];
+    for app in &mut apps {
+        app.insert_resource(AiSettings {
+            left: Some(Difficulty::Hard),
+            ..default()
+        });
+        start_match(app);
+        let world = app.world_mut();
+        let mut paddles = world.query::<(&mut Transform, &mut Interpolated, &Paddle)>();
+        for (mut transform, mut interpolated, paddle) in paddles.iter_mut(world) {
+            if paddle.side == Side::Left {
+                transform.translation.y = 100.0;
+                *interpolated = Interpolated::at(transform.translation);
+            }
+        }
+    }
+    let [normal, reversed] = &mut apps;
+    reversed
+        .world_mut()
+        .resource_mut::<PowerUps>()
+        .grant(PowerUpKind::Reverse, Side::Right);
+    for _ in 0..5 {
+        normal.update();
+        reversed.update();
+    }
+
+    // It was put above anywhere it'd aim for, so reversed it climbs instead
+    assert!(paddle_y(normal, Side::Left) < 100.0);
+    assert!(paddle_y(reversed, Side::Left) > 100.0);
+}
+
+#[test]
+fn chaos_balls_pile_up_and_score_separately_without_a_serve() {
+    let mut app = headless_app(3);
+    app.insert_resource(RulesPreset::Chaos.rules())
+        .insert_resource(AiSettings {
+            left: Some(Difficulty::Hard),
+            right: Some(Difficulty::Hard),
+            ..default()
+        });
+    start_match(&mut app);
+
+    let mut seen = HashSet::new();
+    let mut in_play = 0;
+    run_until(&mut app, 120 * DEFAULT_TICK_RATE_HZ as u32, |app| {
+        let world = app.world_mut();
+        let balls = world
+            .query_filtered::<(&BallNumber, Has<AwaitingServe>), With<Ball>>()
+            .iter(world)
+            .map(|(number, waiting)| (*number, waiting))
+            .collect::<Vec<_>>();
+        assert!(
+            balls.iter().all(|(_, waiting)| !waiting),
+            "served with other balls still in play"
+        );
+        seen.extend(balls.iter().map(|(number, _)| *number));
+        in_play = balls.len();
+        in_play > 1 && in_play < seen.len()
+    });
+    // The balls that have gone were each a point, and weren't put back
+    let points = app.world().resource::<MatchProgress>().points;
+    assert_eq!(
+        (points.get(Side::Left) + points.get(Side::Right)) as usize,
+        seen.len() - in_play
+    );
+}
+
+#[test]
+fn top_and_bottom_paddles_slide_along_their_walls() {
+    let mut app = headless_app(8);
+    app.insert_resource(RulesPreset::FourPlayers.rules());
+    start_match(&mut app);
+    let paddle = |app: &mut App, side: Side| {
+        let world = app.world_mut();
+        world
+            .query::<(&Transform, &Paddle)>()
+            .iter(world)
+            .find(|(_, paddle)| paddle.side == side)
+            .expect("a paddle on every side")
+            .0
+            .translation
+    };
+    let start = paddle(&mut app, Side::Top);
+    assert!(start.y > 0.0 && paddle(&mut app, Side::Bottom).y == -start.y);
+
+    press(&mut app, Side::Top, Action::PaddleUp, true);
+    press(&mut app, Side::Bottom, Action::PaddleDown, true);
+    for _ in 0..(DEFAULT_TICK_RATE_HZ as u32 * 5) {
+        app.update();
+    }
+    let top = paddle(&mut app, Side::Top);
+    let bottom = paddle(&mut app, Side::Bottom);
+    assert_eq!(top.y, start.y);
+    assert_eq!(bottom.x, -top.x);
+    // Stopped short of the left and right paddles' columns
+    assert!(top.x > 0.0 && top.x < paddle(&mut app, Side::Right).x);
+    assert_eq!(shown_score(&mut app, Side::Top), 3);
+}
+
+#[test]
+fn four_players_are_knocked_out_until_one_is_left() {
+    let mut app = headless_app(9);
+    app.insert_resource(MatchRules {
+        lives: 1,
+        ..RulesPreset::FourPlayers.rules()
+    })
+    .insert_resource(AiSettings {
+        left: Some(Difficulty::Easy),
+        right: Some(Difficulty::Easy),
+        top: Some(Difficulty::Easy),
+        bottom: Some(Difficulty::Easy),
+    });
+    start_match(&mut app);
+
+    run_until(&mut app, 600 * DEFAULT_TICK_RATE_HZ as u32, |app| {
+        state(app) == AppState::GameOver
+    });
+    let progress = app.world().resource::<MatchProgress>().clone();
+    let winner = progress.winner.expect("someone won");
+    let out: Vec<Side> = Side::ALL
+        .into_iter()
+        .filter(|side| progress.knocked_out(*side))
+        .collect();
+    assert_eq!(out.len(), 3);
+    assert!(!out.contains(&winner));
+    assert_eq!(shown_score(&mut app, winner), 1);
+}
+
+fn doubles_paddle(app: &mut App, side: Side, role: Role) -> Vec3 {
+    let world = app.world_mut();
+    world
+        .query::<(&Transform, &Paddle)>()
+        .iter(world)
+        .find(|(_, paddle)| paddle.side == side && paddle.role == role)
+        .expect("two paddles a side")
+        .0
+        .translation
+}
+
+#[test]
+fn doubles_partners_each_move_their_own_paddle() {
+    let mut app = headless_app(10);
+    app.insert_resource(RulesPreset::Doubles.rules());
+    start_match(&mut app);
+    let back = doubles_paddle(&mut app, Side::Left, Role::Back);
+    let forward = doubles_paddle(&mut app, Side::Left, Role::Forward);
+    assert!(back.x < forward.x && forward.x < 0.0);
+    assert_eq!(
+        doubles_paddle(&mut app, Side::Right, Role::Forward).x,
+        -forward.x
+    );
+
+    // Player three is on the left's forward paddle
+    press(&mut app, Side::Top, Action::PaddleUp, true);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(doubles_paddle(&mut app, Side::Left, Role::Forward).y > forward.y);
+    assert_eq!(doubles_paddle(&mut app, Side::Left, Role::Back), back);
+    // Still one score a side
+    let world = app.world_mut();
+    assert_eq!(world.query::<&Score>().iter(world).count(), 2);
+}
+
+#[test]
+fn a_return_from_the_back_paddle_passes_through_the_forward_one() {
+    let mut app = headless_app(11);
+    app.insert_resource(RulesPreset::Doubles.rules());
+    start_match(&mut app);
+    let forward = doubles_paddle(&mut app, Side::Left, Role::Forward);
+
+    // Just behind the forward paddle, heading out of the back court
+    {
+        let world = app.world_mut();
+        let (mut transform, mut velocity) = world
+            .query_filtered::<(&mut Transform, &mut Velocity), With<Ball>>()
+            .single_mut(world)
+            .expect("one ball");
+        transform.translation = Vec3::new(forward.x - 20.0, forward.y, transform.translation.z);
+        velocity.x = 400.0;
+        velocity.y = 0.0;
+    }
+    app.world_mut().resource_mut::<Heard>().0.clear();
+    for _ in 0..20 {
+        app.update();
+    }
+    assert!(ball(&mut app).0.x > forward.x + 20.0);
+    assert!(!app
+        .world()
+        .resource::<Heard>()
+        .0
+        .contains(&SoundEffect::PaddleHit));
+}
+
+#[test]
+fn cpu_doubles_teams_play_a_match_out() {
+    let mut app = headless_app(12);
+    app.insert_resource(MatchRules {
+        points_to_win: 3,
+        ..RulesPreset::Doubles.rules()
+    })
+    .insert_resource(AiSettings {
+        left: Some(Difficulty::Easy),
+        right: Some(Difficulty::Easy),
+        top: Some(Difficulty::Easy),
+        bottom: Some(Difficulty::Easy),
+    });
+    start_match(&mut app);
+
+    run_until(&mut app, 600 * DEFAULT_TICK_RATE_HZ as u32, |app| {
+        state(app) == AppState::GameOver
+    });
+    let winner = app
+        .world()
+        .resource::<MatchProgress>()
+        .winner
+        .expect("a team won");
+    assert_eq!(shown_score(&mut app, winner), 3);
+}
+
+// Replays aren't part of the headless plugin, so this brings them in with the input they look at
+fn replay_app(seed: u64) -> App {
+    let mut app = headless_app(seed);
+    app.add_plugins(ReplayPlugin)
+        .init_resource::<ButtonInput<KeyCode>>()
+        .add_event::<FileDragAndDrop>();
+    app
+}
+
+fn checksum(app: &mut App) -> u64 {
+    app.world_mut()
+        .run_system_once(|state: MatchState| state.snapshot().expect("a match").checksum())
+        .expect("the match state is there")
+}
+
+fn playback_tick(app: &App) -> usize {
+    app.world().resource::<ReplayPlayback>().tick
+}
+
+// Plays the replay to its end, seeking from each tick in `seeks` to the one that goes with it
+fn play_back(replay: &Replay, seeks: &[(usize, usize)]) -> App {
+    let mut app = replay_app(1);
+    app.world_mut().send_event(PlayReplay(replay.clone()));
+    app.update();
+    let mut seeks = seeks.iter().peekable();
+    run_until(&mut app, replay.ticks.len() as u32 * 2, |app| {
+        if let Some((_, target)) = seeks.next_if(|(at, _)| playback_tick(app) >= *at) {
+            app.world_mut()
+                .resource_mut::<ReplayPlayback>()
+                .seek_to(*target);
+        }
+        playback_tick(app) >= replay.ticks.len()
+    });
+    app
+}
+
+#[test]
+fn a_recorded_match_plays_back_the_same_with_or_without_seeking() {
+    let mut app = replay_app(9);
+    app.insert_resource(MatchRules {
+        points_to_win: 99,
+        ..default()
+    });
+    start_match(&mut app);
+    for tick in 0..(DEFAULT_TICK_RATE_HZ as u32 * 20) {
+        press(&mut app, Side::Left, Action::PaddleUp, tick % 100 < 50);
+        press(&mut app, Side::Left, Action::PaddleDown, tick % 100 >= 50);
+        press(&mut app, Side::Right, Action::PaddleUp, tick % 80 >= 40);
+        press(&mut app, Side::Right, Action::PaddleDown, tick % 80 < 40);
+        press(&mut app, Side::Left, Action::Serve, tick % 60 == 0);
+        press(&mut app, Side::Right, Action::Serve, tick % 60 == 30);
+        // The tuning file saved mid-match
+        if tick == DEFAULT_TICK_RATE_HZ as u32 * 8 {
+            let mut tuning = app.world_mut().resource_mut::<GameTuning>();
+            tuning.ball_radius *= 1.5;
+            tuning.paddle_height *= 1.5;
+        }
+        app.update();
+    }
+    let replay = app
+        .world()
+        .resource::<ReplayRecorder>()
+        .0
+        .clone()
+        .expect("the match is being recorded");
+    assert_eq!(replay.tuning_changes.len(), 1);
+    let points = app.world().resource::<MatchProgress>().points;
+    let recorded_ball = ball(&mut app);
+    assert!(points.left + points.right > 0);
+
+    let mut straight = play_back(&replay, &[]);
+    assert_eq!(straight.world().resource::<MatchProgress>().points, points);
+    assert_eq!(ball(&mut straight), recorded_ball);
+
+    // Back past a snapshot, then forward past the furthest one taken so far
+    let mut seeking = play_back(&replay, &[(1000, 300), (600, 1900)]);
+    assert_eq!(checksum(&mut seeking), checksum(&mut straight));
+    assert_eq!(ball(&mut seeking), recorded_ball);
+}
+
+// One end of a network match over UDP on this machine, with a bad network faked for what it sends
+fn net_app(seed: u64, role: NetRole) -> App {
+    let mut app = headless_app(seed);
+    app.add_plugins(NetplayPlugin).insert_resource(NetSettings {
+        role: Some(role),
+        loss: 0.1,
+        latency: Duration::from_millis(30),
+        ..default()
+    });
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Connecting);
+    app
+}
+
+#[test]
+fn two_ends_of_a_network_match_stay_in_sync_over_a_bad_network() {
+    let mut apps = [
+        net_app(10, NetRole::Host { port: 47_913 }),
+        net_app(
+            11,
+            NetRole::Join {
+                address: "127.0.0.1:47913".to_string(),
+            },
+        ),
+    ];
+    let connecting = Instant::now();
+    while apps
+        .iter()
+        .any(|app| !app.world().contains_resource::<NetSession>())
+    {
+        assert!(
+            connecting.elapsed() < Duration::from_secs(5),
+            "never connected"
+        );
+        for app in &mut apps {
+            app.update();
+        }
+    }
+
+    for tick in 0..600 {
+        let [host, joiner] = &mut apps;
+        press(host, Side::Left, Action::PaddleUp, tick % 90 < 45);
+        press(host, Side::Left, Action::Serve, tick % 60 == 0);
+        press(joiner, Side::Left, Action::PaddleDown, tick % 70 < 35);
+        press(joiner, Side::Left, Action::Serve, tick % 60 == 30);
+        for app in &mut apps {
+            app.update();
+        }
+        // Gives the latency a chance to go by, as it's timed on the real clock
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let [host, joiner] = apps
+        .each_ref()
+        .map(|app| app.world().resource::<NetSession>());
+    assert_eq!(host.desync, None);
+    assert_eq!(joiner.desync, None);
+    let settled = host.settled_tick().min(joiner.settled_tick());
+    assert!(settled > 300, "only {settled} ticks settled");
+    let compared = (settled - 300..=settled)
+        .filter_map(|tick| Some((tick, host.checksum(tick)?, joiner.checksum(tick)?)))
+        .inspect(|(tick, host, joiner)| assert_eq!(host, joiner, "tick {tick}"))
+        .count();
+    assert!(compared > 0);
+}